@@ -0,0 +1,262 @@
+//! Identification of untagged files by their AcoustID fingerprints
+//!
+//! Fingerprints are computed with `fpcalc` of Chromaprint and looked up in the AcoustID web service,
+//! results (including failures to identify a file) are cached in a data directory of the user.
+
+use crate::{
+    http::{self, HttpError},
+    json::{Json, JsonError},
+};
+use std::{
+    error::Error as StdError,
+    fmt, fs,
+    io::Error as IoError,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Root of the AcoustID web service
+pub const DEFAULT_URL: &str = "https://api.acoustid.org/v2";
+
+/// Minimum score (0 to 1) of a result to accept it
+const MIN_SCORE: f64 = 0.8;
+
+/// A recording identified by a fingerprint
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recording {
+    pub title: String,
+    /// Credited artists joined as MusicBrainz shows them, e.g. `A & B`
+    pub artist: Option<String>,
+    /// Title of a release group of the recording
+    pub album: Option<String>,
+}
+
+/// A client of the AcoustID web service with a cache of lookups
+#[derive(Debug)]
+pub struct AcoustId {
+    url: String,
+    /// An API key of an application registered at acoustid.org
+    key: String,
+    cache_path: Option<PathBuf>,
+    /// Fingerprints and recordings, `None` when a fingerprint is unknown
+    cache: Vec<(String, Option<Recording>)>,
+}
+
+impl AcoustId {
+    /// Creates a client with a cache of a data directory of the user (`tagg/acoustid.json`)
+    ///
+    /// An unreadable cache is ignored and replaced on the next lookup.
+    pub fn new(key: impl Into<String>) -> Self {
+        let cache_path = dirs::data_dir().map(|x| x.join("tagg").join("acoustid.json"));
+        let cache = cache_path
+            .as_deref()
+            .and_then(|x| fs::read_to_string(x).ok())
+            .and_then(|x| parse_cache(&x))
+            .unwrap_or_default();
+        Self {
+            url: String::from(DEFAULT_URL),
+            key: key.into(),
+            cache_path,
+            cache,
+        }
+    }
+
+    /// Uses a service at `url`, e.g. a mirror
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Returns a recording of a file, `None` when the file is not identified
+    pub fn identify(&mut self, path: &Path) -> Result<Option<Recording>, AcoustIdError> {
+        let (duration, fingerprint) = fingerprint(path)?;
+        if let Some((_, recording)) = self.cache.iter().find(|(x, _)| *x == fingerprint) {
+            return Ok(recording.clone());
+        }
+        let url = format!(
+            "{}/lookup?client={}&meta=recordings+releasegroups&duration={}&fingerprint={}",
+            self.url.trim_end_matches('/'),
+            http::encode_query(&self.key),
+            duration,
+            http::encode_query(&fingerprint)
+        );
+        let response: Json = http::get(&url)
+            .map_err(AcoustIdError::Http)?
+            .parse()
+            .map_err(AcoustIdError::Json)?;
+        match text(&response, "status") {
+            Some("ok") => {}
+            _ => {
+                let message = response.get("error").and_then(|x| text(x, "message"));
+                return Err(AcoustIdError::Service(message.unwrap_or("unknown error").to_owned()));
+            }
+        }
+        let recording = best_recording(&response);
+        self.cache.push((fingerprint, recording.clone()));
+        self.save_cache().map_err(AcoustIdError::WriteCache)?;
+        Ok(recording)
+    }
+
+    fn save_cache(&self) -> Result<(), IoError> {
+        let path = match self.cache_path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let entries = self.cache.iter().map(|(fingerprint, recording)| {
+            let recording = recording.as_ref().map(|x| {
+                Json::Object(vec![
+                    (String::from("title"), Json::from(x.title.as_str())),
+                    (String::from("artist"), Json::from(x.artist.as_deref())),
+                    (String::from("album"), Json::from(x.album.as_deref())),
+                ])
+            });
+            Json::Object(vec![
+                (String::from("fingerprint"), Json::from(fingerprint.as_str())),
+                (String::from("recording"), recording.unwrap_or(Json::Null)),
+            ])
+        });
+        fs::write(path, format!("{}\n", Json::Array(entries.collect())))
+    }
+}
+
+/// Returns a duration in seconds and a fingerprint of a file
+fn fingerprint(path: &Path) -> Result<(u32, String), AcoustIdError> {
+    let output = Command::new("fpcalc")
+        .arg("-json")
+        .arg(path)
+        .output()
+        .map_err(AcoustIdError::RunFpcalc)?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(AcoustIdError::Fingerprint(message));
+    }
+    let value: Json = String::from_utf8_lossy(&output.stdout)
+        .parse()
+        .map_err(AcoustIdError::Json)?;
+    let duration = match value.get("duration") {
+        Some(Json::Number(duration)) => duration.round() as u32,
+        _ => return Err(AcoustIdError::InvalidResponse),
+    };
+    let fingerprint = text(&value, "fingerprint").ok_or(AcoustIdError::InvalidResponse)?;
+    Ok((duration, fingerprint.to_owned()))
+}
+
+/// Returns the first recording of the best result scoring enough
+fn best_recording(response: &Json) -> Option<Recording> {
+    let results = match response.get("results") {
+        Some(Json::Array(results)) => results,
+        _ => return None,
+    };
+    let result = results
+        .iter()
+        .filter(|x| matches!(x.get("score"), Some(Json::Number(score)) if *score >= MIN_SCORE))
+        .max_by(|a, b| score(a).total_cmp(&score(b)))?;
+    let recording = match result.get("recordings") {
+        Some(Json::Array(recordings)) => recordings.iter().find(|x| text(x, "title").is_some())?,
+        _ => return None,
+    };
+    let artist = match recording.get("artists") {
+        Some(Json::Array(artists)) => Some(
+            artists
+                .iter()
+                .map(|x| {
+                    format!(
+                        "{}{}",
+                        text(x, "name").unwrap_or_default(),
+                        text(x, "joinphrase").unwrap_or_default()
+                    )
+                })
+                .collect::<String>(),
+        ),
+        _ => None,
+    };
+    let album = match recording.get("releasegroups") {
+        Some(Json::Array(groups)) => groups.iter().find_map(|x| text(x, "title")).map(ToOwned::to_owned),
+        _ => None,
+    };
+    Some(Recording {
+        title: text(recording, "title")?.to_owned(),
+        artist: artist.filter(|x| !x.is_empty()),
+        album,
+    })
+}
+
+fn score(value: &Json) -> f64 {
+    match value.get("score") {
+        Some(Json::Number(score)) => *score,
+        _ => 0.0,
+    }
+}
+
+fn parse_cache(data: &str) -> Option<Vec<(String, Option<Recording>)>> {
+    let entries = match data.parse().ok()? {
+        Json::Array(entries) => entries,
+        _ => return None,
+    };
+    entries
+        .iter()
+        .map(|entry| {
+            let fingerprint = text(entry, "fingerprint")?.to_owned();
+            let recording = match entry.get("recording") {
+                Some(Json::Null) | None => None,
+                Some(recording) => Some(Recording {
+                    title: text(recording, "title")?.to_owned(),
+                    artist: text(recording, "artist").map(ToOwned::to_owned),
+                    album: text(recording, "album").map(ToOwned::to_owned),
+                }),
+            };
+            Some((fingerprint, recording))
+        })
+        .collect()
+}
+
+fn text<'a>(value: &'a Json, key: &str) -> Option<&'a str> {
+    match value.get(key) {
+        Some(Json::String(value)) => Some(value),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub enum AcoustIdError {
+    Fingerprint(String),
+    Http(HttpError),
+    InvalidResponse,
+    Json(JsonError),
+    RunFpcalc(IoError),
+    Service(String),
+    WriteCache(IoError),
+}
+
+impl StdError for AcoustIdError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::AcoustIdError::*;
+        match self {
+            Fingerprint(_) => None,
+            Http(err) => Some(err),
+            InvalidResponse => None,
+            Json(err) => Some(err),
+            RunFpcalc(err) => Some(err),
+            Service(_) => None,
+            WriteCache(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for AcoustIdError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::AcoustIdError::*;
+        match self {
+            Fingerprint(message) => write!(out, "failed to compute fingerprint: {}", message),
+            Http(err) => write!(out, "{}", err),
+            InvalidResponse => write!(out, "unexpected response"),
+            Json(err) => write!(out, "failed to parse response: {}", err),
+            RunFpcalc(err) => write!(out, "failed to run fpcalc: {}", err),
+            Service(message) => write!(out, "AcoustID error: {}", message),
+            WriteCache(err) => write!(out, "failed to write cache: {}", err),
+        }
+    }
+}
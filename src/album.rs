@@ -1,23 +1,78 @@
 use crate::{
-    counter::Counter,
-    editor::{DefaultValue, Editor, EditorError, EditorOutput, OutputBuilder, Prompt, State, StateInput},
+    counter::{normalize_text, Counter},
+    date::{self, Date, DateError},
+    editor::{
+        DefaultValue, Editor, EditorError, EditorOutput, OutputBuilder, Prompt, Reader, State, StateInput, Terminal,
+    },
     file::FileInput,
+    genre::{find_genre, GENRES},
 };
-use std::{error::Error as StdError, fmt, num::ParseIntError};
+use std::{collections::BTreeMap, error::Error as StdError, fmt, num::ParseIntError, ops::RangeInclusive};
+
+/// Maximum number of values offered as numbered choices
+const MAX_CHOICES: usize = 5;
+
+/// Maximum number of tracks of a disc or of discs of an album, a larger number is a typo
+const MAX_TOTAL: u32 = 999;
+
+/// Album artist of a compilation
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+/// How album-wide values are inferred from files
+#[derive(Clone, Debug)]
+pub struct AlbumOptions {
+    /// Album artist of an album detected as a compilation
+    pub various_artists: String,
+    /// An album with more distinct artists than this is a compilation
+    pub various_artists_threshold: usize,
+    /// Years accepted by the editor, a typo like `19999` is asked again
+    pub years: RangeInclusive<i32>,
+    /// Whether every track gets the album artist, tracks of a compilation keep their artists anyway
+    pub same_artist: bool,
+}
+
+impl Default for AlbumOptions {
+    fn default() -> Self {
+        Self {
+            various_artists: String::from(VARIOUS_ARTISTS),
+            various_artists_threshold: 2,
+            years: 0..=date::current_year() + 1,
+            same_artist: false,
+        }
+    }
+}
 
 #[derive(Clone, Debug, Default)]
 pub struct AlbumInput {
     pub artist: Option<String>,
     pub album_artist: Option<String>,
     pub album: Option<String>,
-    pub year: Option<i32>,
+    pub date: Option<Date>,
+    pub genre: Option<String>,
+    /// A work or a content group tracks belong to, e.g. of classical music
+    pub grouping: Option<String>,
     pub total_tracks: Option<u32>,
     pub total_discs: Option<u32>,
+    /// Numbers of tracks of every disc of a multi-disc album, `total_tracks` is used for other discs
+    pub disc_tracks: BTreeMap<u32, u32>,
+    /// Whether the album is a compilation of various artists
+    pub compilation: Option<bool>,
+    /// Values seen in files, offered for completion
+    candidates: Counter<AlbumKey, String>,
 }
 
 impl AlbumInput {
+    /// Takes the most common value of every field, spellings differing in case or whitespace are counted together
     pub fn from_file_input(items: &[FileInput]) -> Self {
-        let mut counter = Counter::default();
+        Self::from_file_input_with_options(items, &AlbumOptions::default())
+    }
+
+    /// Same as [`AlbumInput::from_file_input`], a compilation is detected according to options
+    ///
+    /// Files of one album with many different artists are a compilation,
+    /// its album artist defaults to a label such as `Various Artists`.
+    pub fn from_file_input_with_options(items: &[FileInput], options: &AlbumOptions) -> Self {
+        let mut counter = Counter::with_normalizer(|x: &String| normalize_text(x, true));
         for item in items {
             if let Some(ref artist) = item.artist {
                 counter.insert(AlbumKey::Artist, artist.clone());
@@ -28,8 +83,14 @@ impl AlbumInput {
             if let Some(ref album) = item.album {
                 counter.insert(AlbumKey::Album, album.clone());
             }
-            if let Some(year) = item.year {
-                counter.insert(AlbumKey::Year, format!("{}", year));
+            if let Some(date) = item.date {
+                counter.insert(AlbumKey::Year, format!("{}", date));
+            }
+            if let Some(ref genre) = item.genre {
+                counter.insert(AlbumKey::Genre, genre.clone());
+            }
+            if let Some(ref grouping) = item.grouping {
+                counter.insert(AlbumKey::Grouping, grouping.clone());
             }
             if let Some(total_tracks) = item.total_tracks {
                 counter.insert(AlbumKey::TotalTracks, format!("{}", total_tracks));
@@ -37,16 +98,178 @@ impl AlbumInput {
             if let Some(total_discs) = item.total_discs {
                 counter.insert(AlbumKey::TotalDiscs, format!("{}", total_discs));
             }
+            if let Some(compilation) = item.compilation {
+                counter.insert(AlbumKey::Compilation, String::from(format_flag(compilation)));
+            }
         }
+        let is_compilation = counter.values(AlbumKey::Artist).len() > options.various_artists_threshold
+            && counter.values(AlbumKey::Album).len() <= 1;
+        let album_artist = counter.most_common(AlbumKey::AlbumArtist);
+        let album_artist = match album_artist {
+            None if is_compilation => Some(options.various_artists.clone()),
+            album_artist => album_artist,
+        };
         Self {
             artist: counter.most_common(AlbumKey::Artist),
-            album_artist: counter.most_common(AlbumKey::AlbumArtist),
+            album_artist,
             album: counter.most_common(AlbumKey::Album),
-            year: counter.most_common(AlbumKey::Year).and_then(|x| x.parse().ok()),
-            total_tracks: counter.most_common(AlbumKey::TotalTracks).and_then(|x| x.parse().ok()),
-            total_discs: counter.most_common(AlbumKey::TotalDiscs).and_then(|x| x.parse().ok()),
+            date: counter.most_common(AlbumKey::Year).and_then(|x| x.parse().ok()),
+            genre: counter.most_common(AlbumKey::Genre),
+            grouping: counter.most_common(AlbumKey::Grouping),
+            total_tracks: counter
+                .most_common(AlbumKey::TotalTracks)
+                .and_then(|x| x.parse().ok())
+                .or_else(|| count_tracks(items)),
+            total_discs: counter
+                .most_common(AlbumKey::TotalDiscs)
+                .and_then(|x| x.parse().ok())
+                .or_else(|| items.iter().filter_map(|x| x.disc_number).max()),
+            disc_tracks: count_disc_tracks(items),
+            compilation: counter
+                .most_common(AlbumKey::Compilation)
+                .and_then(|x| parse_flag(&x))
+                .or(if is_compilation { Some(true) } else { None }),
+            candidates: counter,
         }
     }
+
+    /// Returns an output without editing, fails when a required value is missing
+    pub fn into_output(self) -> Result<AlbumOutput, AlbumOutputError> {
+        AlbumOutputBuilder::new(self, &AlbumOptions::default()).build()
+    }
+}
+
+/// Files of one album found among files of a directory
+#[derive(Debug)]
+pub struct AlbumGroup {
+    /// An album of the first file of the group, `None` for files without an album
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub items: Vec<FileInput>,
+}
+
+/// Splits files into albums by album and album artist, spellings differing in case or whitespace are the same
+///
+/// A file without an album artist joins a group of its album, albums with different album artists are split.
+/// Files without an album join the only album, or make a group of their own when there are several albums.
+/// Groups and their files keep the order of files.
+pub fn group_albums(items: Vec<FileInput>) -> Vec<AlbumGroup> {
+    let normalize = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(|x| normalize_text(x, true))
+            .filter(|x| !x.is_empty())
+    };
+    // normalized album and album artist of every group
+    let mut keys: Vec<(String, Option<String>)> = Vec::new();
+    let mut groups: Vec<AlbumGroup> = Vec::new();
+    let mut indices = Vec::with_capacity(items.len());
+    for item in &items {
+        let album = match normalize(&item.album) {
+            Some(album) => album,
+            None => {
+                indices.push(None);
+                continue;
+            }
+        };
+        let album_artist = normalize(&item.album_artist);
+        let idx = keys.iter().position(|(group_album, group_album_artist)| {
+            *group_album == album
+                && (album_artist.is_none() || group_album_artist.is_none() || *group_album_artist == album_artist)
+        });
+        let idx = match idx {
+            Some(idx) => {
+                if keys[idx].1.is_none() && album_artist.is_some() {
+                    keys[idx].1 = album_artist;
+                    groups[idx].album_artist = item.album_artist.clone();
+                }
+                idx
+            }
+            None => {
+                keys.push((album, album_artist));
+                groups.push(AlbumGroup {
+                    album: item.album.clone(),
+                    album_artist: item.album_artist.clone(),
+                    items: Vec::new(),
+                });
+                groups.len() - 1
+            }
+        };
+        indices.push(Some(idx));
+    }
+    let untitled = match groups.len() {
+        0 | 1 => 0,
+        len => len,
+    };
+    if indices.iter().any(Option::is_none) && untitled == groups.len() {
+        groups.push(AlbumGroup {
+            album: None,
+            album_artist: None,
+            items: Vec::new(),
+        });
+    }
+    for (item, idx) in items.into_iter().zip(indices) {
+        groups[idx.unwrap_or(untitled)].items.push(item);
+    }
+    groups
+}
+
+/// Returns a number of files of the first disc, or of all files when there are no disc numbers
+fn count_tracks(items: &[FileInput]) -> Option<u32> {
+    let first_disc = items.iter().filter_map(|x| x.disc_number).min();
+    let count = items
+        .iter()
+        .filter(|x| first_disc.is_none() || x.disc_number == first_disc)
+        .count();
+    Some(count as u32).filter(|x| *x > 0)
+}
+
+/// Returns numbers of tracks of every disc when files belong to several discs
+///
+/// A number is the most common total of a disc in tags, or a number of files of the disc.
+fn count_disc_tracks(items: &[FileInput]) -> BTreeMap<u32, u32> {
+    let mut files: BTreeMap<u32, u32> = BTreeMap::new();
+    let mut totals = Counter::default();
+    for item in items {
+        if let Some(disc_number) = item.disc_number {
+            *files.entry(disc_number).or_default() += 1;
+            if let Some(total_tracks) = item.total_tracks {
+                totals.insert(disc_number, total_tracks);
+            }
+        }
+    }
+    if files.len() < 2 {
+        return BTreeMap::new();
+    }
+    files
+        .into_iter()
+        .map(|(disc_number, count)| (disc_number, totals.most_common(disc_number).unwrap_or(count)))
+        .collect()
+}
+
+fn check_total(value: u32) -> Result<u32, AlbumInputError> {
+    if value > MAX_TOTAL {
+        Err(AlbumInputError::TotalOutOfRange(value))
+    } else {
+        Ok(value)
+    }
+}
+
+/// Parses a yes/no answer such as `y`, `no` or `1`
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "y" | "yes" | "1" | "true" => Some(true),
+        "n" | "no" | "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn format_flag(value: bool) -> &'static str {
+    if value {
+        "y"
+    } else {
+        "n"
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -55,8 +278,11 @@ enum AlbumKey {
     AlbumArtist,
     Album,
     Year,
+    Genre,
+    Grouping,
     TotalTracks,
     TotalDiscs,
+    Compilation,
 }
 
 impl Prompt for AlbumKey {
@@ -66,9 +292,12 @@ impl Prompt for AlbumKey {
             Artist => "ARTIST",
             AlbumArtist => "ALBUM ARTIST",
             Album => "ALBUM",
-            Year => "YEAR",
+            Year => "YEAR (YYYY[-MM[-DD]])",
+            Genre => "GENRE",
+            Grouping => "GROUPING",
             TotalTracks => "TOTAL TRACKS",
             TotalDiscs => "TOTAL DISCS",
+            Compilation => "COMPILATION (Y/N)",
         }
     }
 }
@@ -76,11 +305,20 @@ impl Prompt for AlbumKey {
 #[derive(Debug)]
 struct AlbumOutputBuilder {
     album_input: AlbumInput,
+    years: RangeInclusive<i32>,
+    same_artist: bool,
+    /// An artist of files before editing
+    file_artist: Option<String>,
 }
 
 impl AlbumOutputBuilder {
-    fn new(album_input: AlbumInput) -> Self {
-        Self { album_input }
+    fn new(album_input: AlbumInput, options: &AlbumOptions) -> Self {
+        Self {
+            file_artist: album_input.artist.clone(),
+            album_input,
+            years: options.years.clone(),
+            same_artist: options.same_artist,
+        }
     }
 }
 
@@ -96,9 +334,46 @@ impl OutputBuilder for AlbumOutputBuilder {
             Artist => self.album_input.artist = Some(value),
             AlbumArtist => self.album_input.album_artist = Some(value),
             Album => self.album_input.album = Some(value),
-            Year => self.album_input.year = Some(value.parse().map_err(AlbumInputError::Year)?),
-            TotalTracks => self.album_input.total_tracks = Some(value.parse().map_err(AlbumInputError::TotalTracks)?),
-            TotalDiscs => self.album_input.total_discs = Some(value.parse().map_err(AlbumInputError::TotalDiscs)?),
+            Year => {
+                let date: Date = value.parse().map_err(AlbumInputError::Year)?;
+                if !self.years.contains(&date.year) {
+                    return Err(AlbumInputError::YearOutOfRange(date.year, self.years.clone()));
+                }
+                self.album_input.date = Some(date);
+            }
+            // a genre of the standard list gets its canonical spelling, other genres are kept as typed
+            Genre => self.album_input.genre = Some(find_genre(&value).map(ToOwned::to_owned).unwrap_or(value)),
+            Grouping => self.album_input.grouping = Some(value),
+            // an empty value leaves a total unknown
+            TotalTracks if value.trim().is_empty() => self.album_input.total_tracks = None,
+            TotalTracks => {
+                let total_tracks = value.trim().parse().map_err(AlbumInputError::TotalTracks)?;
+                self.album_input.total_tracks = Some(check_total(total_tracks)?);
+            }
+            TotalDiscs if value.trim().is_empty() => self.album_input.total_discs = None,
+            TotalDiscs => {
+                let total_discs = value.trim().parse().map_err(AlbumInputError::TotalDiscs)?;
+                self.album_input.total_discs = Some(check_total(total_discs)?);
+            }
+            Compilation => {
+                self.album_input.compilation = Some(parse_flag(&value).ok_or(AlbumInputError::Compilation(value))?)
+            }
+        }
+        Ok(())
+    }
+
+    fn unset_value(&mut self, key: Self::Key) -> Result<(), Self::OutputError> {
+        use self::AlbumKey::*;
+        match key {
+            Artist => return Err(AlbumOutputError::Artist),
+            AlbumArtist => return Err(AlbumOutputError::AlbumArtist),
+            Album => return Err(AlbumOutputError::Album),
+            Year => return Err(AlbumOutputError::Year),
+            Genre => self.album_input.genre = None,
+            Grouping => self.album_input.grouping = None,
+            TotalTracks => self.album_input.total_tracks = None,
+            TotalDiscs => self.album_input.total_discs = None,
+            Compilation => self.album_input.compilation = None,
         }
         Ok(())
     }
@@ -108,9 +383,16 @@ impl OutputBuilder for AlbumOutputBuilder {
             artist: self.album_input.artist.ok_or(AlbumOutputError::Artist)?,
             album_artist: self.album_input.album_artist.ok_or(AlbumOutputError::AlbumArtist)?,
             album: self.album_input.album.ok_or(AlbumOutputError::Album)?,
-            year: self.album_input.year.ok_or(AlbumOutputError::Year)?,
-            total_tracks: self.album_input.total_tracks.ok_or(AlbumOutputError::TotalTracks)?,
-            total_discs: self.album_input.total_discs.ok_or(AlbumOutputError::TotalDiscs)?,
+            date: self.album_input.date.ok_or(AlbumOutputError::Year)?,
+            genre: self.album_input.genre.filter(|x| !x.is_empty()),
+            grouping: self.album_input.grouping.filter(|x| !x.is_empty()),
+            // zero is an unknown total
+            total_tracks: self.album_input.total_tracks.filter(|x| *x > 0),
+            total_discs: self.album_input.total_discs.filter(|x| *x > 0),
+            disc_tracks: self.album_input.disc_tracks,
+            compilation: self.album_input.compilation.unwrap_or(false),
+            same_artist: self.same_artist,
+            file_artist: self.file_artist,
         })
     }
 }
@@ -120,16 +402,54 @@ pub struct AlbumOutput {
     pub artist: String,
     pub album_artist: String,
     pub album: String,
-    pub year: i32,
-    pub total_tracks: u32,
-    pub total_discs: u32,
+    pub date: Date,
+    pub genre: Option<String>,
+    pub grouping: Option<String>,
+    /// `None` when unknown, numbers are written without a total then
+    pub total_tracks: Option<u32>,
+    pub total_discs: Option<u32>,
+    /// Numbers of tracks of every disc of a multi-disc album, `total_tracks` is used for other discs
+    pub disc_tracks: BTreeMap<u32, u32>,
+    pub compilation: bool,
+    /// Whether every track other than a track of a compilation gets the album artist
+    pub same_artist: bool,
+    /// The most common artist of files before editing
+    pub file_artist: Option<String>,
+}
+
+impl AlbumOutput {
+    /// Returns an artist of a track of a file
+    ///
+    /// Tracks of a compilation keep artists of their files, as well as files of an artist other than
+    /// the most common one unless every track gets the album artist. Other tracks get the album artist,
+    /// so a corrected artist is written to all of them.
+    pub fn track_artist(&self, file_artist: Option<&str>) -> String {
+        let normalize = |x: &str| normalize_text(x, true);
+        match file_artist.filter(|x| !x.trim().is_empty()) {
+            Some(artist) if self.compilation => artist.to_owned(),
+            Some(artist)
+                if !self.same_artist && self.file_artist.as_deref().map(normalize) != Some(normalize(artist)) =>
+            {
+                artist.to_owned()
+            }
+            _ => self.artist.clone(),
+        }
+    }
+
+    /// Returns a number of tracks of a disc
+    pub fn total_tracks_of(&self, disc_number: u32) -> Option<u32> {
+        self.disc_tracks.get(&disc_number).copied().or(self.total_tracks)
+    }
 }
 
 #[derive(Debug)]
-enum AlbumInputError {
-    Year(ParseIntError),
+pub enum AlbumInputError {
+    Year(DateError),
+    YearOutOfRange(i32, RangeInclusive<i32>),
     TotalTracks(ParseIntError),
     TotalDiscs(ParseIntError),
+    TotalOutOfRange(u32),
+    Compilation(String),
 }
 
 impl StdError for AlbumInputError {
@@ -137,8 +457,11 @@ impl StdError for AlbumInputError {
         use self::AlbumInputError::*;
         match self {
             Year(err) => Some(err),
+            YearOutOfRange(..) => None,
             TotalTracks(err) => Some(err),
             TotalDiscs(err) => Some(err),
+            TotalOutOfRange(_) => None,
+            Compilation(_) => None,
         }
     }
 }
@@ -147,21 +470,28 @@ impl fmt::Display for AlbumInputError {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
         use self::AlbumInputError::*;
         match self {
-            Year(err) => write!(out, "invalid year: {}", err),
+            Year(err) => write!(out, "invalid date: {} (expected YYYY, YYYY-MM or YYYY-MM-DD)", err),
+            YearOutOfRange(year, years) => write!(
+                out,
+                "year {} is out of range, expected {} to {}",
+                year,
+                years.start(),
+                years.end()
+            ),
             TotalTracks(err) => write!(out, "invalid number of tracks: {}", err),
             TotalDiscs(err) => write!(out, "invalid number of discs: {}", err),
+            TotalOutOfRange(value) => write!(out, "total {} is out of range, expected at most {}", value, MAX_TOTAL),
+            Compilation(value) => write!(out, "invalid compilation flag: {} (expected y or n)", value),
         }
     }
 }
 
 #[derive(Debug)]
-enum AlbumOutputError {
+pub enum AlbumOutputError {
     Artist,
     AlbumArtist,
     Album,
     Year,
-    TotalTracks,
-    TotalDiscs,
 }
 
 impl StdError for AlbumOutputError {}
@@ -177,8 +507,6 @@ impl fmt::Display for AlbumOutputError {
                 AlbumArtist => "album artist",
                 Album => "album",
                 Year => "year",
-                TotalTracks => "number of tracks",
-                TotalDiscs => "number of discs",
             }
         )
     }
@@ -187,15 +515,21 @@ impl fmt::Display for AlbumOutputError {
 struct AlbumState {
     album_input: AlbumInput,
     kind: AlbumStateKind,
+    /// A field active before an interrupt, editing resumes there when quitting is cancelled
+    resume_kind: AlbumStateKind,
 }
 
+#[derive(Clone, Copy)]
 enum AlbumStateKind {
     Artist,
     AlbumArtist,
     Album,
     Year,
+    Genre,
+    Grouping,
     TotalTracks,
     TotalDiscs,
+    Compilation,
     Interrupted,
     Finished,
 }
@@ -205,6 +539,7 @@ impl AlbumState {
         Self {
             album_input,
             kind: AlbumStateKind::Artist,
+            resume_kind: AlbumStateKind::Artist,
         }
     }
 }
@@ -219,7 +554,14 @@ impl State<AlbumKey> for AlbumState {
             },
             AlbumArtist => StateInput::Read {
                 key: AlbumKey::AlbumArtist,
-                default_value: DefaultValue::from(&self.album_input.album_artist),
+                // files of a single-artist album often have no album artist
+                default_value: DefaultValue::from(
+                    &self
+                        .album_input
+                        .album_artist
+                        .as_ref()
+                        .or(self.album_input.artist.as_ref()),
+                ),
             },
             Album => StateInput::Read {
                 key: AlbumKey::Album,
@@ -227,7 +569,15 @@ impl State<AlbumKey> for AlbumState {
             },
             Year => StateInput::Read {
                 key: AlbumKey::Year,
-                default_value: DefaultValue::from(&self.album_input.year),
+                default_value: DefaultValue::from(&self.album_input.date),
+            },
+            Genre => StateInput::Read {
+                key: AlbumKey::Genre,
+                default_value: DefaultValue::from(&self.album_input.genre),
+            },
+            Grouping => StateInput::Read {
+                key: AlbumKey::Grouping,
+                default_value: DefaultValue::from(&self.album_input.grouping),
             },
             TotalTracks => StateInput::Read {
                 key: AlbumKey::TotalTracks,
@@ -237,6 +587,10 @@ impl State<AlbumKey> for AlbumState {
                 key: AlbumKey::TotalDiscs,
                 default_value: DefaultValue::from(&self.album_input.total_discs),
             },
+            Compilation => StateInput::Read {
+                key: AlbumKey::Compilation,
+                default_value: DefaultValue::from(&self.album_input.compilation.map(format_flag)),
+            },
             Interrupted => StateInput::Interrupted,
             Finished => StateInput::Finished,
         }
@@ -248,9 +602,12 @@ impl State<AlbumKey> for AlbumState {
             Artist => AlbumArtist,
             AlbumArtist => Album,
             Album => Year,
-            Year => TotalTracks,
+            Year => Genre,
+            Genre => Grouping,
+            Grouping => TotalTracks,
             TotalTracks => TotalDiscs,
-            TotalDiscs => Finished,
+            TotalDiscs => Compilation,
+            Compilation => Finished,
             Interrupted => Interrupted,
             Finished => Finished,
         };
@@ -263,32 +620,126 @@ impl State<AlbumKey> for AlbumState {
             AlbumArtist => Artist,
             Album => AlbumArtist,
             Year => Album,
-            TotalTracks => Year,
+            Genre => Year,
+            Grouping => Genre,
+            TotalTracks => Grouping,
             TotalDiscs => TotalTracks,
-            Interrupted => Artist,
-            Finished => TotalDiscs,
+            Compilation => TotalDiscs,
+            Interrupted => self.resume_kind,
+            Finished => Compilation,
         };
     }
 
     fn interrupt(&mut self) {
+        if let AlbumStateKind::Interrupted = self.kind {
+            return;
+        }
+        self.resume_kind = self.kind;
         self.kind = AlbumStateKind::Interrupted;
     }
 
-    fn finish(&mut self) {
-        self.kind = AlbumStateKind::Finished;
+    fn resume(&mut self) {
+        if let AlbumStateKind::Interrupted = self.kind {
+            self.kind = self.resume_kind;
+        }
+    }
+
+    fn keys(&self) -> Vec<AlbumKey> {
+        use self::AlbumKey::*;
+        vec![
+            Artist,
+            AlbumArtist,
+            Album,
+            Year,
+            Genre,
+            Grouping,
+            TotalTracks,
+            TotalDiscs,
+            Compilation,
+        ]
+    }
+
+    fn goto(&mut self, key: &AlbumKey) {
+        self.kind = match key {
+            AlbumKey::Artist => AlbumStateKind::Artist,
+            AlbumKey::AlbumArtist => AlbumStateKind::AlbumArtist,
+            AlbumKey::Album => AlbumStateKind::Album,
+            AlbumKey::Year => AlbumStateKind::Year,
+            AlbumKey::Genre => AlbumStateKind::Genre,
+            AlbumKey::Grouping => AlbumStateKind::Grouping,
+            AlbumKey::TotalTracks => AlbumStateKind::TotalTracks,
+            AlbumKey::TotalDiscs => AlbumStateKind::TotalDiscs,
+            AlbumKey::Compilation => AlbumStateKind::Compilation,
+        };
+    }
+
+    /// Genres of files are followed by the standard genres
+    fn candidates(&self, key: &AlbumKey) -> Vec<String> {
+        let mut values = self.album_input.candidates.values(*key);
+        if let AlbumKey::Genre = key {
+            for genre in GENRES.iter() {
+                if !values.iter().any(|x| x.eq_ignore_ascii_case(genre)) {
+                    values.push((*genre).to_owned());
+                }
+            }
+        }
+        values
+    }
+
+    fn choices(&self, key: &AlbumKey) -> Vec<(String, u32)> {
+        use self::AlbumKey::*;
+        match key {
+            // a typed number is a value of numeric fields, not a choice
+            Year | TotalTracks | TotalDiscs | Compilation => Vec::new(),
+            Artist | AlbumArtist | Album | Genre | Grouping => {
+                let choices = self.album_input.candidates.most_common_n(*key, MAX_CHOICES);
+                if choices.len() > 1 {
+                    choices
+                } else {
+                    Vec::new()
+                }
+            }
+        }
+    }
+
+    fn conflicts(&self, key: &AlbumKey) -> Vec<(String, u32)> {
+        use self::AlbumKey::*;
+        match key {
+            // conflicting values of other fields are shown as choices
+            Artist | AlbumArtist | Album | Genre | Grouping => Vec::new(),
+            Year | TotalTracks | TotalDiscs | Compilation => {
+                let counts = self.album_input.candidates.counts(*key);
+                if counts.len() > 1 {
+                    counts
+                } else {
+                    Vec::new()
+                }
+            }
+        }
     }
 }
 
-pub struct AlbumEditor {
-    inner: Editor<AlbumState, AlbumOutputBuilder>,
+pub struct AlbumEditor<R = Terminal> {
+    inner: Editor<AlbumState, AlbumOutputBuilder, R>,
 }
 
 impl AlbumEditor {
     pub fn new(album_input: AlbumInput) -> Self {
+        Self::with_options(album_input, &AlbumOptions::default())
+    }
+
+    pub fn with_options(album_input: AlbumInput, options: &AlbumOptions) -> Self {
+        Self::with_reader(album_input, options, Terminal::default())
+    }
+}
+
+impl<R: Reader> AlbumEditor<R> {
+    pub fn with_reader(album_input: AlbumInput, options: &AlbumOptions, reader: R) -> Self {
         Self {
-            inner: Editor::new(
+            inner: Editor::with_reader(
                 AlbumState::new(album_input.clone()),
-                AlbumOutputBuilder::new(album_input),
+                AlbumOutputBuilder::new(album_input, options),
+                reader,
             ),
         }
     }
@@ -297,3 +748,63 @@ impl AlbumEditor {
         self.inner.run()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finder::Format;
+
+    fn file(name: &str, album: Option<&str>, album_artist: Option<&str>) -> FileInput {
+        FileInput {
+            album: album.map(String::from),
+            album_artist: album_artist.map(String::from),
+            ..FileInput::empty(name, Format::Mp3)
+        }
+    }
+
+    fn names(group: &AlbumGroup) -> Vec<&str> {
+        group.items.iter().map(|x| x.path.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn groups_spellings_of_one_album() {
+        let groups = group_albums(vec![
+            file("1", Some("Album"), None),
+            file("2", Some(" album "), Some("Artist")),
+            file("3", Some("ALBUM"), Some("artist")),
+            file("4", None, None),
+        ]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].album.as_deref(), Some("Album"));
+        assert_eq!(groups[0].album_artist.as_deref(), Some("Artist"));
+        assert_eq!(names(&groups[0]), ["1", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn splits_distinct_albums() {
+        let groups = group_albums(vec![
+            file("1", Some("First"), Some("Artist")),
+            file("2", Some("Second"), Some("Artist")),
+            file("3", Some("First"), Some("Other")),
+            file("4", None, None),
+            file("5", Some("Second"), None),
+        ]);
+        assert_eq!(groups.len(), 4);
+        assert_eq!(names(&groups[0]), ["1"]);
+        assert_eq!(names(&groups[1]), ["2", "5"]);
+        assert_eq!(groups[2].album_artist.as_deref(), Some("Other"));
+        assert_eq!(names(&groups[2]), ["3"]);
+        // files without an album can't be assigned to one of several albums
+        assert_eq!(groups[3].album, None);
+        assert_eq!(names(&groups[3]), ["4"]);
+    }
+
+    #[test]
+    fn groups_files_without_albums() {
+        let groups = group_albums(vec![file("1", None, None), file("2", Some(""), None)]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].album, None);
+        assert_eq!(names(&groups[0]), ["1", "2"]);
+        assert!(group_albums(Vec::new()).is_empty());
+    }
+}
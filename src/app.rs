@@ -1,104 +1,1630 @@
+#[cfg(feature = "acoustid")]
+use crate::acoustid::AcoustId;
 use crate::{
-    album::{AlbumEditor, AlbumInput},
+    album::{group_albums, AlbumEditor, AlbumGroup, AlbumInput, AlbumOptions, AlbumOutput},
+    config::{Config, ConfigError},
+    cover::{Cover, CoverError},
     editor::{EditorError, EditorOutput},
-    file::{FileOutput, FileOutputError},
-    finder::{self, FindError},
-    track::{TrackEditor, TrackInput},
+    exchange::{self, ImportError},
+    featured::FeaturedMode,
+    file::{self, Backup, FileInput, FileOutput, FileOutputError, WriteOptions},
+    finder::{self, FindError, FinderOptions},
+    json::Json,
+    manifest::{Manifest, ManifestEntry, ManifestError, KEPT_MANIFESTS},
+    musicbrainz::{MusicBrainz, DEFAULT_URL as MUSICBRAINZ_URL},
+    playlist::{self, PlaylistEntry},
+    template::{FilenameTemplate, TemplateError, DEFAULT_REPLACEMENT, ORGANIZE_TEMPLATE},
+    track::{Numbering, TrackEditor, TrackInput, TrackOutput},
+    validate::{self, Issue},
 };
+use id3::Version as Id3Version;
 use std::{
+    collections::{BTreeMap, BTreeSet},
     env,
     error::Error as StdError,
-    fmt,
-    io::{stdin, stdout, Error as IoError, Write},
-    path::PathBuf,
+    ffi::{OsStr, OsString},
+    fmt, fs,
+    io::{stdin, stdout, Error as IoError, ErrorKind as IoErrorKind, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    str::FromStr,
 };
 
-fn get_path() -> Result<PathBuf, AppError> {
-    let mut args = env::args();
+#[cfg(feature = "acoustid")]
+const OPTION_ACOUSTID: &str = "--acoustid";
+const OPTION_BACKUP: &str = "--backup";
+const OPTION_DESTINATION: &str = "--destination";
+const OPTION_DRY_RUN: &str = "--dry-run";
+const OPTION_EXPORT: &str = "--export";
+const OPTION_CLEAN: &str = "--clean";
+const OPTION_CONTINUE_ON_ERROR: &str = "--continue-on-error";
+const OPTION_COPY: &str = "--copy";
+const OPTION_EXTENSIONS: &str = "--extensions";
+const OPTION_FEATURED: &str = "--featured";
+const OPTION_FILL_MISSING: &str = "--fill-missing";
+const OPTION_FILES_FROM: &str = "--files-from";
+const OPTION_FORMAT: &str = "--format";
+const OPTION_HELP: &str = "--help";
+const OPTION_IMPORT: &str = "--import";
+const OPTION_ID3V1: &str = "--id3v1";
+const OPTION_ID3V23: &str = "--id3v23";
+const OPTION_JOBS: &str = "--jobs";
+const OPTION_LOWERCASE_EXTENSION: &str = "--lowercase-extension";
+const OPTION_MAKE_WRITABLE: &str = "--make-writable";
+const OPTION_MANIFEST: &str = "--manifest";
+const OPTION_MUSICBRAINZ: &str = "--musicbrainz";
+const OPTION_NUMBER: &str = "--number";
+const OPTION_NUMBER_WIDTH: &str = "--number-width";
+const OPTION_PLAYLIST: &str = "--playlist";
+const OPTION_PRESERVE_MTIME: &str = "--preserve-mtime";
+const OPTION_QUIET: &str = "--quiet";
+const OPTION_QUIET_SHORT: &str = "-q";
+const OPTION_RECURSIVE: &str = "--recursive";
+const OPTION_SAME_ARTIST: &str = "--same-artist";
+const OPTION_SELECT: &str = "--select";
+const OPTION_RENUMBER: &str = "--renumber";
+const OPTION_STRICT: &str = "--strict";
+const OPTION_TEMPLATE: &str = "--template";
+const OPTION_TITLE_CASE: &str = "--title-case";
+const OPTION_TEMPORARY_BACKUP: &str = "--temporary-backup";
+const OPTION_VERSION: &str = "--version";
+const OPTION_YES: &str = "--yes";
+const OPTION_YES_SHORT: &str = "-y";
+
+const COMMAND_UNDO: &str = "undo";
+
+const USAGE: &str = "\
+Usage: tagg [OPTIONS] [DIRECTORY]...
+       tagg undo [-y] [-q] [--format <FORMAT>] MANIFEST
+
+Tags music files of every DIRECTORY as an album (the current directory by default)
+or restores tags and paths of files written by a run from its MANIFEST
+
+Options:
+    --acoustid            suggest titles of files without one by their AcoustID fingerprints
+                          (requires the acoustid feature, fpcalc, curl and acoustid_key of a config)
+    --backup              keep a copy of every changed file
+    --clean               remove all tags of files without writing new ones or renaming files
+    --continue-on-error   go on with other files and albums when one fails, failures are listed at the end
+    --copy                write tagged copies to new paths (e.g. of --destination), originals are left untouched
+    --destination <DIR>   move files into DIR/album artist/year - album
+    --dry-run             print changes instead of writing them
+    --export <FORMAT>     print tags of files as csv or json instead of editing them
+    --extensions <LIST>   comma-separated extensions of files to tag (flac,m4a,mp3,ogg)
+    --featured <MODE>     move featured artists of titles (\"Song (ft. Guest)\") to the artist
+                          with \"artist\" or rewrite them as \"(feat. Guest)\" with \"title\"
+    --fill-missing        only write values missing in files, values already set are kept
+    --files-from <FILE>   tag files listed in FILE one per line, files of a directory make an album
+    --format <FORMAT>     print results as text (totals) or json (original_path, new_path and status of every file)
+    --import <FILE>       write tags of a csv or json file (as printed by --export) instead of editing them
+    --id3v1               write an ID3v1 tag along with ID3v2
+    --id3v23              write ID3v2.3 instead of ID3v2.4
+    --jobs <N>            read tags with N threads (the number of CPUs by default)
+    --lowercase-extension lowercase extensions of renamed files (\"Song.MP3\" becomes \".mp3\"), their case is kept by default
+    --make-writable       make read-only files (e.g. copied off a CD) writable instead of failing to write them
+    --manifest <FILE>     record original tags and paths of written files to FILE
+                          (tagg/manifests of the user data directory by default, where the newest 50 are kept)
+    --musicbrainz         fill values missing in tags with a release found in MusicBrainz (requires curl)
+    --number              number tracks without a number by their position
+    --number-width <N>    pad track and disc numbers to at least N digits (2), wider totals widen them
+    --playlist            write an M3U playlist of written files named after the album, existing files are kept
+    --preserve-mtime      keep modification times of written files
+    -q, --quiet           do not print progress and totals, only questions and errors
+    --recursive           look for files in subdirectories
+    --renumber            number all tracks by their position
+    --select              choose files of an album to tag from a numbered list, e.g. \"1-3,5\" or \"!4\"
+    --same-artist         write the artist of the album to every track, tracks of a compilation keep their artists
+                          (by default tracks of an artist other than the most common one keep it)
+    --strict              stop on suspicious values (e.g. a track number exceeding total tracks)
+    --template <TEMPLATE> file name template, e.g. \"{number} - {title}.{ext}\"
+    --temporary-backup    keep a copy of a file until it is written
+    --title-case          convert artists, album and titles to title case, e.g. \"The End of the World\"
+    -y, --yes             embed a cover and write files without asking for confirmation
+    --help                print this help
+    --version             print version
+
+Defaults of extensions, filename_template, lowercase_extension, recursive, copy, make_writable, preserve_mtime, id3_version (\"2.3\" or \"2.4\"), confirm,
+title_case, same_artist, featured, number_width and musicbrainz_url are read from tagg.toml of the user config directory and of the working directory.
+An album with more than various_artists_threshold (2) artists is a compilation,
+its album artist defaults to various_artists (\"Various Artists\").
+A year is accepted from min_year (0) to max_year (next year).
+Multiple artists are separated with value_separator (\";\"), e.g. \"Artist A; Artist B\"
+An empty answer to a yes/no question takes default_answer (\"yes\" or \"no\"), it is asked again without one";
+
+/// What to do according to command line arguments
+enum Command {
+    Help,
+    Version,
+    Tag(Box<Args>),
+    /// Restore files written by a run
+    Undo(UndoArgs),
+}
+
+impl Command {
+    fn is_quiet(&self) -> bool {
+        match self {
+            Command::Tag(args) => args.quiet,
+            Command::Undo(args) => args.quiet,
+            _ => false,
+        }
+    }
+}
+
+struct UndoArgs {
+    manifest: PathBuf,
+    yes: bool,
+    default_answer: Option<bool>,
+    quiet: bool,
+    format: SummaryFormat,
+    write_options: WriteOptions,
+}
+
+/// A format to print tags of files in
+#[derive(Clone, Copy, Debug)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(AppError::UnknownExportFormat(value.to_owned())),
+        }
+    }
+}
+
+/// A format to print a summary of a run in
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SummaryFormat {
+    /// Totals for a human
+    #[default]
+    Text,
+    /// A JSON array of processed files
+    Json,
+}
+
+impl FromStr for SummaryFormat {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(SummaryFormat::Text),
+            "json" => Ok(SummaryFormat::Json),
+            _ => Err(AppError::InvalidValue(OPTION_FORMAT, value.to_owned())),
+        }
+    }
+}
+
+/// Files edited as one album
+enum Source {
+    Directory(PathBuf),
+    /// Listed files of a directory
+    Files(PathBuf, Vec<PathBuf>),
+}
+
+impl Source {
+    fn dir(&self) -> &Path {
+        match self {
+            Source::Directory(path) => path,
+            Source::Files(path, _) => path,
+        }
+    }
+
+    fn find(&self, options: &FinderOptions) -> Result<Vec<FileInput>, FindError> {
+        match self {
+            Source::Directory(path) => finder::find(path, options),
+            Source::Files(_, paths) => finder::read_files(paths, options),
+        }
+    }
+}
+
+/// Groups listed files by directories, keeping the order of first appearance
+fn group_files(paths: Vec<PathBuf>) -> Vec<Source> {
+    let mut result: Vec<Source> = Vec::new();
+    for path in paths {
+        let dir = path.parent().map(ToOwned::to_owned).unwrap_or_default();
+        match result.iter_mut().find(|x| x.dir() == dir) {
+            Some(Source::Files(_, files)) => files.push(path),
+            _ => result.push(Source::Files(dir, vec![path])),
+        }
+    }
+    result
+}
+
+struct Args {
+    sources: Vec<Source>,
+    /// Go on with other files and albums when one fails
+    continue_on_error: bool,
+    /// Print tags of files instead of editing them
+    export: Option<ExportFormat>,
+    /// A file to read tags from instead of editing them
+    import: Option<PathBuf>,
+    /// Remove all tags of files instead of editing them
+    clean: bool,
+    /// Print changes instead of writing them
+    dry_run: bool,
+    /// Keep values already set in files, only missing values are written
+    fill_missing: bool,
+    /// Stop on suspicious values instead of warning about them
+    strict: bool,
+    /// Answer yes to every question, a dry run still only prints changes
+    yes: bool,
+    /// An answer taken when nothing is typed
+    default_answer: Option<bool>,
+    /// Do not print progress and totals
+    quiet: bool,
+    format: SummaryFormat,
+    /// Default track numbers
+    numbering: Numbering,
+    /// Minimum number of digits of track and disc numbers
+    number_width: Option<usize>,
+    /// Convert artists, album and titles to title case
+    title_case: bool,
+    /// What to do with featured artists of titles
+    featured: Option<FeaturedMode>,
+    /// A file to record written files to
+    manifest: Option<PathBuf>,
+    /// Write a playlist of written files
+    playlist: bool,
+    /// Choose files of an album to tag
+    select: bool,
+    /// A service to look albums up in
+    musicbrainz: Option<MusicBrainz>,
+    /// An API key to identify files without a title with
+    #[cfg(feature = "acoustid")]
+    acoustid_key: Option<String>,
+    album_options: AlbumOptions,
+    finder_options: FinderOptions,
+    write_options: WriteOptions,
+}
+
+fn get_command() -> Result<Command, AppError> {
+    let mut paths = Vec::new();
+    let mut files_from = None;
+    let mut continue_on_error = false;
+    let mut strict = false;
+    let mut dry_run = false;
+    let mut fill_missing = false;
+    let mut export = None;
+    let mut import = None;
+    let mut clean = false;
+    let mut yes = false;
+    let mut quiet = false;
+    let mut format = SummaryFormat::default();
+    let mut numbering = Numbering::Tags;
+    let mut number_width = None;
+    let mut title_case = false;
+    let mut featured = None;
+    let mut template = None;
+    let mut extensions = None;
+    let mut recursive = false;
+    let mut lowercase_extension = false;
+    let mut same_artist = false;
+    let mut id3_version = None;
+    let mut finder_options = FinderOptions::default();
+    let mut write_options = WriteOptions::default();
+    let mut manifest = None;
+    let mut musicbrainz = false;
+    let mut playlist = false;
+    let mut select = false;
+    #[cfg(feature = "acoustid")]
+    let mut acoustid = false;
+    // paths may be not valid UTF-8, only option names and values are converted
+    let mut args = env::args_os().peekable();
     args.next(); // contains path to executable
-    match args.next() {
-        Some(path) => {
-            let path = PathBuf::from(path);
-            if !path.is_dir() {
-                Err(AppError::PathNotExists(path))
-            } else {
-                Ok(path)
+    if args.peek().and_then(|x| x.to_str()) == Some(COMMAND_UNDO) {
+        args.next();
+        return get_undo_command(args);
+    }
+    while let Some(arg) = args.next() {
+        match arg.to_str().unwrap_or_default() {
+            #[cfg(feature = "acoustid")]
+            OPTION_ACOUSTID => acoustid = true,
+            OPTION_BACKUP => write_options.backup = Backup::Keep,
+            OPTION_CLEAN => clean = true,
+            OPTION_CONTINUE_ON_ERROR => continue_on_error = true,
+            OPTION_COPY => write_options.copy = true,
+            OPTION_DESTINATION => {
+                write_options.destination = Some(next_path(&mut args, OPTION_DESTINATION)?);
+            }
+            OPTION_DRY_RUN => dry_run = true,
+            OPTION_FILL_MISSING => fill_missing = true,
+            OPTION_EXPORT => export = Some(next_value(&mut args, OPTION_EXPORT)?.parse()?),
+            OPTION_EXTENSIONS => {
+                let value = next_value(&mut args, OPTION_EXTENSIONS)?;
+                extensions = Some(
+                    value
+                        .split(',')
+                        .map(|x| x.trim().trim_start_matches('.').to_owned())
+                        .filter(|x| !x.is_empty())
+                        .collect(),
+                );
+            }
+            OPTION_FEATURED => {
+                let value = next_value(&mut args, OPTION_FEATURED)?;
+                featured = Some(
+                    value
+                        .parse()
+                        .map_err(|_| AppError::InvalidValue(OPTION_FEATURED, value))?,
+                );
+            }
+            OPTION_FILES_FROM => files_from = Some(next_path(&mut args, OPTION_FILES_FROM)?),
+            OPTION_FORMAT => format = next_value(&mut args, OPTION_FORMAT)?.parse()?,
+            OPTION_HELP => return Ok(Command::Help),
+            OPTION_IMPORT => import = Some(next_path(&mut args, OPTION_IMPORT)?),
+            OPTION_ID3V1 => write_options.write_id3v1 = true,
+            OPTION_ID3V23 => id3_version = Some(Id3Version::Id3v23),
+            OPTION_JOBS => {
+                let value = next_value(&mut args, OPTION_JOBS)?;
+                finder_options.threads = value.parse().map_err(|_| AppError::InvalidValue(OPTION_JOBS, value))?;
+            }
+            OPTION_LOWERCASE_EXTENSION => lowercase_extension = true,
+            OPTION_MAKE_WRITABLE => write_options.make_writable = true,
+            OPTION_MANIFEST => manifest = Some(next_path(&mut args, OPTION_MANIFEST)?),
+            OPTION_MUSICBRAINZ => musicbrainz = true,
+            OPTION_NUMBER => numbering = Numbering::Missing,
+            OPTION_NUMBER_WIDTH => {
+                let value = next_value(&mut args, OPTION_NUMBER_WIDTH)?;
+                number_width = Some(
+                    value
+                        .parse()
+                        .map_err(|_| AppError::InvalidValue(OPTION_NUMBER_WIDTH, value))?,
+                );
+            }
+            OPTION_PLAYLIST => playlist = true,
+            OPTION_PRESERVE_MTIME => write_options.preserve_modified_time = true,
+            OPTION_QUIET | OPTION_QUIET_SHORT => quiet = true,
+            OPTION_RECURSIVE => recursive = true,
+            OPTION_RENUMBER => numbering = Numbering::Position,
+            OPTION_SAME_ARTIST => same_artist = true,
+            OPTION_SELECT => select = true,
+            OPTION_STRICT => strict = true,
+            OPTION_TEMPLATE => template = Some(next_value(&mut args, OPTION_TEMPLATE)?),
+            OPTION_TITLE_CASE => title_case = true,
+            OPTION_TEMPORARY_BACKUP => write_options.backup = Backup::RemoveOnSuccess,
+            OPTION_VERSION => return Ok(Command::Version),
+            OPTION_YES | OPTION_YES_SHORT => yes = true,
+            _ if arg.to_string_lossy().starts_with("--") => {
+                return Err(AppError::UnknownOption(arg.to_string_lossy().into_owned()))
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    // command line options override a config
+    let config = Config::load().map_err(AppError::Config)?;
+    if let Some(extensions) = extensions.or(config.extensions) {
+        finder_options.extensions = extensions;
+    }
+    finder_options.recursive = recursive || config.recursive.unwrap_or(false);
+    write_options.preserve_modified_time =
+        write_options.preserve_modified_time || config.preserve_mtime.unwrap_or(false);
+    write_options.make_writable = write_options.make_writable || config.make_writable.unwrap_or(false);
+    write_options.copy = write_options.copy || config.copy.unwrap_or(false);
+    if let Some(id3_version) = id3_version.or(config.id3_version) {
+        write_options.id3_version = id3_version;
+    }
+    if let Some(value_separator) = config.value_separator {
+        finder_options.value_separator = value_separator.clone();
+        write_options.value_separator = value_separator;
+    }
+    let yes = yes || config.confirm == Some(false);
+    let number_width = number_width.or(config.number_width);
+    let title_case = title_case || config.title_case.unwrap_or(false);
+    let featured = featured.or(config.featured);
+    let mut album_options = AlbumOptions::default();
+    if let Some(various_artists) = config.various_artists {
+        album_options.various_artists = various_artists;
+    }
+    if let Some(threshold) = config.various_artists_threshold {
+        album_options.various_artists_threshold = threshold;
+    }
+    let min_year = config.min_year.unwrap_or(*album_options.years.start());
+    let max_year = config.max_year.unwrap_or(*album_options.years.end());
+    album_options.years = min_year..=max_year;
+    album_options.same_artist = same_artist || config.same_artist.unwrap_or(false);
+    let template = template.or(config.filename_template);
+    // files are organized into directories when moved to a destination, unless a template is given
+    let template = match template {
+        Some(ref template) => Some(template.as_str()),
+        None if write_options.destination.is_some() => Some(ORGANIZE_TEMPLATE),
+        None => None,
+    };
+    if let Some(template) = template {
+        write_options.template = FilenameTemplate::new(template, DEFAULT_REPLACEMENT).map_err(AppError::Template)?;
+    }
+    write_options.template = write_options
+        .template
+        .with_lowercase_extension(lowercase_extension || config.lowercase_extension.unwrap_or(false));
+    let manifest = manifest.or_else(Manifest::default_path);
+    let musicbrainz = if musicbrainz {
+        Some(MusicBrainz::new(
+            config.musicbrainz_url.as_deref().unwrap_or(MUSICBRAINZ_URL),
+        ))
+    } else {
+        None
+    };
+    #[cfg(feature = "acoustid")]
+    let acoustid_key = match config.acoustid_key {
+        Some(key) if acoustid => Some(key),
+        None if acoustid => return Err(AppError::MissingValue("acoustid_key")),
+        _ => None,
+    };
+    let mut sources = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            return Err(AppError::PathNotExists(path));
+        }
+        sources.push(Source::Directory(path));
+    }
+    if let Some(files_from) = files_from {
+        let list = fs::read_to_string(&files_from).map_err(|err| AppError::ReadFileList(files_from, err))?;
+        let files = list.lines().map(str::trim).filter(|x| !x.is_empty()).map(PathBuf::from);
+        sources.extend(group_files(files.collect()));
+    }
+    if sources.is_empty() {
+        sources.push(Source::Directory(env::current_dir().map_err(AppError::GetCurrentDir)?));
+    }
+    Ok(Command::Tag(Box::new(Args {
+        sources,
+        continue_on_error,
+        export,
+        import,
+        clean,
+        dry_run,
+        fill_missing,
+        strict,
+        yes,
+        default_answer: config.default_answer,
+        quiet,
+        format,
+        numbering,
+        number_width,
+        title_case,
+        featured,
+        manifest,
+        playlist,
+        select,
+        musicbrainz,
+        #[cfg(feature = "acoustid")]
+        acoustid_key,
+        album_options,
+        finder_options,
+        write_options,
+    })))
+}
+
+fn get_undo_command(mut args: impl Iterator<Item = OsString>) -> Result<Command, AppError> {
+    let mut manifest = None;
+    let mut yes = false;
+    let mut quiet = false;
+    let mut format = SummaryFormat::default();
+    while let Some(arg) = args.next() {
+        match arg.to_str().unwrap_or_default() {
+            OPTION_FORMAT => format = next_value(&mut args, OPTION_FORMAT)?.parse()?,
+            OPTION_HELP => return Ok(Command::Help),
+            OPTION_QUIET | OPTION_QUIET_SHORT => quiet = true,
+            OPTION_YES | OPTION_YES_SHORT => yes = true,
+            _ if arg.to_string_lossy().starts_with("--") => {
+                return Err(AppError::UnknownOption(arg.to_string_lossy().into_owned()))
             }
+            _ if manifest.is_none() => manifest = Some(PathBuf::from(arg)),
+            _ => return Err(AppError::UnknownOption(arg.to_string_lossy().into_owned())),
         }
-        None => env::current_dir().map_err(AppError::GetCurrentDir),
     }
+    let manifest = manifest.ok_or(AppError::MissingValue(COMMAND_UNDO))?;
+    let config = Config::load().map_err(AppError::Config)?;
+    let mut write_options = WriteOptions::default();
+    if let Some(id3_version) = config.id3_version {
+        write_options.id3_version = id3_version;
+    }
+    if let Some(value_separator) = config.value_separator {
+        write_options.value_separator = value_separator;
+    }
+    Ok(Command::Undo(UndoArgs {
+        manifest,
+        yes: yes || config.confirm == Some(false),
+        default_answer: config.default_answer,
+        quiet,
+        format,
+        write_options,
+    }))
+}
+
+/// Returns a value following an option, it has to be valid UTF-8 unlike paths
+fn next_value(args: &mut impl Iterator<Item = OsString>, option: &'static str) -> Result<String, AppError> {
+    args.next()
+        .ok_or(AppError::MissingValue(option))?
+        .into_string()
+        .map_err(|x| AppError::InvalidValue(option, x.to_string_lossy().into_owned()))
+}
+
+/// Returns a path following an option
+fn next_path(args: &mut impl Iterator<Item = OsString>, option: &'static str) -> Result<PathBuf, AppError> {
+    args.next().map(PathBuf::from).ok_or(AppError::MissingValue(option))
 }
 
-pub fn run() -> Result<(), AppError> {
-    let root_path = get_path()?;
-    let input = finder::find(root_path).map_err(AppError::FindTracks)?;
+/// Returns a number with a total, e.g. `3/12`, or the number alone when the total is unknown
+fn format_number(number: u32, total: Option<u32>) -> String {
+    match total {
+        Some(total) => format!("{}/{}", number, total),
+        None => number.to_string(),
+    }
+}
 
-    let album_input = AlbumInput::from_file_input(&input);
-    let album_output = match AlbumEditor::new(album_input).run().map_err(AppError::EditAlbum)? {
+fn print_output(output: &FileOutput, target_path: &Path) {
+    println!("{} -> {}", output.path.display(), target_path.display());
+    let mut fields = vec![
+        ("ARTIST", output.artist.clone()),
+        ("ALBUM ARTIST", output.album_artist.clone()),
+        ("ALBUM", output.album.clone()),
+        ("DATE", output.date.to_string()),
+        ("TITLE", output.title.clone()),
+        ("TRACK", format_number(output.track_number, output.total_tracks)),
+        ("DISC", format_number(output.disc_number, output.total_discs)),
+    ];
+    if let Some(ref disc_subtitle) = output.disc_subtitle {
+        fields.push(("DISC SUBTITLE", disc_subtitle.clone()));
+    }
+    if let Some(ref genre) = output.genre {
+        fields.push(("GENRE", genre.clone()));
+    }
+    if let Some(ref grouping) = output.grouping {
+        fields.push(("GROUPING", grouping.clone()));
+    }
+    if let Some(ref subtitle) = output.subtitle {
+        fields.push(("SUBTITLE", subtitle.clone()));
+    }
+    if let Some(ref composer) = output.composer {
+        fields.push(("COMPOSER", composer.clone()));
+    }
+    if let Some(ref comment) = output.comment {
+        fields.push(("COMMENT", comment.clone()));
+    }
+    if let Some(ref lyrics) = output.lyrics {
+        let lines = lyrics.lines().count();
+        fields.push(("LYRICS", format!("{} line{}", lines, if lines == 1 { "" } else { "s" })));
+    }
+    if let Some(bpm) = output.bpm {
+        fields.push(("BPM", bpm.to_string()));
+    }
+    if let Some(rating) = output.rating {
+        fields.push(("RATING", format!("{}/5", rating)));
+    }
+    if let Some(ref isrc) = output.isrc {
+        fields.push(("ISRC", isrc.clone()));
+    }
+    if output.compilation {
+        fields.push(("COMPILATION", String::from("yes")));
+    }
+    if let Some(ref cover) = output.cover {
+        fields.push(("COVER", cover.path.display().to_string()));
+    }
+    for (key, value) in fields {
+        println!("    {}: {}", key, value);
+    }
+}
+
+/// Asks a yes/no question until a valid answer is given
+fn confirm(question: &str, default: Option<bool>) -> Result<bool, AppError> {
+    loop {
+        match ask(question, "", default)? {
+            Answer::Yes => return Ok(true),
+            Answer::No => return Ok(false),
+            Answer::Other(_) => println!("Wrong answer!"),
+        }
+    }
+}
+
+/// An answer to a question
+enum Answer {
+    Yes,
+    No,
+    /// Anything else, e.g. a track to edit
+    Other(String),
+}
+
+/// Asks a question with a hint of other answers, e.g. `Continue? [Y/n, or a track number]`
+///
+/// Yes and no are accepted in any case, an empty answer takes a default or is asked again.
+/// The end of input takes a default too, without one it is an error.
+fn ask(question: &str, hint: &str, default: Option<bool>) -> Result<Answer, AppError> {
+    let choices = match default {
+        Some(true) => "Y/n",
+        Some(false) => "y/N",
+        None => "y/n",
+    };
+    loop {
+        print!("{} [{}{}]: ", question, choices, hint);
+        stdout().flush().map_err(AppError::PrintConfirmation)?;
+        let mut answer = String::new();
+        let len = stdin().read_line(&mut answer).map_err(AppError::ReadConfirmation)?;
+        let value = answer.trim();
+        return Ok(match value.to_lowercase().as_str() {
+            "y" | "yes" => Answer::Yes,
+            "n" | "no" => Answer::No,
+            "" => match default {
+                Some(true) => Answer::Yes,
+                Some(false) => Answer::No,
+                None if len == 0 => {
+                    return Err(AppError::ReadConfirmation(IoError::from(IoErrorKind::UnexpectedEof)));
+                }
+                None => continue,
+            },
+            _ => Answer::Other(value.to_owned()),
+        });
+    }
+}
+
+/// Returns target paths shared by several files or taken by a file which is not going to be renamed
+fn find_collisions(output: &[FileOutput], options: &WriteOptions) -> Vec<Collision> {
+    let mut targets: Vec<Collision> = Vec::new();
+    for item in output {
+        let target = item.target_path(options);
+        match targets.iter_mut().find(|x| x.target == target) {
+            Some(collision) => collision.sources.push(item.path.clone()),
+            None => targets.push(Collision {
+                target,
+                sources: vec![item.path.clone()],
+            }),
+        }
+    }
+    targets
+        .into_iter()
+        .filter(|x| x.sources.len() > 1 || (x.target.exists() && output.iter().all(|item| item.path != x.target)))
+        .collect()
+}
+
+/// Files which are going to be renamed to the same path
+#[derive(Debug)]
+pub struct Collision {
+    pub target: PathBuf,
+    pub sources: Vec<PathBuf>,
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        let sources: Vec<String> = self.sources.iter().map(|x| x.display().to_string()).collect();
+        write!(out, "{} <- {}", self.target.display(), sources.join(", "))?;
+        if self.sources.len() == 1 {
+            write!(out, " (file already exists)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of a run, printed by the binary
+#[derive(Debug, Default)]
+pub struct Summary {
+    /// Original and new paths of files whose tags were written
+    pub written: Vec<(PathBuf, PathBuf)>,
+    /// Files whose tags were removed
+    pub cleaned: Vec<PathBuf>,
+    /// Copies removed when undoing a run
+    pub removed: Vec<PathBuf>,
+    /// Files which were read but not written (a dry run, a declined confirmation or quitting)
+    pub skipped: Vec<PathBuf>,
+    /// Albums and files skipped because of an error, when going on with others
+    pub errors: Vec<(PathBuf, AppError)>,
+    /// An error which stopped the run, files processed before it are listed as usual
+    pub error: Option<AppError>,
+    /// Original tags of written files
+    pub manifest: Manifest,
+    /// A file the manifest is recorded to
+    pub manifest_path: Option<PathBuf>,
+    /// How the summary is printed
+    pub format: SummaryFormat,
+    /// Totals are not printed, a JSON summary still is
+    pub quiet: bool,
+}
+
+impl Summary {
+    /// Returns a number of files read
+    pub fn processed(&self) -> usize {
+        self.written.len() + self.cleaned.len() + self.removed.len() + self.skipped.len()
+    }
+
+    /// Returns a JSON array of objects with `original_path`, `new_path` and `status` of every file
+    ///
+    /// A status is `written`, `cleaned`, `removed`, `skipped` or `failed`, a failed album or file has an `error` too.
+    /// A new path is `null` unless a file is written or cleaned. An error which stopped the run goes last,
+    /// as a failed entry without paths.
+    pub fn to_json(&self) -> Json {
+        let entry = |original: &Path, new: Option<&Path>, status: &str| {
+            vec![
+                (
+                    String::from("original_path"),
+                    Json::from(original.to_string_lossy().as_ref()),
+                ),
+                (
+                    String::from("new_path"),
+                    new.map(|x| Json::from(x.to_string_lossy().as_ref()))
+                        .unwrap_or(Json::Null),
+                ),
+                (String::from("status"), Json::from(status)),
+            ]
+        };
+        let written = self
+            .written
+            .iter()
+            .map(|(source, target)| entry(source, Some(target), "written"));
+        let cleaned = self.cleaned.iter().map(|x| entry(x, Some(x), "cleaned"));
+        let removed = self.removed.iter().map(|x| entry(x, None, "removed"));
+        let skipped = self.skipped.iter().map(|x| entry(x, None, "skipped"));
+        let failed = self.errors.iter().map(|(path, err)| {
+            let mut items = entry(path, None, "failed");
+            items.push((String::from("error"), Json::from(err.to_string().as_str())));
+            items
+        });
+        let stopped = self.error.iter().map(|err| {
+            vec![
+                (String::from("original_path"), Json::Null),
+                (String::from("new_path"), Json::Null),
+                (String::from("status"), Json::from("failed")),
+                (String::from("error"), Json::from(err.to_string().as_str())),
+            ]
+        });
+        Json::Array(
+            written
+                .chain(cleaned)
+                .chain(removed)
+                .chain(skipped)
+                .chain(failed)
+                .chain(stopped)
+                .map(Json::Object)
+                .collect(),
+        )
+    }
+}
+
+/// Prints totals and failures or a JSON array of files, a quiet or an empty text summary prints nothing
+impl fmt::Display for Summary {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        if self.format == SummaryFormat::Json {
+            return writeln!(out, "{:#}", self.to_json());
+        }
+        if self.quiet || (self.processed() == 0 && self.errors.is_empty()) {
+            return Ok(());
+        }
+        write!(out, "{} written, ", self.written.len())?;
+        if !self.cleaned.is_empty() {
+            write!(out, "{} cleaned, ", self.cleaned.len())?;
+        }
+        if !self.removed.is_empty() {
+            write!(out, "{} removed, ", self.removed.len())?;
+        }
+        writeln!(out, "{} skipped, {} failed", self.skipped.len(), self.errors.len())?;
+        for (path, err) in &self.errors {
+            writeln!(out, "    {}: {}", path.display(), err)?;
+        }
+        match self.manifest_path {
+            Some(ref path) if !self.manifest.entries.is_empty() => {
+                writeln!(out, "Undo with: tagg undo {}", path.display())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Receives events of a run as they happen, e.g. to drive a progress bar
+///
+/// All methods do nothing by default.
+pub trait Observer {
+    /// Tags of a file are read
+    fn on_file_read(&mut self, _input: &FileInput) {}
+
+    /// Tags of a file at `source` are written and the file is moved to `target`
+    fn on_file_written(&mut self, _source: &Path, _target: &Path) {}
+
+    /// All tags of a file are removed
+    fn on_file_cleaned(&mut self, _path: &Path) {}
+
+    /// A copy of a file made by a run is removed when undoing the run
+    fn on_file_removed(&mut self, _path: &Path) {}
+
+    /// An album or a directory at `path` is skipped because of an error
+    fn on_error(&mut self, _path: &Path, _err: &AppError) {}
+}
+
+/// Prints written files and skipped albums
+#[derive(Debug, Default)]
+pub struct PrintObserver {
+    /// Only print skipped albums, to stderr
+    pub quiet: bool,
+}
+
+impl Observer for PrintObserver {
+    fn on_file_written(&mut self, _source: &Path, target: &Path) {
+        if !self.quiet {
+            println!("Tags written to {}", target.display());
+        }
+    }
+
+    fn on_file_cleaned(&mut self, path: &Path) {
+        if !self.quiet {
+            println!("Tags removed from {}", path.display());
+        }
+    }
+
+    fn on_file_removed(&mut self, path: &Path) {
+        if !self.quiet {
+            println!("Removed {}", path.display());
+        }
+    }
+
+    fn on_error(&mut self, path: &Path, err: &AppError) {
+        eprintln!("{}: {}", path.display(), err);
+    }
+}
+
+/// Runs according to command line arguments, events are printed unless asked to be quiet
+///
+/// Only invalid arguments are returned as an error, an error stopping the run is [`Summary::error`].
+pub fn run() -> Result<Summary, AppError> {
+    let command = get_command()?;
+    let mut observer = PrintObserver {
+        quiet: command.is_quiet(),
+    };
+    Ok(run_command(command, &mut observer))
+}
+
+/// Runs according to command line arguments, events go to an observer
+pub fn run_with(observer: &mut dyn Observer) -> Result<Summary, AppError> {
+    Ok(run_command(get_command()?, observer))
+}
+
+fn run_command(command: Command, observer: &mut dyn Observer) -> Summary {
+    let mut summary = Summary::default();
+    if let Err(err) = run_summarized(command, &mut summary, observer) {
+        summary.error = Some(err);
+    }
+    summary
+}
+
+fn run_summarized(command: Command, summary: &mut Summary, observer: &mut dyn Observer) -> Result<(), AppError> {
+    let args = match command {
+        Command::Help => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Command::Version => {
+            println!("tagg {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Command::Tag(args) => args,
+        Command::Undo(args) => {
+            summary.quiet = args.quiet;
+            summary.format = args.format;
+            return undo(&args, summary, observer);
+        }
+    };
+    summary.quiet = args.quiet;
+    summary.format = args.format;
+    summary.manifest_path = args.manifest.clone();
+    if let Some(ref import_path) = args.import {
+        let data = fs::read_to_string(import_path).map_err(|err| AppError::ReadImport(import_path.clone(), err))?;
+        let is_csv = import_path.extension().is_some_and(|x| x.eq_ignore_ascii_case("csv"));
+        let output = if is_csv {
+            exchange::from_csv(&data)
+        } else {
+            exchange::from_json(&data)
+        };
+        let output = output.map_err(AppError::Import)?;
+        let inputs = output
+            .iter()
+            .map(|x| {
+                FileInput::from_path(&x.path, x.format)
+                    .map(|input| input.join_values(&args.write_options.value_separator))
+                    .map_err(|err| AppError::ReadTags(x.path.clone(), err))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        return write_output(output, &inputs, &args, summary, observer, None);
+    }
+    if let Some(format) = args.export {
+        let mut input = Vec::new();
+        for source in &args.sources {
+            match source.find(&args.finder_options) {
+                Ok(items) => {
+                    items.iter().for_each(|x| observer.on_file_read(x));
+                    input.extend(items);
+                }
+                Err(err) if args.continue_on_error => observer.on_error(source.dir(), &AppError::FindTracks(err)),
+                Err(err) => return Err(AppError::FindTracks(err)),
+            }
+        }
+        match format {
+            ExportFormat::Csv => print!("{}", exchange::to_csv(&input)),
+            ExportFormat::Json => println!("{:#}", exchange::to_json(&input)),
+        }
+        return Ok(());
+    }
+
+    if args.clean {
+        for source in &args.sources {
+            match clean_album(source, &args, summary, observer) {
+                Ok(()) => {}
+                Err(err) if args.continue_on_error => {
+                    observer.on_error(source.dir(), &err);
+                    summary.errors.push((source.dir().to_owned(), err));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        return Ok(());
+    }
+
+    if !args.quiet {
+        println!("Type :h for a list of commands");
+    }
+    for source in &args.sources {
+        if args.sources.len() > 1 && !args.quiet {
+            println!("{}", source.dir().display());
+        }
+        match run_album(source, &args, summary, observer) {
+            Ok(AlbumResult::Done) => {}
+            Ok(AlbumResult::Quit) => break,
+            Err(err) if args.continue_on_error => {
+                observer.on_error(source.dir(), &err);
+                summary.errors.push((source.dir().to_owned(), err));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// How editing of an album has ended
+enum AlbumResult {
+    Done,
+    /// A user asked to quit, other albums are not edited
+    Quit,
+}
+
+/// Removes all tags of files of an album, after a confirmation
+fn clean_album(
+    source: &Source,
+    args: &Args,
+    summary: &mut Summary,
+    observer: &mut dyn Observer,
+) -> Result<(), AppError> {
+    let input = source.find(&args.finder_options).map_err(AppError::FindTracks)?;
+    input.iter().for_each(|x| observer.on_file_read(x));
+    if !args.quiet {
+        for item in &input {
+            println!("{}", item.path.display());
+        }
+    }
+    let question = format!("Remove all tags of {} files?", input.len());
+    if args.dry_run || !(args.yes || confirm(&question, args.default_answer)?) {
+        summary.skipped.extend(input.into_iter().map(|x| x.path));
+        return Ok(());
+    }
+    for item in input {
+        file::clean(&item.path, item.format, &args.write_options).map_err(AppError::WriteFile)?;
+        observer.on_file_cleaned(&item.path);
+        summary.cleaned.push(item.path);
+    }
+    Ok(())
+}
+
+/// Edits files of a source as one album, or as several albums when they have different album tags
+fn run_album(
+    source: &Source,
+    args: &Args,
+    summary: &mut Summary,
+    observer: &mut dyn Observer,
+) -> Result<AlbumResult, AppError> {
+    let input = source.find(&args.finder_options).map_err(AppError::FindTracks)?;
+    input.iter().for_each(|x| observer.on_file_read(x));
+    let groups = group_albums(input.clone());
+    if groups.len() < 2 || !confirm_groups(&groups, args)? {
+        return edit_album(source, input, args, summary, observer);
+    }
+    for group in groups {
+        if !args.quiet {
+            println!("{}", describe_group(&group));
+        }
+        if let AlbumResult::Quit = edit_album(source, group.items, args, summary, observer)? {
+            return Ok(AlbumResult::Quit);
+        }
+    }
+    Ok(AlbumResult::Done)
+}
+
+/// Lists albums found among files of a source and asks whether to edit them separately
+fn confirm_groups(groups: &[AlbumGroup], args: &Args) -> Result<bool, AppError> {
+    if !args.quiet {
+        println!("Found {} albums:", groups.len());
+        for group in groups {
+            println!("    {}", describe_group(group));
+        }
+    }
+    Ok(args.yes || confirm("Edit them as separate albums?", args.default_answer)?)
+}
+
+/// Returns a line such as `Album by Artist (10 files)`
+fn describe_group(group: &AlbumGroup) -> String {
+    let files = match group.items.len() {
+        1 => String::from("1 file"),
+        len => format!("{} files", len),
+    };
+    match (&group.album, &group.album_artist) {
+        (Some(album), Some(album_artist)) => format!("{} by {} ({})", album, album_artist, files),
+        (Some(album), None) => format!("{} ({})", album, files),
+        (None, _) => format!("Files without an album ({})", files),
+    }
+}
+
+fn edit_album(
+    source: &Source,
+    mut input: Vec<FileInput>,
+    args: &Args,
+    summary: &mut Summary,
+    observer: &mut dyn Observer,
+) -> Result<AlbumResult, AppError> {
+    if let Some(ref musicbrainz) = args.musicbrainz {
+        look_up_album(musicbrainz, &mut input, args);
+    }
+
+    // album values are taken from all files, unless asked to take them from selected files only
+    let mut album_files = None;
+    if args.select {
+        let selected = select_files(&input)?;
+        let (chosen, others): (Vec<_>, Vec<_>) = input
+            .into_iter()
+            .enumerate()
+            .partition(|(idx, _)| selected.contains(idx));
+        input = chosen.into_iter().map(|(_, x)| x).collect();
+        if !others.is_empty() {
+            summary.skipped.extend(others.iter().map(|(_, x)| x.path.clone()));
+            if args.yes || !confirm("Take album values from selected files only?", args.default_answer)? {
+                let mut files = input.clone();
+                files.extend(others.into_iter().map(|(_, x)| x));
+                album_files = Some(files);
+            }
+        }
+    }
+    let paths: Vec<PathBuf> = input.iter().map(|x| x.path.clone()).collect();
+
+    let cover = match Cover::find(source.dir()).map_err(AppError::FindCover)? {
+        Some(cover) if args.yes || confirm(&format!("Embed {}?", cover.path.display()), args.default_answer)? => {
+            Some(Rc::new(cover))
+        }
+        _ => None,
+    };
+
+    let album_input =
+        AlbumInput::from_file_input_with_options(album_files.as_ref().unwrap_or(&input), &args.album_options);
+    let album_output = match AlbumEditor::with_options(album_input, &args.album_options)
+        .run()
+        .map_err(AppError::EditAlbum)?
+    {
         EditorOutput::Finished(output) => output,
-        EditorOutput::Interrupted => return Ok(()),
+        EditorOutput::Interrupted => {
+            summary.skipped.extend(paths);
+            return Ok(AlbumResult::Quit);
+        }
     };
     println!();
 
+    #[cfg(feature = "acoustid")]
+    let mut acoustid = args.acoustid_key.as_deref().map(AcoustId::new);
     let mut output = Vec::new();
-    for item in input {
-        println!("{}", item.path.display());
-        let track_input = TrackInput::from(&item);
-        let track_output = match TrackEditor::new(track_input).run().map_err(AppError::EditTrack)? {
-            EditorOutput::Interrupted => return Ok(()),
+    let mut tracks = Vec::new();
+    let mut inputs = Vec::new();
+    let mut previous = None;
+    // a subtitle typed for a disc is offered for following tracks of the disc
+    let mut disc_subtitles: BTreeMap<u32, String> = BTreeMap::new();
+    let total = input.len();
+    for (idx, item) in input.into_iter().enumerate() {
+        if !args.quiet {
+            println!("{}", item.path.display());
+        }
+        let mut track_input = TrackInput::from(&item).with_position(idx as u32 + 1, args.numbering);
+        if track_input.disc_subtitle.is_none() {
+            track_input.disc_subtitle = disc_subtitles.get(&track_input.disc_number.unwrap_or(1)).cloned();
+        }
+        #[cfg(feature = "acoustid")]
+        let track_input = match acoustid {
+            Some(ref mut acoustid) if item.title.is_none() => {
+                identify_track(acoustid, &item.path, track_input, args.quiet)
+            }
+            _ => track_input,
+        };
+        let track_editor = TrackEditor::new(track_input)
+            .with_progress(idx + 1, total)
+            .with_previous(previous.take());
+        let track_output = match track_editor.run().map_err(AppError::EditTrack)? {
+            EditorOutput::Interrupted => {
+                summary.skipped.extend(paths);
+                return Ok(AlbumResult::Quit);
+            }
             EditorOutput::Finished(output) => output,
         };
-        output.push(FileOutput::from((item.path, &album_output, track_output)));
+        if let Some(ref disc_subtitle) = track_output.disc_subtitle {
+            disc_subtitles.insert(track_output.disc_number, disc_subtitle.clone());
+        }
+        previous = Some(track_output.clone());
+        tracks.push(track_output.clone());
+        inputs.push(item.clone());
+        let mut file_output = FileOutput::from((item, &album_output, track_output));
+        file_output.cover = cover.clone();
+        output.push(file_output);
         println!();
     }
 
+    let album = EditedAlbum { album_output, tracks };
+    write_output(output, &inputs, args, summary, observer, Some(album))?;
+    Ok(AlbumResult::Done)
+}
+
+/// Asks which files of an album to tag, returns their indices
+///
+/// All files are tagged when nothing is typed or at the end of input.
+fn select_files(input: &[FileInput]) -> Result<BTreeSet<usize>, AppError> {
+    for (idx, item) in input.iter().enumerate() {
+        let name = item.path.file_name().unwrap_or(item.path.as_os_str());
+        println!("{:>3}) {}", idx + 1, name.to_string_lossy());
+    }
     loop {
-        print!("Continue? [y/n]: ");
+        print!("Files to tag [all, or numbers like 1-3,5, !4 to exclude]: ");
         stdout().flush().map_err(AppError::PrintConfirmation)?;
         let mut answer = String::new();
         stdin().read_line(&mut answer).map_err(AppError::ReadConfirmation)?;
-        match answer.trim() {
-            "y" => {
-                for item in output {
-                    let path = item.write().map_err(AppError::WriteFile)?;
-                    println!("Tags written to {}", path.display());
+        let value = answer.trim();
+        if value.is_empty() || value.eq_ignore_ascii_case("all") {
+            return Ok((0..input.len()).collect());
+        }
+        match parse_selection(value, input.len()) {
+            Some(selected) => return Ok(selected),
+            None => println!("Wrong answer!"),
+        }
+    }
+}
+
+/// Returns 0-based indices of items selected by 1-based numbers and ranges, e.g. `1-3,5`
+///
+/// Numbers and ranges prefixed with `!` are excluded, from all items when nothing is included.
+/// A malformed or out of range number and an empty selection give `None`.
+fn parse_selection(value: &str, len: usize) -> Option<BTreeSet<usize>> {
+    let mut included = BTreeSet::new();
+    let mut excluded = BTreeSet::new();
+    for part in value.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+        let (indices, part) = match part.strip_prefix('!') {
+            Some(part) => (&mut excluded, part.trim()),
+            None => (&mut included, part),
+        };
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (start.trim().parse::<usize>().ok()?, end.trim().parse::<usize>().ok()?),
+            None => {
+                let number = part.parse::<usize>().ok()?;
+                (number, number)
+            }
+        };
+        if start == 0 || start > end || end > len {
+            return None;
+        }
+        indices.extend(start - 1..end);
+    }
+    if included.is_empty() {
+        included.extend(0..len);
+    }
+    let selected: BTreeSet<usize> = included.difference(&excluded).copied().collect();
+    Some(selected).filter(|x| !x.is_empty())
+}
+
+/// Fills values missing in tags with a release found in MusicBrainz
+///
+/// Failures are only reported, so the album is still edited with values of tags.
+fn look_up_album(musicbrainz: &MusicBrainz, input: &mut [FileInput], args: &Args) {
+    let album_input = AlbumInput::from_file_input_with_options(input, &args.album_options);
+    let album = match album_input.album {
+        Some(ref album) => album,
+        None => {
+            if !args.quiet {
+                eprintln!("Warning: MusicBrainz: an album title is required to look the album up");
+            }
+            return;
+        }
+    };
+    match musicbrainz.find_release(album_input.artist.as_deref(), album) {
+        Ok(Some(release)) => {
+            if !args.quiet {
+                println!("MusicBrainz: found {} - {}", release.artist, release.title);
+            }
+            release.fill_missing(input);
+        }
+        Ok(None) if args.quiet => {}
+        Ok(None) => println!("MusicBrainz: no release found"),
+        Err(_) if args.quiet => {}
+        Err(err) => eprintln!("Warning: MusicBrainz lookup failed: {}", err),
+    }
+}
+
+/// Suggests a title of a file identified by its fingerprint
+///
+/// Failures are only reported, so the track is still edited with values of tags and of a file name.
+#[cfg(feature = "acoustid")]
+fn identify_track(acoustid: &mut AcoustId, path: &Path, mut track_input: TrackInput, quiet: bool) -> TrackInput {
+    match acoustid.identify(path) {
+        Ok(Some(recording)) => {
+            match recording.artist {
+                _ if quiet => {}
+                Some(ref artist) => println!("AcoustID: {} - {}", artist, recording.title),
+                None => println!("AcoustID: {}", recording.title),
+            }
+            track_input.title = Some(recording.title);
+        }
+        Ok(None) if quiet => {}
+        Ok(None) => println!("AcoustID: no recording found"),
+        Err(_) if quiet => {}
+        Err(err) => eprintln!("Warning: AcoustID lookup failed: {}", err),
+    }
+    track_input
+}
+
+/// Values of an edited album, kept so a track can be edited again before writing
+struct EditedAlbum {
+    album_output: AlbumOutput,
+    tracks: Vec<TrackOutput>,
+}
+
+/// Prints or writes outputs, depending on arguments
+///
+/// Changes against `inputs` (tags read from files of outputs, in the same order) are shown
+/// and a track of an edited album can be edited again when asked for confirmation.
+fn write_output(
+    mut output: Vec<FileOutput>,
+    inputs: &[FileInput],
+    args: &Args,
+    summary: &mut Summary,
+    observer: &mut dyn Observer,
+    mut album: Option<EditedAlbum>,
+) -> Result<(), AppError> {
+    let write_options = &args.write_options;
+    for item in &mut output {
+        prepare_output(item, args)?;
+    }
+    loop {
+        let issues = validate::validate(&output);
+        if args.strict && !issues.is_empty() {
+            return Err(AppError::InvalidAlbum(issues));
+        }
+        if !args.quiet {
+            for issue in &issues {
+                eprintln!("Warning: {}", issue);
+            }
+        }
+        if args.dry_run {
+            for item in &output {
+                print_output(item, &item.target_path(write_options));
+            }
+        }
+        let collisions = find_collisions(&output, write_options);
+        if !collisions.is_empty() {
+            return Err(AppError::TargetCollision(collisions));
+        }
+        if args.dry_run {
+            summary.skipped.extend(output.into_iter().map(|x| x.path));
+            return Ok(());
+        }
+        if args.yes {
+            break;
+        }
+        print_changes(&output, inputs, write_options);
+        print_table(&output, write_options);
+        let answer = match album {
+            Some(_) => ask(
+                "Continue?",
+                ", or a track number or a file name to edit the track",
+                args.default_answer,
+            )?,
+            None => ask("Continue?", "", args.default_answer)?,
+        };
+        match (answer, album.as_mut()) {
+            (Answer::Yes, _) => break,
+            (Answer::No, _) => {
+                summary.skipped.extend(output.into_iter().map(|x| x.path));
+                return Ok(());
+            }
+            (Answer::Other(track), Some(album)) => match find_track(&output, &track) {
+                Some(idx) => {
+                    if edit_track(album, idx, &mut output[idx])? {
+                        prepare_output(&mut output[idx], args)?;
+                    }
                 }
-                break;
+                None => println!("There is no track {}", track),
+            },
+            (Answer::Other(_), None) => println!("Wrong answer!"),
+        }
+    }
+    let album = output.first().map(|x| x.album.clone()).unwrap_or_default();
+    let mut playlist = Vec::new();
+    for (item, input) in output.into_iter().zip(inputs) {
+        let source = item.path.clone();
+        let mut entry = PlaylistEntry::new(&item, source.clone(), input.duration);
+        let path = match item.write(write_options).map_err(AppError::WriteFile) {
+            Ok(path) => path,
+            Err(err) if args.continue_on_error => {
+                observer.on_error(&source, &err);
+                summary.errors.push((source, err));
+                continue;
             }
-            "n" => {
-                break;
+            Err(err) => return Err(err),
+        };
+        observer.on_file_written(&source, &path);
+        summary.written.push((source, path.clone()));
+        entry.path = path.clone();
+        playlist.push(entry);
+        // recorded after every file, so a run failing halfway can be undone too
+        summary.manifest.entries.push(ManifestEntry {
+            original: input.clone(),
+            new_path: path,
+            copy: write_options.copy,
+        });
+        if let Some(ref manifest_path) = summary.manifest_path {
+            summary
+                .manifest
+                .write(manifest_path)
+                .map_err(|err| AppError::WriteManifest(manifest_path.clone(), err))?;
+            if summary.manifest.entries.len() == 1 {
+                prune_manifests(manifest_path);
             }
-            _ => println!("Wrong answer!"),
         }
     }
+    if args.playlist && !playlist.is_empty() {
+        let path = playlist::write_playlist(&album, &playlist).map_err(AppError::WritePlaylist)?;
+        if !args.quiet {
+            println!("Playlist written to {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Removes the oldest manifests once a new manifest is recorded to the default directory
+///
+/// Manifests only matter for undoing, so a failure is ignored.
+fn prune_manifests(manifest_path: &Path) {
+    if let Some(dir) = Manifest::default_dir().filter(|x| manifest_path.parent() == Some(x.as_path())) {
+        let _ = Manifest::prune(&dir, KEPT_MANIFESTS);
+    }
+}
 
+/// Restores original tags and paths of files recorded to a manifest, after a confirmation
+///
+/// Files are restored in reverse order, a file which fails is skipped. Copies are removed,
+/// their originals were never changed.
+fn undo(args: &UndoArgs, summary: &mut Summary, observer: &mut dyn Observer) -> Result<(), AppError> {
+    let manifest = Manifest::read(&args.manifest).map_err(|err| AppError::ReadManifest(args.manifest.clone(), err))?;
+    if !args.quiet {
+        for entry in &manifest.entries {
+            if entry.copy {
+                println!("{} (copy, removed)", entry.new_path.display());
+            } else {
+                println!("{} -> {}", entry.new_path.display(), entry.original.path.display());
+            }
+        }
+    }
+    let question = format!("Restore {} files?", manifest.entries.len());
+    if !(args.yes || confirm(&question, args.default_answer)?) {
+        summary.skipped.extend(manifest.entries.into_iter().map(|x| x.new_path));
+        return Ok(());
+    }
+    for entry in manifest.entries.iter().rev() {
+        let source = entry.new_path.clone();
+        if entry.copy {
+            match fs::remove_file(&source) {
+                Ok(()) => {
+                    observer.on_file_removed(&source);
+                    summary.removed.push(source);
+                }
+                Err(err) => {
+                    let err = AppError::RemoveCopy(err);
+                    observer.on_error(&source, &err);
+                    summary.errors.push((source, err));
+                }
+            }
+            continue;
+        }
+        let target = entry.original.path.clone();
+        let result = if target != source && target.exists() {
+            Err(AppError::PathExists(target))
+        } else {
+            entry
+                .restore_output()
+                .write_to(target, &args.write_options)
+                .map_err(AppError::WriteFile)
+        };
+        match result {
+            Ok(path) => {
+                observer.on_file_written(&source, &path);
+                summary.written.push((source, path));
+            }
+            Err(err) => {
+                observer.on_error(&source, &err);
+                summary.errors.push((source, err));
+            }
+        }
+    }
     Ok(())
 }
 
+/// Prints changed fields and new paths of files, files without changes are only counted
+fn print_changes(output: &[FileOutput], inputs: &[FileInput], options: &WriteOptions) {
+    let show = |value: &Option<String>| match value {
+        Some(value) => format!("{:?}", value),
+        None => String::from("(none)"),
+    };
+    let mut unchanged = 0;
+    for (item, input) in output.iter().zip(inputs) {
+        let changes = item.changes(input);
+        let target = item.target_path(options);
+        if changes.is_empty() && target == item.path {
+            unchanged += 1;
+            continue;
+        }
+        if target == item.path {
+            println!("{}", item.path.display());
+        } else {
+            println!("{} -> {}", item.path.display(), target.display());
+        }
+        for change in changes {
+            println!("    {}: {} -> {}", change.field, show(&change.old), show(&change.new));
+        }
+    }
+    if unchanged > 0 {
+        println!("{} unchanged file{}", unchanged, if unchanged == 1 { "" } else { "s" });
+    }
+}
+
+/// Prints a number, a title and a target file name of every track aligned in columns
+fn print_table(output: &[FileOutput], options: &WriteOptions) {
+    let rows: Vec<[String; 3]> = output
+        .iter()
+        .map(|item| {
+            let (track_number, _) = item.padded_track_number();
+            let number = if item.total_discs.unwrap_or(1) > 1 {
+                format!("{}-{}", item.padded_disc_number().0, track_number)
+            } else {
+                track_number
+            };
+            let target = item.target_path(options);
+            let file_name = target.file_name().unwrap_or(target.as_os_str());
+            [number, item.title.clone(), file_name.to_string_lossy().into_owned()]
+        })
+        .collect();
+    let header = [String::from("#"), String::from("TITLE"), String::from("FILE")];
+    let width = |column: usize| {
+        rows.iter()
+            .chain(Some(&header))
+            .map(|x| x[column].chars().count())
+            .max()
+            .unwrap_or(0)
+    };
+    let (number_width, title_width) = (width(0), width(1));
+    println!();
+    for [number, title, file_name] in Some(&header).into_iter().chain(&rows) {
+        println!(
+            "{:>number_width$}  {:<title_width$}  {}",
+            number,
+            title,
+            file_name,
+            number_width = number_width,
+            title_width = title_width
+        );
+    }
+    println!();
+}
+
+/// Applies conversions asked for by arguments to an output before it is written
+fn prepare_output(item: &mut FileOutput, args: &Args) -> Result<(), AppError> {
+    item.number_width = args.number_width;
+    if args.title_case {
+        item.apply_title_case();
+    }
+    // after title case, which would capitalize "feat."
+    if let Some(featured) = args.featured {
+        item.apply_featured(featured, &args.write_options.value_separator);
+    }
+    // existing values are kept as they are, without title case or other conversions
+    if args.fill_missing {
+        item.fill_missing(&args.write_options)
+            .map_err(|err| AppError::ReadTags(item.path.clone(), err))?;
+    }
+    Ok(())
+}
+
+/// Returns an index of a track by its 1-based number in the list or by a file name
+fn find_track(output: &[FileOutput], value: &str) -> Option<usize> {
+    if let Ok(number) = value.parse::<usize>() {
+        return number.checked_sub(1).filter(|x| *x < output.len());
+    }
+    output.iter().position(|x| {
+        let name = |path: Option<&OsStr>| path.is_some_and(|x| x.to_string_lossy() == value);
+        name(x.path.file_name()) || name(x.path.file_stem())
+    })
+}
+
+/// Edits a track of an album again, returns whether the output is changed
+fn edit_track(album: &mut EditedAlbum, idx: usize, output: &mut FileOutput) -> Result<bool, AppError> {
+    println!("{}", output.path.display());
+    let track_input = TrackInput::from(&album.tracks[idx]);
+    let track_editor = TrackEditor::new(track_input).with_progress(idx + 1, album.tracks.len());
+    match track_editor.run().map_err(AppError::EditTrack)? {
+        EditorOutput::Finished(track_output) => {
+            output.set_track(&album.album_output, track_output.clone());
+            album.tracks[idx] = track_output;
+            println!();
+            Ok(true)
+        }
+        EditorOutput::Interrupted => Ok(false),
+    }
+}
+
 #[derive(Debug)]
 pub enum AppError {
+    Config(ConfigError),
     EditAlbum(EditorError),
     EditTrack(EditorError),
+    FindCover(CoverError),
     FindTracks(FindError),
     GetCurrentDir(IoError),
+    Import(ImportError),
+    InvalidAlbum(Vec<Issue>),
+    InvalidValue(&'static str, String),
+    MissingValue(&'static str),
+    PathExists(PathBuf),
     PathNotExists(PathBuf),
     PrintConfirmation(IoError),
     ReadConfirmation(IoError),
+    ReadFileList(PathBuf, IoError),
+    ReadImport(PathBuf, IoError),
+    ReadManifest(PathBuf, ManifestError),
+    ReadTags(PathBuf, Box<dyn StdError + Send + Sync>),
+    RemoveCopy(IoError),
+    TargetCollision(Vec<Collision>),
+    Template(TemplateError),
+    UnknownExportFormat(String),
+    UnknownOption(String),
     WriteFile(FileOutputError),
+    WriteManifest(PathBuf, ManifestError),
+    WritePlaylist(IoError),
 }
 
 impl StdError for AppError {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use self::AppError::*;
         match self {
+            Config(err) => Some(err),
             EditAlbum(err) => Some(err),
             EditTrack(err) => Some(err),
+            FindCover(err) => Some(err),
             FindTracks(err) => Some(err),
             GetCurrentDir(err) => Some(err),
+            Import(err) => Some(err),
+            InvalidAlbum(_) => None,
+            InvalidValue(..) => None,
+            MissingValue(_) => None,
+            PathExists(_) => None,
             PathNotExists(_) => None,
             PrintConfirmation(err) => Some(err),
             ReadConfirmation(err) => Some(err),
+            ReadFileList(_, err) => Some(err),
+            ReadImport(_, err) => Some(err),
+            ReadManifest(_, err) => Some(err),
+            ReadTags(_, err) => Some(err.as_ref()),
+            RemoveCopy(err) => Some(err),
+            TargetCollision(_) => None,
+            Template(err) => Some(err),
+            UnknownExportFormat(_) => None,
+            UnknownOption(_) => None,
             WriteFile(err) => Some(err),
+            WriteManifest(_, err) => Some(err),
+            WritePlaylist(err) => Some(err),
         }
     }
 }
@@ -107,14 +1633,100 @@ impl fmt::Display for AppError {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
         use self::AppError::*;
         match self {
+            Config(err) => write!(out, "invalid config: {}", err),
             EditAlbum(err) => write!(out, "edit album error: {}", err),
             EditTrack(err) => write!(out, "edit track error: {}", err),
+            FindCover(err) => write!(out, "unable to find cover: {}", err),
             FindTracks(err) => write!(out, "unable to find tracks: {}", err),
             GetCurrentDir(err) => write!(out, "failed to get current directory: {}", err),
+            Import(err) => write!(out, "failed to import tags: {}", err),
+            InvalidAlbum(issues) => {
+                write!(out, "suspicious values:")?;
+                for issue in issues {
+                    write!(out, "\n    {}", issue)?;
+                }
+                Ok(())
+            }
+            InvalidValue(option, value) => write!(out, "invalid value of {}: {}", option, value),
+            MissingValue(option) => write!(out, "{} requires a value", option),
+            PathExists(path) => write!(out, "{} already exists", path.display()),
             PathNotExists(path) => write!(out, "{} is not a directory", path.display()),
             PrintConfirmation(err) => write!(out, "failed to print confirmation: {}", err),
             ReadConfirmation(err) => write!(out, "failed to read confirmation: {}", err),
+            ReadFileList(path, err) => write!(out, "failed to read a list of files {}: {}", path.display(), err),
+            ReadImport(path, err) => write!(out, "failed to read {}: {}", path.display(), err),
+            ReadManifest(path, err) => write!(out, "{}: {}", path.display(), err),
+            ReadTags(path, err) => write!(out, "failed to read tags of {}: {}", path.display(), err),
+            RemoveCopy(err) => write!(out, "failed to remove copy: {}", err),
+            TargetCollision(collisions) => {
+                write!(out, "target paths collide:")?;
+                for collision in collisions {
+                    write!(out, "\n    {}", collision)?;
+                }
+                Ok(())
+            }
+            Template(err) => write!(out, "invalid template: {}", err),
+            UnknownExportFormat(format) => write!(out, "unknown export format: {}", format),
+            UnknownOption(arg) => write!(out, "unknown option: {}", arg),
             WriteFile(err) => write!(out, "could not write a file: {}", err),
+            WriteManifest(path, err) => write!(out, "{}: {}", path.display(), err),
+            WritePlaylist(err) => write!(out, "failed to write playlist: {}", err),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selection(indices: &[usize]) -> Option<BTreeSet<usize>> {
+        Some(indices.iter().copied().collect())
+    }
+
+    #[test]
+    fn parses_numbers_and_ranges() {
+        assert_eq!(parse_selection("1-3,5", 6), selection(&[0, 1, 2, 4]));
+        assert_eq!(parse_selection(" 2 , 4 - 5 ", 6), selection(&[1, 3, 4]));
+        assert_eq!(parse_selection("3,3,1-3", 3), selection(&[0, 1, 2]));
+    }
+
+    #[test]
+    fn parses_exclusions() {
+        assert_eq!(parse_selection("!2", 4), selection(&[0, 2, 3]));
+        assert_eq!(parse_selection("1-4,!2-3", 4), selection(&[0, 3]));
+        assert_eq!(parse_selection("! 1", 2), selection(&[1]));
+    }
+
+    #[test]
+    fn rejects_invalid_selections() {
+        for value in &["0", "5", "3-2", "1-5", "a", "1-", "!1-4", "1,!1", "-1"] {
+            assert_eq!(parse_selection(value, 4), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn selects_all_items_by_default() {
+        assert_eq!(parse_selection("", 3), selection(&[0, 1, 2]));
+        assert_eq!(parse_selection(",", 3), selection(&[0, 1, 2]));
+        assert_eq!(parse_selection("", 0), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_non_utf8_paths_of_arguments() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let path = OsString::from_vec(b"Caf\xe9".to_vec());
+        let mut args = vec![path.clone(), path.clone(), OsString::from("json")].into_iter();
+        assert_eq!(next_path(&mut args, OPTION_MANIFEST).unwrap(), PathBuf::from(&path));
+        assert!(matches!(
+            next_value(&mut args, OPTION_FORMAT),
+            Err(AppError::InvalidValue(OPTION_FORMAT, ref x)) if x == "Caf\u{fffd}"
+        ));
+        assert_eq!(next_value(&mut args, OPTION_FORMAT).unwrap(), "json");
+        assert!(matches!(
+            next_path(&mut args, OPTION_MANIFEST),
+            Err(AppError::MissingValue(OPTION_MANIFEST))
+        ));
+    }
+}
@@ -0,0 +1,156 @@
+/// Words kept lowercase inside a title
+const STOP_WORDS: [&str; 19] = [
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "in", "nor", "of", "on", "or", "the", "to", "vs", "via",
+    "with",
+];
+
+/// Characters after which a word starts a new phrase and is capitalized even if it is a stop word
+const PHRASE_ENDS: [char; 7] = [':', '.', '!', '?', '(', '[', '-'];
+
+/// Maximum number of letters of an uppercase word kept as an acronym, e.g. `MGMT`
+const MAX_ACRONYM_LEN: usize = 5;
+
+/// Converts a text to title case, e.g. `THE END OF the world` becomes `The End of the World`
+///
+/// The first and the last word are always capitalized, stop words such as `of` or `the` are lowercased elsewhere.
+/// Text in a single case is recased completely, while in mixed-case text words with deliberate casing
+/// (`McCartney`, `iPod`) and short uppercase words standing alone (`MGMT`) are kept as is.
+/// A run of uppercase words (`THE END OF`) is shouting rather than acronyms, so it is recased.
+/// Whitespace is preserved.
+pub fn title_case(value: &str) -> String {
+    let has_lowercase = value.chars().any(char::is_lowercase);
+    let has_uppercase = value.chars().any(char::is_uppercase);
+    let is_mixed = has_lowercase && has_uppercase;
+    let words: Vec<&str> = value.split_whitespace().collect();
+    let last_idx = words.len().saturating_sub(1);
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut starts_phrase = true;
+    for (idx, word) in words.iter().enumerate() {
+        let start = rest.find(word).unwrap_or(0);
+        result.push_str(&rest[..start]);
+        rest = &rest[start + word.len()..];
+        let is_acronym = is_uppercase(word)
+            && word.chars().filter(|x| x.is_alphabetic()).count() <= MAX_ACRONYM_LEN
+            && !STOP_WORDS.contains(&word.to_lowercase().trim_matches(|x: char| !x.is_alphanumeric()))
+            && !(idx > 0 && is_uppercase(words[idx - 1]))
+            && !words.get(idx + 1).is_some_and(|x| is_uppercase(x));
+        if is_mixed && (has_mixed_case(word) || is_acronym) {
+            result.push_str(word);
+        } else {
+            let lowercase = word.to_lowercase();
+            let is_stop_word = STOP_WORDS.contains(&lowercase.trim_matches(|x: char| !x.is_alphanumeric()));
+            let starts_phrase = starts_phrase || word.starts_with(['(', '[']);
+            if is_stop_word && !starts_phrase && idx != last_idx {
+                result.push_str(&lowercase);
+            } else {
+                result.push_str(&capitalize(&lowercase));
+            }
+        }
+        starts_phrase = word.ends_with(PHRASE_ENDS) || *word == "-";
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Whether a word mixes cases other than capitalized, e.g. `McCartney` or `iPod`
+fn has_mixed_case(word: &str) -> bool {
+    let mut letters = word.chars().filter(|x| x.is_alphabetic());
+    match letters.next() {
+        Some(_) => {
+            let rest: Vec<char> = letters.collect();
+            rest.iter().any(|x| x.is_uppercase()) && rest.iter().any(|x| x.is_lowercase())
+        }
+        None => false,
+    }
+}
+
+/// Whether a word has at least two letters, all of them uppercase
+fn is_uppercase(word: &str) -> bool {
+    let letters: Vec<char> = word.chars().filter(|x| x.is_alphabetic()).collect();
+    letters.len() > 1 && letters.iter().all(|x| x.is_uppercase())
+}
+
+/// Uppercases the first letter of every part of a hyphenated word, except for stop words (`Rock-and-Roll`)
+fn capitalize(word: &str) -> String {
+    word.split('-')
+        .enumerate()
+        .map(|(idx, part)| {
+            if idx > 0 && STOP_WORDS.contains(&part) {
+                part.to_owned()
+            } else {
+                capitalize_first(part)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Uppercases the first letter, leading punctuation such as `(` is skipped
+fn capitalize_first(value: &str) -> String {
+    match value.find(char::is_alphanumeric) {
+        Some(idx) => {
+            let mut chars = value[idx..].chars();
+            let first = chars
+                .next()
+                .map(|x| x.to_uppercase().collect::<String>())
+                .unwrap_or_default();
+            format!("{}{}{}", &value[..idx], first, chars.as_str())
+        }
+        None => value.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recases_uppercase_run_in_mixed_text() {
+        assert_eq!(title_case("THE END OF the world"), "The End of the World");
+        assert_eq!(title_case("THE SONG remix"), "The Song Remix");
+    }
+
+    #[test]
+    fn recases_single_case_text() {
+        assert_eq!(title_case("THE SONG OF THE YEAR"), "The Song of the Year");
+        assert_eq!(title_case("the song of the year"), "The Song of the Year");
+    }
+
+    #[test]
+    fn keeps_deliberate_case() {
+        assert_eq!(title_case("live at the MGMT show"), "Live at the MGMT Show");
+        assert_eq!(title_case("songs by paul McCartney"), "Songs by Paul McCartney");
+        assert_eq!(title_case("my iPod song"), "My iPod Song");
+    }
+
+    #[test]
+    fn recases_long_uppercase_word() {
+        assert_eq!(title_case("BEAUTIFUL day"), "Beautiful Day");
+    }
+
+    #[test]
+    fn recases_uppercase_stop_word() {
+        assert_eq!(title_case("THE world"), "The World");
+        assert_eq!(title_case("rise OF the machines"), "Rise of the Machines");
+    }
+
+    #[test]
+    fn capitalizes_first_and_last_words_and_phrases() {
+        assert_eq!(title_case("of mice and men"), "Of Mice and Men");
+        assert_eq!(title_case("what are you looking at"), "What Are You Looking At");
+        assert_eq!(title_case("song (the remix)"), "Song (The Remix)");
+        assert_eq!(title_case("part one: the return"), "Part One: The Return");
+    }
+
+    #[test]
+    fn capitalizes_hyphenated_words() {
+        assert_eq!(title_case("rock-and-roll music"), "Rock-and-Roll Music");
+    }
+
+    #[test]
+    fn preserves_whitespace() {
+        assert_eq!(title_case("  a  song "), "  A  Song ");
+        assert_eq!(title_case(""), "");
+    }
+}
@@ -0,0 +1,335 @@
+use crate::featured::FeaturedMode;
+use id3::Version as Id3Version;
+use std::{
+    convert::TryFrom,
+    env,
+    error::Error as StdError,
+    fmt, fs,
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    path::{Path, PathBuf},
+};
+
+/// Name of a config file looked up in the working directory and in a config directory of the user
+pub const CONFIG_FILE_NAME: &str = "tagg.toml";
+
+/// Default options read from `tagg.toml`, command line options override them
+///
+/// Only top-level `key = value` pairs of TOML are supported:
+/// strings, booleans, non-negative integers and single-line arrays of strings.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub extensions: Option<Vec<String>>,
+    pub filename_template: Option<String>,
+    pub recursive: Option<bool>,
+    /// Whether extensions of renamed files are lowercased
+    pub lowercase_extension: Option<bool>,
+    /// Whether tagged files are copied instead of moved
+    pub copy: Option<bool>,
+    /// Whether read-only files are made writable
+    pub make_writable: Option<bool>,
+    /// Whether written files keep their modification time
+    pub preserve_mtime: Option<bool>,
+    pub id3_version: Option<Id3Version>,
+    /// Whether to ask for confirmation before writing
+    pub confirm: Option<bool>,
+    /// An answer to a yes/no question taken when nothing is typed
+    pub default_answer: Option<bool>,
+    /// Album artist of a detected compilation
+    pub various_artists: Option<String>,
+    /// An album with more distinct artists than this is a compilation
+    pub various_artists_threshold: Option<usize>,
+    /// Whether every track gets the album artist
+    pub same_artist: Option<bool>,
+    /// Whether artists, album and titles are converted to title case
+    pub title_case: Option<bool>,
+    /// Minimum number of digits of track and disc numbers
+    pub number_width: Option<usize>,
+    /// Range of years accepted by the editor
+    pub min_year: Option<i32>,
+    pub max_year: Option<i32>,
+    /// Separator of multiple artists
+    pub value_separator: Option<String>,
+    /// What to do with featured artists of titles, they are left alone by default
+    pub featured: Option<FeaturedMode>,
+    /// Root of the MusicBrainz web service, e.g. a mirror
+    pub musicbrainz_url: Option<String>,
+    /// An API key of AcoustID, required to identify files
+    pub acoustid_key: Option<String>,
+}
+
+impl Config {
+    /// Reads `tagg.toml` of a config directory of the user (e.g. `~/.config/tagg/tagg.toml`)
+    /// and of the working directory, values of the working directory win
+    ///
+    /// A missing file gives an empty config.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        let user_path = dirs::config_dir().map(|x| x.join("tagg").join(CONFIG_FILE_NAME));
+        let local_path = env::current_dir().ok().map(|x| x.join(CONFIG_FILE_NAME));
+        for path in user_path.iter().chain(local_path.iter()) {
+            if let Some(file_config) = Self::read(path)? {
+                config = config.merge(file_config);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Returns a config of a file, `None` if there is no such file
+    pub fn read(path: &Path) -> Result<Option<Self>, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(data) => Self::parse(&data)
+                .map(Some)
+                .map_err(|err| ConfigError::Parse(path.to_owned(), err)),
+            Err(err) if err.kind() == IoErrorKind::NotFound => Ok(None),
+            Err(err) => Err(ConfigError::Read(path.to_owned(), err)),
+        }
+    }
+
+    pub fn parse(data: &str) -> Result<Self, ConfigParseError> {
+        let mut config = Self::default();
+        for (idx, line) in data.lines().enumerate() {
+            let line_number = idx + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(pos) => (line[..pos].trim(), line[pos + 1..].trim()),
+                None => return Err(ConfigParseError::Syntax(line_number)),
+            };
+            let value = parse_value(value).ok_or(ConfigParseError::Syntax(line_number))?;
+            let invalid = || ConfigParseError::InvalidValue(key.to_owned());
+            match key {
+                "extensions" => {
+                    let items = match value {
+                        Value::Array(items) => items,
+                        _ => return Err(invalid()),
+                    };
+                    let extensions = items
+                        .into_iter()
+                        .map(|x| match x {
+                            Value::String(x) => Some(x.trim_start_matches('.').to_owned()),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<String>>>()
+                        .ok_or_else(invalid)?;
+                    config.extensions = Some(extensions);
+                }
+                "filename_template" => config.filename_template = Some(value.into_string().ok_or_else(invalid)?),
+                "recursive" => config.recursive = Some(value.into_bool().ok_or_else(invalid)?),
+                "lowercase_extension" => config.lowercase_extension = Some(value.into_bool().ok_or_else(invalid)?),
+                "copy" => config.copy = Some(value.into_bool().ok_or_else(invalid)?),
+                "make_writable" => config.make_writable = Some(value.into_bool().ok_or_else(invalid)?),
+                "preserve_mtime" => config.preserve_mtime = Some(value.into_bool().ok_or_else(invalid)?),
+                "id3_version" => {
+                    config.id3_version = match value.into_string().as_deref() {
+                        Some("2.3") => Some(Id3Version::Id3v23),
+                        Some("2.4") => Some(Id3Version::Id3v24),
+                        _ => return Err(invalid()),
+                    }
+                }
+                "featured" => {
+                    config.featured = Some(value.into_string().and_then(|x| x.parse().ok()).ok_or_else(invalid)?)
+                }
+                "confirm" => config.confirm = Some(value.into_bool().ok_or_else(invalid)?),
+                "default_answer" => {
+                    config.default_answer = match value.into_string().as_deref() {
+                        Some("yes") => Some(true),
+                        Some("no") => Some(false),
+                        _ => return Err(invalid()),
+                    }
+                }
+                "various_artists" => config.various_artists = Some(value.into_string().ok_or_else(invalid)?),
+                "title_case" => config.title_case = Some(value.into_bool().ok_or_else(invalid)?),
+                "same_artist" => config.same_artist = Some(value.into_bool().ok_or_else(invalid)?),
+                "number_width" => config.number_width = Some(value.into_integer().ok_or_else(invalid)?),
+                "acoustid_key" => config.acoustid_key = Some(value.into_string().ok_or_else(invalid)?),
+                "musicbrainz_url" => config.musicbrainz_url = Some(value.into_string().ok_or_else(invalid)?),
+                "value_separator" => config.value_separator = Some(value.into_string().ok_or_else(invalid)?),
+                "min_year" => config.min_year = Some(value.into_year().ok_or_else(invalid)?),
+                "max_year" => config.max_year = Some(value.into_year().ok_or_else(invalid)?),
+                "various_artists_threshold" => {
+                    config.various_artists_threshold = Some(value.into_integer().ok_or_else(invalid)?)
+                }
+                _ => return Err(ConfigParseError::UnknownKey(key.to_owned())),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Returns a config with values of `other` replacing values of this config
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            extensions: other.extensions.or(self.extensions),
+            filename_template: other.filename_template.or(self.filename_template),
+            recursive: other.recursive.or(self.recursive),
+            lowercase_extension: other.lowercase_extension.or(self.lowercase_extension),
+            copy: other.copy.or(self.copy),
+            make_writable: other.make_writable.or(self.make_writable),
+            preserve_mtime: other.preserve_mtime.or(self.preserve_mtime),
+            id3_version: other.id3_version.or(self.id3_version),
+            confirm: other.confirm.or(self.confirm),
+            default_answer: other.default_answer.or(self.default_answer),
+            various_artists: other.various_artists.or(self.various_artists),
+            various_artists_threshold: other.various_artists_threshold.or(self.various_artists_threshold),
+            same_artist: other.same_artist.or(self.same_artist),
+            title_case: other.title_case.or(self.title_case),
+            number_width: other.number_width.or(self.number_width),
+            min_year: other.min_year.or(self.min_year),
+            max_year: other.max_year.or(self.max_year),
+            value_separator: other.value_separator.or(self.value_separator),
+            featured: other.featured.or(self.featured),
+            musicbrainz_url: other.musicbrainz_url.or(self.musicbrainz_url),
+            acoustid_key: other.acoustid_key.or(self.acoustid_key),
+        }
+    }
+}
+
+enum Value {
+    Array(Vec<Value>),
+    Bool(bool),
+    Integer(usize),
+    String(String),
+}
+
+impl Value {
+    fn into_bool(self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn into_integer(self) -> Option<usize> {
+        match self {
+            Value::Integer(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn into_year(self) -> Option<i32> {
+        self.into_integer().and_then(|x| i32::try_from(x).ok())
+    }
+
+    fn into_string(self) -> Option<String> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a value with an optional trailing comment
+fn parse_value(value: &str) -> Option<Value> {
+    let (result, rest) = parse_value_prefix(value)?;
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+/// Returns a value at the start of a string and the rest of the string
+fn parse_value_prefix(value: &str) -> Option<(Value, &str)> {
+    let value = value.trim_start();
+    if let Some(rest) = value.strip_prefix("true") {
+        return Some((Value::Bool(true), rest));
+    }
+    if let Some(rest) = value.strip_prefix("false") {
+        return Some((Value::Bool(false), rest));
+    }
+    if let Some(rest) = value.strip_prefix('\'') {
+        let end = rest.find('\'')?;
+        return Some((Value::String(rest[..end].to_owned()), &rest[end + 1..]));
+    }
+    if let Some(rest) = value.strip_prefix('"') {
+        return parse_basic_string(rest);
+    }
+    if value.starts_with(|x: char| x.is_ascii_digit()) {
+        let end = value.find(|x: char| !x.is_ascii_digit()).unwrap_or(value.len());
+        return Some((Value::Integer(value[..end].parse().ok()?), &value[end..]));
+    }
+    let mut rest = value.strip_prefix('[')?;
+    let mut items = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if let Some(rest) = rest.strip_prefix(']') {
+            return Some((Value::Array(items), rest));
+        }
+        let (item, item_rest) = parse_value_prefix(rest)?;
+        items.push(item);
+        rest = item_rest.trim_start();
+        match rest.strip_prefix(',') {
+            Some(item_rest) => rest = item_rest,
+            None if rest.starts_with(']') => {}
+            None => return None,
+        }
+    }
+}
+
+/// Parses a string after an opening quote, handles `\"`, `\\`, `\n` and `\t` escapes
+fn parse_basic_string(value: &str) -> Option<(Value, &str)> {
+    let mut result = String::new();
+    let mut chars = value.char_indices();
+    while let Some((pos, x)) = chars.next() {
+        match x {
+            '"' => return Some((Value::String(result), &value[pos + 1..])),
+            '\\' => match chars.next()?.1 {
+                '"' => result.push('"'),
+                '\\' => result.push('\\'),
+                'n' => result.push('\n'),
+                't' => result.push('\t'),
+                _ => return None,
+            },
+            x => result.push(x),
+        }
+    }
+    None
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Parse(PathBuf, ConfigParseError),
+    Read(PathBuf, IoError),
+}
+
+impl StdError for ConfigError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::ConfigError::*;
+        match self {
+            Parse(_, err) => Some(err),
+            Read(_, err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::ConfigError::*;
+        match self {
+            Parse(path, err) => write!(out, "failed to parse {}: {}", path.display(), err),
+            Read(path, err) => write!(out, "failed to read {}: {}", path.display(), err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigParseError {
+    InvalidValue(String),
+    Syntax(usize),
+    UnknownKey(String),
+}
+
+impl StdError for ConfigParseError {}
+
+impl fmt::Display for ConfigParseError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::ConfigParseError::*;
+        match self {
+            InvalidValue(key) => write!(out, "invalid value of {}", key),
+            Syntax(line) => write!(out, "syntax error at line {}", line),
+            UnknownKey(key) => write!(out, "unknown key: {}", key),
+        }
+    }
+}
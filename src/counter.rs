@@ -1,32 +1,108 @@
-use std::{collections::HashMap, hash::Hash};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::HashMap,
+    hash::Hash,
+};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Counter<K, V> {
-    items: HashMap<K, HashMap<V, u32>>,
+    /// Counts of original values grouped by a normalized value
+    items: HashMap<K, HashMap<V, HashMap<V, u32>>>,
+    normalize: Option<fn(&V) -> V>,
 }
 
 impl<K, V> Default for Counter<K, V> {
     fn default() -> Self {
         Self {
             items: Default::default(),
+            normalize: None,
         }
     }
 }
 
-impl<K: Eq + Hash, V: Clone + Eq + Hash> Counter<K, V> {
+impl<K, V> Counter<K, V> {
+    /// Creates a counter which treats values with the same normalized form as equal
+    ///
+    /// Values are still returned as they were inserted, the most common spelling of a group is used.
+    pub fn with_normalizer(normalize: fn(&V) -> V) -> Self {
+        Self {
+            items: Default::default(),
+            normalize: Some(normalize),
+        }
+    }
+}
+
+impl<K: Eq + Hash, V: Clone + Eq + Hash + Ord> Counter<K, V> {
     pub fn insert(&mut self, key: K, value: V) {
-        let key_map = self.items.entry(key).or_insert_with(HashMap::new);
-        *key_map.entry(value).or_insert(0) += 1;
+        let group = match self.normalize {
+            Some(normalize) => normalize(&value),
+            None => value.clone(),
+        };
+        let key_map = self.items.entry(key).or_default();
+        *key_map.entry(group).or_default().entry(value).or_insert(0) += 1;
     }
 
+    /// Returns the most common value, a tie is won by the smallest value
     pub fn most_common(&self, key: K) -> Option<V> {
+        self.sorted(key).into_iter().next().map(|(value, _count)| value)
+    }
+
+    /// Returns up to `n` most common values with counts, sorted by count descending
+    pub fn most_common_n(&self, key: K, n: usize) -> Vec<(V, u32)> {
+        let mut values = self.sorted(key);
+        values.truncate(n);
+        values
+    }
+
+    /// Returns all values seen for a key with counts, the most common first
+    pub fn counts(&self, key: K) -> Vec<(V, u32)> {
+        self.sorted(key)
+    }
+
+    /// Returns all values seen for a key, the most common first
+    pub fn values(&self, key: K) -> Vec<V> {
+        self.sorted(key).into_iter().map(|(value, _count)| value).collect()
+    }
+
+    /// Returns values with counts, ordered by count descending and then by value,
+    /// so the order never depends on a hash map iteration
+    fn sorted(&self, key: K) -> Vec<(V, u32)> {
         match self.items.get(&key) {
             Some(key_map) => {
-                let mut values: Vec<(V, u32)> = key_map.iter().map(|(value, count)| (value.clone(), *count)).collect();
-                values.sort_by_key(|&(_, count)| count);
-                values.last().map(|(value, _count)| value.clone())
+                let mut values: Vec<(V, u32)> = key_map
+                    .values()
+                    .filter_map(|spellings| {
+                        let total = spellings.values().sum();
+                        sort_counts(spellings)
+                            .into_iter()
+                            .next()
+                            .map(|(value, _count)| (value, total))
+                    })
+                    .collect();
+                values.sort_by(compare_counts);
+                values
             }
-            None => None,
+            None => Vec::new(),
         }
     }
 }
+
+fn sort_counts<V: Clone + Ord>(items: &HashMap<V, u32>) -> Vec<(V, u32)> {
+    let mut values: Vec<(V, u32)> = items.iter().map(|(value, count)| (value.clone(), *count)).collect();
+    values.sort_by(compare_counts);
+    values
+}
+
+fn compare_counts<V: Ord>((a, a_count): &(V, u32), (b, b_count): &(V, u32)) -> Ordering {
+    Reverse(a_count).cmp(&Reverse(b_count)).then(a.cmp(b))
+}
+
+/// Returns a text with trimmed and collapsed whitespace, lowercased if case is ignored
+pub fn normalize_text(value: &str, ignore_case: bool) -> String {
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    if ignore_case {
+        value.to_lowercase()
+    } else {
+        value
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    error::Error as StdError,
+    fmt, fs,
+    io::Error as IoError,
+    path::{Path, PathBuf},
+};
+
+/// File names (without extension) of a cover, in order of preference
+const NAMES: [&str; 3] = ["cover", "folder", "front"];
+
+const MIME_TYPES: [(&str, &str); 3] = [("jpg", "image/jpeg"), ("jpeg", "image/jpeg"), ("png", "image/png")];
+
+/// Picture type of a front cover (ID3v2 APIC and FLAC PICTURE)
+pub const PICTURE_TYPE_FRONT: u32 = 3;
+
+/// An image to embed into every file as a front cover
+#[derive(Debug)]
+pub struct Cover {
+    pub path: PathBuf,
+    pub mime_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+impl Cover {
+    /// Returns a cover image of an album directory, such as `cover.jpg`, `folder.jpg` or `front.png`
+    ///
+    /// File names are compared case-insensitively.
+    pub fn find(dir: impl AsRef<Path>) -> Result<Option<Self>, CoverError> {
+        let dir = dir.as_ref();
+        let mut candidates = Vec::new();
+        for entry in fs::read_dir(dir).map_err(|err| CoverError::ReadDir(dir.to_owned(), err))? {
+            let path = entry.map_err(|err| CoverError::ReadDir(dir.to_owned(), err))?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let name = path.file_stem().and_then(|x| x.to_str()).map(str::to_lowercase);
+            let extension = path.extension().and_then(|x| x.to_str()).map(str::to_lowercase);
+            if let (Some(name), Some(extension)) = (name, extension) {
+                let priority = NAMES.iter().position(|x| *x == name);
+                let mime_type = MIME_TYPES.iter().find(|(x, _)| *x == extension).map(|(_, x)| *x);
+                if let (Some(priority), Some(mime_type)) = (priority, mime_type) {
+                    candidates.push((priority, path, mime_type));
+                }
+            }
+        }
+        candidates.sort();
+        match candidates.into_iter().next() {
+            Some((_, path, mime_type)) => {
+                let data = fs::read(&path).map_err(|err| CoverError::ReadFile(path.clone(), err))?;
+                Ok(Some(Self { path, mime_type, data }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the cover as a FLAC PICTURE block, also used by `METADATA_BLOCK_PICTURE` comments
+    ///
+    /// Dimensions and color depth are left unspecified (zero).
+    pub fn to_picture_block(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(32 + self.mime_type.len() + self.data.len());
+        data.extend_from_slice(&PICTURE_TYPE_FRONT.to_be_bytes());
+        data.extend_from_slice(&(self.mime_type.len() as u32).to_be_bytes());
+        data.extend_from_slice(self.mime_type.as_bytes());
+        data.extend_from_slice(&0u32.to_be_bytes()); // description
+        data.extend_from_slice(&[0; 16]); // width, height, depth, colors
+        data.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        data.extend_from_slice(&self.data);
+        data
+    }
+}
+
+#[derive(Debug)]
+pub enum CoverError {
+    ReadDir(PathBuf, IoError),
+    ReadFile(PathBuf, IoError),
+}
+
+impl StdError for CoverError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::CoverError::*;
+        Some(match self {
+            ReadDir(_, err) => err,
+            ReadFile(_, err) => err,
+        })
+    }
+}
+
+impl fmt::Display for CoverError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::CoverError::*;
+        match self {
+            ReadDir(path, err) => write!(out, "failed to read directory {}: {}", path.display(), err),
+            ReadFile(path, err) => write!(out, "failed to read cover {}: {}", path.display(), err),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::{error::Error as StdError, fmt, mem};
+
+/// Returns a line of comma-separated values ending with CRLF
+///
+/// A value containing a comma, a quote or a line break is quoted, quotes are doubled.
+pub fn write_row<I, T>(values: I) -> String
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<str>,
+{
+    let values: Vec<String> = values.into_iter().map(|x| quote(x.as_ref())).collect();
+    let mut result = values.join(",");
+    result.push_str("\r\n");
+    result
+}
+
+fn quote(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Returns rows of comma-separated values, empty lines are skipped
+///
+/// Quoted values may contain commas, doubled quotes and line breaks.
+pub fn parse(value: &str) -> Result<Vec<Vec<String>>, CsvError> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut chars = value.chars().peekable();
+    // a field is started by a quote only at its beginning
+    let mut at_field_start = true;
+    while let Some(x) = chars.next() {
+        match x {
+            '"' if at_field_start => {
+                let start_line = line;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(x) => {
+                            if x == '\n' {
+                                line += 1;
+                            }
+                            field.push(x);
+                        }
+                        None => return Err(CsvError::UnclosedQuote(start_line)),
+                    }
+                }
+                match chars.peek() {
+                    None | Some(',') | Some('\r') | Some('\n') => {}
+                    Some(_) => return Err(CsvError::UnexpectedQuote(line)),
+                }
+                at_field_start = false;
+            }
+            '"' => return Err(CsvError::UnexpectedQuote(line)),
+            ',' => {
+                row.push(mem::take(&mut field));
+                at_field_start = true;
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                line += 1;
+                finish_row(&mut rows, &mut row, &mut field);
+                at_field_start = true;
+            }
+            x => {
+                field.push(x);
+                at_field_start = false;
+            }
+        }
+    }
+    finish_row(&mut rows, &mut row, &mut field);
+    Ok(rows)
+}
+
+fn finish_row(rows: &mut Vec<Vec<String>>, row: &mut Vec<String>, field: &mut String) {
+    row.push(mem::take(field));
+    let row = mem::take(row);
+    if row.len() > 1 || !row[0].is_empty() {
+        rows.push(row);
+    }
+}
+
+#[derive(Debug)]
+pub enum CsvError {
+    UnclosedQuote(usize),
+    UnexpectedQuote(usize),
+}
+
+impl StdError for CsvError {}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::CsvError::*;
+        match self {
+            UnclosedQuote(line) => write!(out, "quote opened at line {} is not closed", line),
+            UnexpectedQuote(line) => write!(out, "unexpected quote at line {}", line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_values() {
+        let rows = [
+            vec!["plain", "with, comma", "with \"quotes\"", ""],
+            vec!["line\r\nbreak", "new\nline", "\"", ","],
+        ];
+        let data: String = rows.iter().map(write_row).collect();
+        assert_eq!(
+            data,
+            "plain,\"with, comma\",\"with \"\"quotes\"\"\",\r\n\"line\r\nbreak\",\"new\nline\",\"\"\"\",\",\"\r\n"
+        );
+        assert_eq!(parse(&data).unwrap(), rows);
+    }
+
+    #[test]
+    fn parses_crlf_lines() {
+        assert_eq!(
+            parse("a,b\r\n\r\nc,d\r\n").unwrap(),
+            vec![vec!["a", "b"], vec!["c", "d"]]
+        );
+        assert_eq!(parse("a,b\nc,d").unwrap(), vec![vec!["a", "b"], vec!["c", "d"]]);
+    }
+
+    #[test]
+    fn reports_unclosed_quote() {
+        let err = parse("a,b\n\"c\nd,e\n").unwrap_err();
+        assert!(matches!(err, CsvError::UnclosedQuote(2)));
+        assert_eq!(err.to_string(), "quote opened at line 2 is not closed");
+    }
+
+    #[test]
+    fn reports_unexpected_quote() {
+        assert!(matches!(parse("a,b\nc\"d\n"), Err(CsvError::UnexpectedQuote(2))));
+        // a line break in a quoted value is counted
+        let err = parse("\"a\nb\"c,d\n").unwrap_err();
+        assert!(matches!(err, CsvError::UnexpectedQuote(2)));
+        assert_eq!(err.to_string(), "unexpected quote at line 2");
+    }
+}
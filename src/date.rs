@@ -0,0 +1,144 @@
+use std::{
+    error::Error as StdError,
+    fmt,
+    num::ParseIntError,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A recording date, either a bare year or a year with month and day
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Date {
+    pub year: i32,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+}
+
+impl Date {
+    pub fn from_year(year: i32) -> Self {
+        Self {
+            year,
+            month: None,
+            day: None,
+        }
+    }
+
+    /// Parses a date stored in a tag
+    ///
+    /// Tags may contain a time (`2001-05-03T10:00:00`) or garbage after a year,
+    /// so the most precise valid prefix is taken.
+    pub fn from_tag(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let date = value.split(['T', ' ']).next().unwrap_or(value);
+        date.parse().ok().or_else(|| {
+            let year = date.split('-').next().unwrap_or(date);
+            year.parse().ok().map(Self::from_year)
+        })
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{:04}", self.year)?;
+        if let Some(month) = self.month {
+            write!(out, "-{:02}", month)?;
+            if let Some(day) = self.day {
+                write!(out, "-{:02}", day)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Accepts `YYYY`, `YYYY-MM` and `YYYY-MM-DD`
+impl FromStr for Date {
+    type Err = DateError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = value.trim().splitn(3, '-');
+        let year = parts.next().unwrap_or_default().parse().map_err(DateError::Year)?;
+        let month = match parts.next() {
+            Some(month) => match month.parse().map_err(DateError::Month)? {
+                month @ 1..=12 => Some(month),
+                month => return Err(DateError::MonthOutOfRange(month)),
+            },
+            None => None,
+        };
+        let day = match parts.next() {
+            Some(day) => {
+                let day = day.parse().map_err(DateError::Day)?;
+                if day < 1 || day > days_in_month(year, month.unwrap_or_default()) {
+                    return Err(DateError::DayOutOfRange(day));
+                }
+                Some(day)
+            }
+            None => None,
+        };
+        Ok(Self { year, month, day })
+    }
+}
+
+/// Returns a year of the system clock (UTC)
+pub fn current_year() -> i32 {
+    let mut days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs() / 86400)
+        .unwrap_or_default();
+    let mut year = 1970;
+    loop {
+        let year_days = if is_leap_year(year) { 366 } else { 365 };
+        if days < year_days {
+            return year;
+        }
+        days -= year_days;
+        year += 1;
+    }
+}
+
+fn is_leap_year(year: i32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[derive(Debug)]
+pub enum DateError {
+    Day(ParseIntError),
+    DayOutOfRange(u8),
+    Month(ParseIntError),
+    MonthOutOfRange(u8),
+    Year(ParseIntError),
+}
+
+impl StdError for DateError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::DateError::*;
+        match self {
+            Day(err) => Some(err),
+            DayOutOfRange(_) => None,
+            Month(err) => Some(err),
+            MonthOutOfRange(_) => None,
+            Year(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for DateError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::DateError::*;
+        match self {
+            Day(err) => write!(out, "invalid day: {}", err),
+            DayOutOfRange(day) => write!(out, "day {} is out of range", day),
+            Month(err) => write!(out, "invalid month: {}", err),
+            MonthOutOfRange(month) => write!(out, "month {} is out of range", month),
+            Year(err) => write!(out, "invalid year: {}", err),
+        }
+    }
+}
@@ -1,13 +1,272 @@
-use rustyline::{error::ReadlineError, Editor as BaseEditor};
-use std::{error::Error as StdError, fmt};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter, validate::Validator, Context,
+    Editor as BaseEditor, Helper, Result as ReadlineResult,
+};
+use std::{
+    collections::VecDeque,
+    env,
+    error::Error as StdError,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
 
-const COMMAND_BACK: &str = ":b";
-const COMMAND_QUIT: &str = ":q";
+/// Environment variable overriding a path of the history file
+const HISTORY_ENV: &str = "TAGG_HISTORY";
 
-pub struct Editor<S, O> {
-    base: BaseEditor<()>,
+/// Commands available in every prompt, used both to parse input and to print help
+const COMMANDS: [Command; 9] = [
+    Command {
+        kind: CommandKind::All,
+        names: &[":a", ":all"],
+        description: "accept defaults of this and the following fields",
+    },
+    Command {
+        kind: CommandKind::Back,
+        names: &[":b", ":back"],
+        description: "go back to the previous field",
+    },
+    Command {
+        kind: CommandKind::Clear,
+        names: &[":c", ":clear"],
+        description: "set an empty value",
+    },
+    Command {
+        kind: CommandKind::Goto,
+        names: &[":g", ":go"],
+        description: "go to a field by its name, e.g. \":g title\"",
+    },
+    Command {
+        kind: CommandKind::Previous,
+        names: &[":p", ":previous"],
+        description: "use a value of the previous track",
+    },
+    Command {
+        kind: CommandKind::Reset,
+        names: &[":r", ":reset"],
+        description: "reset this field to its default and ask again",
+    },
+    Command {
+        kind: CommandKind::Skip,
+        names: &[":s", ":skip"],
+        description: "leave an optional field unset",
+    },
+    Command {
+        kind: CommandKind::Help,
+        names: &[":h", ":help"],
+        description: "show this help",
+    },
+    Command {
+        kind: CommandKind::Quit,
+        names: &[":q", ":quit"],
+        description: "quit without writing anything",
+    },
+];
+
+struct Command {
+    kind: CommandKind,
+    names: &'static [&'static str],
+    description: &'static str,
+}
+
+#[derive(Clone, Copy)]
+enum CommandKind {
+    All,
+    Back,
+    Clear,
+    Goto,
+    Help,
+    Previous,
+    Quit,
+    Reset,
+    Skip,
+}
+
+fn print_help() {
+    for command in &COMMANDS {
+        println!("{:<12}{}", command.names.join(", "), command.description);
+    }
+}
+
+/// Returns whether a name typed by a user names a field of a key
+///
+/// Case, spaces, underscores and a hint in parentheses are ignored, so `album_artist` names `ALBUM ARTIST`
+/// and `rating` names `RATING (1-5)`.
+fn is_field_name(key: &impl Prompt, name: &str) -> bool {
+    let normalize = |value: &str| {
+        value
+            .chars()
+            .filter(|x| !x.is_whitespace() && *x != '_' && *x != '-')
+            .flat_map(char::to_lowercase)
+            .collect::<String>()
+    };
+    let prompt = key.get_prompt();
+    let prompt = prompt.split(" (").next().unwrap_or(prompt);
+    normalize(prompt) == normalize(name)
+}
+
+/// A source of lines typed by a user
+///
+/// Implemented for a terminal editor, other implementations can feed scripted input.
+pub trait Reader {
+    /// Reads a line, `initial` is a text put around the cursor (left and right parts)
+    ///
+    /// `ReadlineError::Interrupted` and `ReadlineError::Eof` are treated as a request to quit.
+    fn read_line(&mut self, prompt: &str, initial: (&str, &str)) -> Result<String, ReadlineError>;
+
+    /// Reads an answer to a question, answers are not kept in a history
+    fn read_answer(&mut self, prompt: &str) -> Result<String, ReadlineError> {
+        self.read_line(prompt, ("", ""))
+    }
+
+    /// Sets values offered for completion in the following prompt
+    fn set_candidates(&mut self, candidates: Vec<String>) {
+        let _ = candidates;
+    }
+}
+
+impl<H: Helper> Reader for BaseEditor<H> {
+    fn read_line(&mut self, prompt: &str, initial: (&str, &str)) -> Result<String, ReadlineError> {
+        self.readline_with_initial(prompt, initial)
+    }
+}
+
+/// Completes a line with values seen in files, Tab cycles through them
+#[derive(Debug, Default)]
+struct Candidates {
+    values: Vec<String>,
+}
+
+impl Completer for Candidates {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> ReadlineResult<(usize, Vec<String>)> {
+        let prefix = line[..pos].to_lowercase();
+        let values = self
+            .values
+            .iter()
+            .filter(|x| x.to_lowercase().starts_with(&prefix))
+            .cloned()
+            .collect();
+        Ok((0, values))
+    }
+}
+
+impl Hinter for Candidates {
+    type Hint = String;
+}
+
+impl Highlighter for Candidates {}
+
+impl Validator for Candidates {}
+
+impl Helper for Candidates {}
+
+/// Reads lines from a terminal and keeps a history of typed lines in a file
+///
+/// The history file is written once, when the terminal is dropped.
+pub struct Terminal {
+    editor: BaseEditor<Candidates>,
+    history_path: Option<PathBuf>,
+    /// Whether lines were added to the history since it was loaded
+    is_history_changed: bool,
+}
+
+impl Terminal {
+    /// Creates a terminal with a history loaded from a file, if any
+    ///
+    /// History is optional, so a missing or unreadable file just gives an empty history.
+    pub fn new(history_path: Option<PathBuf>) -> Self {
+        let mut editor = BaseEditor::new();
+        editor.set_helper(Some(Candidates::default()));
+        if let Some(ref path) = history_path {
+            let _ = editor.load_history(path);
+        }
+        Self {
+            editor,
+            history_path,
+            is_history_changed: false,
+        }
+    }
+
+    /// Returns `$TAGG_HISTORY` or `history` in a data directory of the user (e.g. `~/.local/share/tagg/history`)
+    pub fn default_history_path() -> Option<PathBuf> {
+        env::var_os(HISTORY_ENV)
+            .map(PathBuf::from)
+            .or_else(|| dirs::data_dir().map(|x| x.join("tagg").join("history")))
+    }
+
+    fn save_history(&mut self, path: &Path) -> Result<(), ReadlineError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.editor.save_history(path)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if let (Some(path), true) = (self.history_path.take(), self.is_history_changed) {
+            // failing to save history must not stop editing
+            let _ = self.save_history(&path);
+        }
+    }
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Self::new(Self::default_history_path())
+    }
+}
+
+impl Reader for Terminal {
+    fn read_line(&mut self, prompt: &str, initial: (&str, &str)) -> Result<String, ReadlineError> {
+        let line = self.editor.readline_with_initial(prompt, initial)?;
+        if !line.trim().is_empty() && self.editor.add_history_entry(line.as_str()) {
+            self.is_history_changed = true;
+        }
+        Ok(line)
+    }
+
+    fn read_answer(&mut self, prompt: &str) -> Result<String, ReadlineError> {
+        self.editor.readline(prompt)
+    }
+
+    fn set_candidates(&mut self, candidates: Vec<String>) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.values = candidates;
+        }
+    }
+}
+
+/// Returns prepared lines one by one, then reports the end of input
+#[derive(Debug, Default)]
+pub struct ScriptedReader {
+    lines: VecDeque<String>,
+}
+
+impl ScriptedReader {
+    pub fn new<I, T>(lines: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Self {
+            lines: lines.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Reader for ScriptedReader {
+    fn read_line(&mut self, _prompt: &str, _initial: (&str, &str)) -> Result<String, ReadlineError> {
+        self.lines.pop_front().ok_or(ReadlineError::Eof)
+    }
+}
+
+pub struct Editor<S, O, R = Terminal> {
+    reader: R,
     state: S,
     output_builder: O,
+    label: Option<String>,
 }
 
 impl<S, O> Editor<S, O>
@@ -17,44 +276,180 @@ where
     S: State<O::Key>,
 {
     pub fn new(state: S, output_builder: O) -> Self {
+        Self::with_reader(state, output_builder, Terminal::default())
+    }
+}
+
+impl<S, O, R> Editor<S, O, R>
+where
+    O: OutputBuilder,
+    O::Key: Prompt,
+    S: State<O::Key>,
+    R: Reader,
+{
+    pub fn with_reader(state: S, output_builder: O, reader: R) -> Self {
         Self {
-            base: BaseEditor::new(),
+            reader,
             state,
             output_builder,
+            label: None,
         }
     }
 
+    /// Sets a text shown before every prompt, e.g. `[3/12]`
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Returns a state to adjust it before running
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
     fn read(&mut self, prompt: impl fmt::Display, default_value: DefaultValue) -> Result<ReadlineInput, EditorError> {
-        let prompt = format!("[{}] >>> ", prompt);
+        let prompt = match self.label {
+            Some(ref label) => format!("{} [{}] >>> ", label, prompt),
+            None => format!("[{}] >>> ", prompt),
+        };
         let initial = (default_value.left.as_str(), default_value.right.as_str());
-        match self.base.readline_with_initial(&prompt, initial) {
-            Ok(value) => Ok(match value.trim() {
-                COMMAND_BACK => ReadlineInput::Back,
-                COMMAND_QUIT => ReadlineInput::Exit,
-                value => ReadlineInput::Data(value.to_string()),
-            }),
+        match self.reader.read_line(&prompt, initial) {
+            Ok(value) => {
+                let value = value.trim();
+                let (name, argument) = match value.split_once(char::is_whitespace) {
+                    Some((name, argument)) if name.starts_with(':') => (name, argument.trim()),
+                    _ => (value, ""),
+                };
+                let command = COMMANDS.iter().find(|x| x.names.contains(&name));
+                Ok(match command.map(|x| x.kind) {
+                    Some(CommandKind::All) => ReadlineInput::All,
+                    Some(CommandKind::Back) => ReadlineInput::Back,
+                    // an empty value, without erasing a pre-filled default by hand
+                    Some(CommandKind::Clear) => ReadlineInput::Data(String::new()),
+                    Some(CommandKind::Goto) => ReadlineInput::Goto(argument.to_owned()),
+                    Some(CommandKind::Help) => ReadlineInput::Help,
+                    Some(CommandKind::Previous) => ReadlineInput::Previous,
+                    Some(CommandKind::Quit) => ReadlineInput::Exit,
+                    Some(CommandKind::Reset) => ReadlineInput::Reset,
+                    Some(CommandKind::Skip) => ReadlineInput::Skip,
+                    None => ReadlineInput::Data(value.to_string()),
+                })
+            }
             Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => Ok(ReadlineInput::Exit),
             Err(err) => Err(EditorError::Readline(err)),
         }
     }
 
+    /// Asks whether to quit, an interrupt or the end of input is a yes
+    fn confirm_quit(&mut self) -> Result<bool, EditorError> {
+        loop {
+            match self.reader.read_answer("Are you sure? [y/n] ") {
+                Ok(answer) => match answer.trim().to_lowercase().as_str() {
+                    "y" | "yes" => return Ok(true),
+                    "n" | "no" => return Ok(false),
+                    _ => println!("Wrong answer!"),
+                },
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => return Ok(true),
+                Err(err) => return Err(EditorError::Readline(err)),
+            }
+        }
+    }
+
+    /// Sets a value of a field back to its default, the field is not left
+    ///
+    /// A value typed earlier is dropped, so leaving the field with `:b` or `:g` keeps the default.
+    fn reset(&mut self, key: O::Key) {
+        if let StateInput::Read { default_value, .. } = self.state.get_input() {
+            if default_value.left.is_empty() {
+                // a required field without a default has nothing to go back to
+                let _ = self.output_builder.unset_value(key);
+            } else if let Err(err) = self.output_builder.set_value(key, default_value.left) {
+                println!("{}", err);
+            }
+        }
+    }
+
+    /// Sets default values until a field without a default or an invalid default is found
+    fn accept_defaults(&mut self) {
+        while let StateInput::Read { key, default_value } = self.state.get_input() {
+            if default_value.left.is_empty() {
+                break;
+            }
+            if let Err(err) = self.output_builder.set_value(key, default_value.left) {
+                println!("{}", err);
+                break;
+            }
+            self.state.next();
+        }
+    }
+
     pub fn run(mut self) -> Result<EditorOutput<O::Output>, EditorError> {
         loop {
             match self.state.get_input() {
                 StateInput::Read { key, default_value } => {
+                    self.reader.set_candidates(self.state.candidates(&key));
+                    let choices = self.state.choices(&key);
+                    for (idx, (value, count)) in choices.iter().enumerate() {
+                        println!("{}) {} ({})", idx + 1, value, count);
+                    }
+                    let conflicts = self.state.conflicts(&key);
+                    if !conflicts.is_empty() {
+                        let conflicts: Vec<String> = conflicts
+                            .iter()
+                            .map(|(value, count)| format!("{} ({})", value, count))
+                            .collect();
+                        println!("Files disagree: {}", conflicts.join(", "));
+                    }
                     match self.read(key.get_prompt(), default_value)? {
                         ReadlineInput::Data(value) => {
+                            let value = pick_choice(choices, value);
                             if let Err(err) = self.output_builder.set_value(key, value) {
                                 println!("{}", err);
                             } else {
                                 self.state.next();
                             }
                         }
+                        ReadlineInput::All => {
+                            self.accept_defaults();
+                        }
+                        ReadlineInput::Skip => {
+                            if let Err(err) = self.output_builder.unset_value(key) {
+                                println!("{}", err);
+                            } else {
+                                self.state.next();
+                            }
+                        }
+                        ReadlineInput::Previous => match self.state.previous(&key) {
+                            Some(value) => {
+                                if let Err(err) = self.output_builder.set_value(key, value) {
+                                    println!("{}", err);
+                                } else {
+                                    self.state.next();
+                                }
+                            }
+                            None => println!("There is no previous value"),
+                        },
+                        ReadlineInput::Reset => {
+                            self.reset(key);
+                        }
                         ReadlineInput::Back => {
                             self.state.prev();
                         }
+                        ReadlineInput::Goto(name) => {
+                            match self.state.keys().into_iter().find(|x| is_field_name(x, &name)) {
+                                Some(key) => self.state.goto(&key),
+                                None if name.is_empty() => println!("A name of a field is required"),
+                                None => println!("There is no field {}", name),
+                            }
+                        }
+                        ReadlineInput::Help => {
+                            print_help();
+                        }
                         ReadlineInput::Exit => {
                             self.state.interrupt();
+                            if !self.confirm_quit()? {
+                                self.state.resume();
+                            }
                         }
                     };
                 }
@@ -73,10 +468,27 @@ where
     }
 }
 
+/// Returns a choice if a value is its number, otherwise the value itself
+fn pick_choice(choices: Vec<(String, u32)>, value: String) -> String {
+    let idx = value.parse::<usize>().ok().and_then(|x| x.checked_sub(1));
+    match idx.and_then(|x| choices.into_iter().nth(x)) {
+        Some((choice, _count)) => choice,
+        None => value,
+    }
+}
+
 #[derive(Debug)]
 enum ReadlineInput {
     Data(String),
+    All,
+    Skip,
+    Previous,
+    /// Reset a field to its default
+    Reset,
     Back,
+    /// Go to a field by its name
+    Goto(String),
+    Help,
     Exit,
 }
 
@@ -91,7 +503,42 @@ pub trait State<K: Prompt> {
     fn next(&mut self);
     fn prev(&mut self);
     fn interrupt(&mut self);
-    fn finish(&mut self);
+    /// Returns from an interrupt to the field which was active before it
+    fn resume(&mut self);
+
+    /// Returns keys of all fields in order, a field to go to is looked up among them
+    fn keys(&self) -> Vec<K> {
+        Vec::new()
+    }
+
+    /// Goes to a field of a key
+    fn goto(&mut self, key: &K) {
+        let _ = key;
+    }
+
+    /// Returns values offered for completion of a key
+    fn candidates(&self, key: &K) -> Vec<String> {
+        let _ = key;
+        Vec::new()
+    }
+
+    /// Returns a value of a key entered in a previous editor, if any
+    fn previous(&self, key: &K) -> Option<String> {
+        let _ = key;
+        None
+    }
+
+    /// Returns values with counts offered as numbered choices, a typed number picks a value
+    fn choices(&self, key: &K) -> Vec<(String, u32)> {
+        let _ = key;
+        Vec::new()
+    }
+
+    /// Returns conflicting values of files with counts, shown before a prompt of a field without choices
+    fn conflicts(&self, key: &K) -> Vec<(String, u32)> {
+        let _ = key;
+        Vec::new()
+    }
 }
 
 pub enum StateInput<K: Prompt> {
@@ -156,6 +603,8 @@ pub trait OutputBuilder {
     type OutputError: StdError + 'static;
 
     fn set_value(&mut self, key: Self::Key, value: String) -> Result<(), Self::InputError>;
+    /// Leaves a value unset, fails for a required value
+    fn unset_value(&mut self, key: Self::Key) -> Result<(), Self::OutputError>;
     fn build(self) -> Result<Self::Output, Self::OutputError>;
 }
 
@@ -182,3 +631,176 @@ impl fmt::Display for EditorError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum TestKey {
+        Name,
+        Note,
+    }
+
+    impl Prompt for TestKey {
+        fn get_prompt(&self) -> &str {
+            match self {
+                TestKey::Name => "NAME",
+                TestKey::Note => "NOTE",
+            }
+        }
+    }
+
+    /// Goes through a required name with a default and an optional note
+    struct TestState {
+        position: usize,
+        resume_position: usize,
+    }
+
+    const INTERRUPTED: usize = 3;
+
+    impl State<TestKey> for TestState {
+        fn get_input(&self) -> StateInput<TestKey> {
+            match self.position {
+                0 => StateInput::Read {
+                    key: TestKey::Name,
+                    default_value: DefaultValue::left("default"),
+                },
+                1 => StateInput::Read {
+                    key: TestKey::Note,
+                    default_value: DefaultValue::right(""),
+                },
+                INTERRUPTED => StateInput::Interrupted,
+                _ => StateInput::Finished,
+            }
+        }
+
+        fn next(&mut self) {
+            self.position += 1;
+        }
+
+        fn prev(&mut self) {
+            self.position = self.position.saturating_sub(1);
+        }
+
+        fn interrupt(&mut self) {
+            self.resume_position = self.position;
+            self.position = INTERRUPTED;
+        }
+
+        fn resume(&mut self) {
+            self.position = self.resume_position;
+        }
+
+        fn keys(&self) -> Vec<TestKey> {
+            vec![TestKey::Name, TestKey::Note]
+        }
+
+        fn goto(&mut self, key: &TestKey) {
+            self.position = if *key == TestKey::Name { 0 } else { 1 };
+        }
+    }
+
+    #[derive(Default)]
+    struct TestOutputBuilder {
+        name: Option<String>,
+        note: Option<String>,
+    }
+
+    #[derive(Debug)]
+    struct TestError;
+
+    impl StdError for TestError {}
+
+    impl fmt::Display for TestError {
+        fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+            write!(out, "test error")
+        }
+    }
+
+    impl OutputBuilder for TestOutputBuilder {
+        type Key = TestKey;
+        type Output = (String, Option<String>);
+        type InputError = TestError;
+        type OutputError = TestError;
+
+        fn set_value(&mut self, key: TestKey, value: String) -> Result<(), TestError> {
+            match key {
+                TestKey::Name if value.is_empty() => return Err(TestError),
+                TestKey::Name => self.name = Some(value),
+                TestKey::Note => self.note = Some(value),
+            }
+            Ok(())
+        }
+
+        fn unset_value(&mut self, key: TestKey) -> Result<(), TestError> {
+            match key {
+                TestKey::Name => return Err(TestError),
+                TestKey::Note => self.note = None,
+            }
+            Ok(())
+        }
+
+        fn build(self) -> Result<Self::Output, TestError> {
+            Ok((self.name.ok_or(TestError)?, self.note))
+        }
+    }
+
+    fn run(lines: &[&str]) -> EditorOutput<(String, Option<String>)> {
+        let state = TestState {
+            position: 0,
+            resume_position: 0,
+        };
+        let reader = ScriptedReader::new(lines.iter().copied());
+        Editor::with_reader(state, TestOutputBuilder::default(), reader)
+            .run()
+            .unwrap()
+    }
+
+    fn finished(name: &str, note: Option<&str>) -> (String, Option<String>) {
+        (String::from(name), note.map(String::from))
+    }
+
+    #[test]
+    fn scripted_reader_ends_input() {
+        let mut reader = ScriptedReader::new(vec!["a", "b"]);
+        assert_eq!(reader.read_line("", ("", "")).unwrap(), "a");
+        assert_eq!(reader.read_line("", ("", "")).unwrap(), "b");
+        assert!(matches!(reader.read_line("", ("", "")), Err(ReadlineError::Eof)));
+    }
+
+    #[test]
+    fn reads_values() {
+        match run(&["  name ", "note"]) {
+            EditorOutput::Finished(output) => assert_eq!(output, finished("name", Some("note"))),
+            output => panic!("unexpected output: {:?}", output),
+        }
+    }
+
+    #[test]
+    fn runs_commands() {
+        match run(&[":a", ":s"]) {
+            EditorOutput::Finished(output) => assert_eq!(output, finished("default", None)),
+            output => panic!("unexpected output: {:?}", output),
+        }
+        match run(&["name", ":b", ":r", ":g note", ":c"]) {
+            EditorOutput::Finished(output) => assert_eq!(output, finished("default", Some(""))),
+            output => panic!("unexpected output: {:?}", output),
+        }
+        // a required value is asked again
+        match run(&[":s", ":c", "name", "note"]) {
+            EditorOutput::Finished(output) => assert_eq!(output, finished("name", Some("note"))),
+            output => panic!("unexpected output: {:?}", output),
+        }
+    }
+
+    #[test]
+    fn quits() {
+        assert!(matches!(run(&["name", ":q", "y"]), EditorOutput::Interrupted));
+        assert!(matches!(run(&["name"]), EditorOutput::Interrupted));
+        match run(&["name", ":q", "n", "note"]) {
+            EditorOutput::Finished(output) => assert_eq!(output, finished("name", Some("note"))),
+            output => panic!("unexpected output: {:?}", output),
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Export of tags read from files and import of prepared tags,
+//! so they can be inspected or edited by other programs
+
+use crate::{
+    album::{AlbumInput, AlbumInputError, AlbumOutputError},
+    csv::{self, CsvError},
+    file::{FileInput, FileOutput},
+    finder::Format,
+    json::{Json, JsonError},
+    track::{parse_isrc, parse_rating, TrackInput, TrackInputError, TrackOutputError},
+};
+use std::{error::Error as StdError, fmt, path::PathBuf, str::FromStr, time::Duration};
+
+/// Names of exported fields, also keys of JSON objects and a header of CSV
+const FIELDS: [&str; 22] = [
+    "path",
+    "format",
+    "artist",
+    "album_artist",
+    "album",
+    "date",
+    "genre",
+    "grouping",
+    "title",
+    "subtitle",
+    "composer",
+    "comment",
+    "lyrics",
+    "bpm",
+    "rating",
+    "isrc",
+    "track_number",
+    "total_tracks",
+    "disc_number",
+    "total_discs",
+    "disc_subtitle",
+    "duration",
+];
+
+/// Returns tags of files as a JSON array of objects, missing tags are `null`
+pub fn to_json(items: &[FileInput]) -> Json {
+    Json::Array(items.iter().map(file_input_to_json).collect())
+}
+
+/// Returns tags of a file as a JSON object, missing tags are `null`
+pub fn file_input_to_json(item: &FileInput) -> Json {
+    let number = |x: Option<u32>| Json::from(x);
+    let text = |x: Option<String>| Json::from(x.as_deref());
+    let values = vec![
+        Json::from(item.path.to_string_lossy().as_ref()),
+        Json::from(item.format.extension()),
+        text(item.artist.clone()),
+        text(item.album_artist.clone()),
+        text(item.album.clone()),
+        text(item.date.map(|x| x.to_string())),
+        text(item.genre.clone()),
+        text(item.grouping.clone()),
+        text(item.title.clone()),
+        text(item.subtitle.clone()),
+        text(item.composer.clone()),
+        text(item.comment.clone()),
+        text(item.lyrics.clone()),
+        number(item.bpm),
+        number(item.rating.map(u32::from)),
+        text(item.isrc.clone()),
+        number(item.track_number),
+        number(item.total_tracks),
+        number(item.disc_number),
+        number(item.total_discs),
+        text(item.disc_subtitle.clone()),
+        item.duration
+            .map(|x| Json::Number(duration_secs(x)))
+            .unwrap_or(Json::Null),
+    ];
+    Json::Object(FIELDS.iter().map(|x| x.to_string()).zip(values).collect())
+}
+
+/// Returns tags of files as CSV with a header, missing tags are empty
+pub fn to_csv(items: &[FileInput]) -> String {
+    let mut result = csv::write_row(FIELDS);
+    for item in items {
+        let number = |x: Option<u32>| x.map(|x| x.to_string()).unwrap_or_default();
+        result.push_str(&csv::write_row(&[
+            item.path.to_string_lossy().into_owned(),
+            item.format.extension().to_owned(),
+            item.artist.clone().unwrap_or_default(),
+            item.album_artist.clone().unwrap_or_default(),
+            item.album.clone().unwrap_or_default(),
+            item.date.map(|x| x.to_string()).unwrap_or_default(),
+            item.genre.clone().unwrap_or_default(),
+            item.grouping.clone().unwrap_or_default(),
+            item.title.clone().unwrap_or_default(),
+            item.subtitle.clone().unwrap_or_default(),
+            item.composer.clone().unwrap_or_default(),
+            item.comment.clone().unwrap_or_default(),
+            item.lyrics.clone().unwrap_or_default(),
+            number(item.bpm),
+            number(item.rating.map(u32::from)),
+            item.isrc.clone().unwrap_or_default(),
+            number(item.track_number),
+            number(item.total_tracks),
+            number(item.disc_number),
+            number(item.total_discs),
+            item.disc_subtitle.clone().unwrap_or_default(),
+            item.duration.map(|x| duration_secs(x).to_string()).unwrap_or_default(),
+        ]));
+    }
+    result
+}
+
+/// Returns outputs of a JSON array of objects with the same keys as an export
+///
+/// Fields are validated like in editors, records failing validation are reported together.
+/// A duration is read from audio, so it is ignored.
+pub fn from_json(value: &str) -> Result<Vec<FileOutput>, ImportError> {
+    let records = match value.parse().map_err(ImportError::Json)? {
+        Json::Array(records) => records,
+        _ => return Err(ImportError::NotArray),
+    };
+    records_to_output(records.iter().map(|record| {
+        move |key: &str| match record.get(key) {
+            None | Some(Json::Null) => None,
+            Some(Json::String(value)) => Some(value.clone()),
+            Some(value) => Some(value.to_string()),
+        }
+    }))
+}
+
+/// Returns outputs of CSV with a header naming fields like an export, empty values are missing
+pub fn from_csv(value: &str) -> Result<Vec<FileOutput>, ImportError> {
+    let mut rows = csv::parse(value).map_err(ImportError::Csv)?.into_iter();
+    let header = rows.next().unwrap_or_default();
+    let header = &header;
+    records_to_output(rows.map(|row| {
+        move |key: &str| {
+            let column = header.iter().position(|x| x.trim() == key)?;
+            row.get(column).filter(|x| !x.is_empty()).cloned()
+        }
+    }))
+}
+
+/// Builds outputs of records, errors of all records are collected
+fn records_to_output<I, F>(records: I) -> Result<Vec<FileOutput>, ImportError>
+where
+    I: Iterator<Item = F>,
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = Vec::new();
+    let mut errors = Vec::new();
+    for (idx, get) in records.enumerate() {
+        match record_to_output(&get) {
+            Ok(output) => result.push(output),
+            Err(err) => errors.push(InvalidRecord {
+                record: idx + 1,
+                path: get("path").map(PathBuf::from),
+                error: err,
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(result)
+    } else {
+        Err(ImportError::InvalidRecords(errors))
+    }
+}
+
+/// Builds an output of a record, `get` returns a value of a field
+fn record_to_output<F>(get: F) -> Result<FileOutput, RecordError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let path = PathBuf::from(get("path").ok_or(RecordError::MissingPath)?);
+    let format = Format::from_path(&path).ok_or(RecordError::UnknownFormat)?;
+
+    let mut album_input = AlbumInput::default();
+    album_input.artist = get("artist");
+    album_input.album_artist = get("album_artist");
+    album_input.album = get("album");
+    album_input.date = parse(get("date"), AlbumInputError::Year).map_err(RecordError::AlbumValue)?;
+    album_input.genre = get("genre");
+    album_input.grouping = get("grouping");
+    album_input.total_tracks =
+        parse(get("total_tracks"), AlbumInputError::TotalTracks).map_err(RecordError::AlbumValue)?;
+    album_input.total_discs =
+        parse(get("total_discs"), AlbumInputError::TotalDiscs).map_err(RecordError::AlbumValue)?;
+    let album_output = album_input.into_output().map_err(RecordError::Album)?;
+
+    let track_input = TrackInput {
+        track_number: parse(get("track_number"), TrackInputError::TrackNumber).map_err(RecordError::TrackValue)?,
+        disc_number: parse(get("disc_number"), TrackInputError::DiscNumber).map_err(RecordError::TrackValue)?,
+        disc_subtitle: get("disc_subtitle"),
+        title: get("title"),
+        subtitle: get("subtitle"),
+        composer: get("composer"),
+        comment: get("comment"),
+        lyrics: get("lyrics"),
+        bpm: parse(get("bpm"), TrackInputError::Bpm).map_err(RecordError::TrackValue)?,
+        rating: get("rating")
+            .map(|x| parse_rating(&x).ok_or(TrackInputError::Rating(x)))
+            .transpose()
+            .map_err(RecordError::TrackValue)?,
+        isrc: get("isrc")
+            .map(|x| parse_isrc(&x).ok_or(TrackInputError::Isrc(x)))
+            .transpose()
+            .map_err(RecordError::TrackValue)?,
+    };
+    let track_output = track_input.into_output().map_err(RecordError::Track)?;
+
+    Ok(FileOutput::from((
+        FileInput::empty(path, format),
+        &album_output,
+        track_output,
+    )))
+}
+
+/// Returns a duration in seconds rounded to milliseconds
+fn duration_secs(duration: Duration) -> f64 {
+    duration.as_millis() as f64 / 1000.0
+}
+
+fn parse<T, E, F>(value: Option<String>, map_err: F) -> Result<Option<T>, E>
+where
+    T: FromStr,
+    F: Fn(T::Err) -> E,
+{
+    value.map(|x| x.trim().parse().map_err(map_err)).transpose()
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Csv(CsvError),
+    InvalidRecords(Vec<InvalidRecord>),
+    Json(JsonError),
+    NotArray,
+}
+
+impl StdError for ImportError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::ImportError::*;
+        match self {
+            Csv(err) => Some(err),
+            InvalidRecords(_) => None,
+            Json(err) => Some(err),
+            NotArray => None,
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::ImportError::*;
+        match self {
+            Csv(err) => write!(out, "failed to parse CSV: {}", err),
+            InvalidRecords(errors) => {
+                write!(out, "invalid records:")?;
+                for error in errors {
+                    write!(out, "\n    {}", error)?;
+                }
+                Ok(())
+            }
+            Json(err) => write!(out, "failed to parse JSON: {}", err),
+            NotArray => write!(out, "expected an array of records"),
+        }
+    }
+}
+
+/// A record failing validation, `record` starts from 1
+#[derive(Debug)]
+pub struct InvalidRecord {
+    pub record: usize,
+    pub path: Option<PathBuf>,
+    pub error: RecordError,
+}
+
+impl fmt::Display for InvalidRecord {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match self.path {
+            Some(ref path) => write!(out, "{}: {}", path.display(), self.error),
+            None => write!(out, "record {}: {}", self.record, self.error),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RecordError {
+    Album(AlbumOutputError),
+    AlbumValue(AlbumInputError),
+    MissingPath,
+    Track(TrackOutputError),
+    TrackValue(TrackInputError),
+    UnknownFormat,
+}
+
+impl StdError for RecordError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::RecordError::*;
+        match self {
+            Album(err) => Some(err),
+            AlbumValue(err) => Some(err),
+            MissingPath => None,
+            Track(err) => Some(err),
+            TrackValue(err) => Some(err),
+            UnknownFormat => None,
+        }
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::RecordError::*;
+        match self {
+            Album(err) => write!(out, "{}", err),
+            AlbumValue(err) => write!(out, "{}", err),
+            MissingPath => write!(out, "path is required"),
+            Track(err) => write!(out, "{}", err),
+            TrackValue(err) => write!(out, "{}", err),
+            UnknownFormat => write!(out, "unsupported file format"),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::str::FromStr;
+
+/// Words introducing featured artists, longer spellings go first
+const KEYWORDS: [&str; 5] = ["featuring", "feat.", "feat", "ft.", "ft"];
+
+/// Words introducing featured artists outside of brackets
+///
+/// Spellings without a dot are only accepted in brackets, so `A Heroic Feat` is left alone.
+const BARE_KEYWORDS: [&str; 3] = ["featuring", "feat.", "ft."];
+
+/// What to do with featured artists of a title
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FeaturedMode {
+    /// Move them to the artist field, e.g. `Song (ft. Guest)` by `Artist` becomes `Song` by `Artist; Guest`
+    Artist,
+    /// Keep them in the title written as `(feat. Guest)`
+    Title,
+}
+
+impl FromStr for FeaturedMode {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "artist" => Ok(FeaturedMode::Artist),
+            "title" => Ok(FeaturedMode::Title),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Parts of a title around featured artists
+#[derive(Debug, Eq, PartialEq)]
+struct Featured<'a> {
+    before: &'a str,
+    artists: &'a str,
+    after: &'a str,
+}
+
+/// Splits a title into a title without featured artists and featured artists
+///
+/// `Song (feat. Guest)`, `Song [Ft Guest]` and `Song featuring Guest (Remix)`
+/// give `Song` (`Song (Remix)`) and `Guest`, `None` is returned for a title without featured artists.
+pub fn split_featured(title: &str) -> Option<(String, String)> {
+    let featured = find_featured(title)?;
+    Some((join(featured.before, featured.after), featured.artists.to_owned()))
+}
+
+/// Rewrites featured artists of a title as `(feat. Guest)`, e.g. `Song FT. Guest - Live` becomes `Song (feat. Guest) - Live`
+///
+/// `None` is returned for a title without featured artists.
+pub fn normalize_featured(title: &str) -> Option<String> {
+    let featured = find_featured(title)?;
+    let before = format!("{} (feat. {})", featured.before, featured.artists);
+    Some(join(before.trim_start(), featured.after))
+}
+
+/// Appends featured artists to an artist, unless the artist already mentions them
+pub fn add_featured(artist: &str, featured: &str, separator: &str) -> String {
+    if artist.to_lowercase().contains(&featured.to_lowercase()) {
+        artist.to_owned()
+    } else if artist.is_empty() {
+        featured.to_owned()
+    } else {
+        format!("{}{} {}", artist, separator.trim_end(), featured)
+    }
+}
+
+fn find_featured(title: &str) -> Option<Featured<'_>> {
+    let word_starts = title
+        .char_indices()
+        .filter(|&(pos, _)| pos == 0 || title[..pos].ends_with(char::is_whitespace))
+        .map(|(pos, _)| pos)
+        .filter(|&pos| !title[pos..].starts_with(char::is_whitespace));
+    for (word_idx, start) in word_starts.enumerate() {
+        let word = &title[start..];
+        let (close, word) = match word.chars().next() {
+            Some('(') => (Some(')'), &word[1..]),
+            Some('[') => (Some(']'), &word[1..]),
+            _ => (None, word),
+        };
+        let keywords: &[&str] = if close.is_some() { &KEYWORDS } else { &BARE_KEYWORDS };
+        // the first word starts a title rather than featured artists
+        if close.is_none() && word_idx == 0 {
+            continue;
+        }
+        let rest = match keywords.iter().find_map(|x| strip_keyword(word, x)) {
+            Some(rest) => rest,
+            None => continue,
+        };
+        let rest_start = title.len() - rest.len();
+        let (artists, after) = match close {
+            Some(close) => match rest.find(close) {
+                Some(end) => (&rest[..end], &rest[end + 1..]),
+                None => (rest, ""),
+            },
+            None => {
+                let end = [rest.find(['(', '[']), rest.find(" - ")]
+                    .iter()
+                    .flatten()
+                    .min()
+                    .copied()
+                    .unwrap_or(rest.len());
+                (&rest[..end], &title[rest_start + end..])
+            }
+        };
+        let artists = artists.trim();
+        if artists.is_empty() {
+            continue;
+        }
+        return Some(Featured {
+            before: title[..start].trim_end(),
+            artists,
+            after: after.trim_start(),
+        });
+    }
+    None
+}
+
+/// Returns the rest of a word starting with a keyword (compared case-insensitively) followed by whitespace
+fn strip_keyword<'a>(word: &'a str, keyword: &str) -> Option<&'a str> {
+    let prefix = word.get(..keyword.len())?;
+    let rest = &word[keyword.len()..];
+    if prefix.eq_ignore_ascii_case(keyword) && rest.starts_with(char::is_whitespace) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+/// Joins parts of a title with a space, skipping empty parts
+fn join(before: &str, after: &str) -> String {
+    match (before.is_empty(), after.is_empty()) {
+        (_, true) => before.to_owned(),
+        (true, false) => after.to_owned(),
+        (false, false) => format!("{} {}", before, after),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_featured_artists_in_brackets() {
+        let featured = |title: &str, artists: &str| Some((String::from(title), String::from(artists)));
+        assert_eq!(split_featured("Song (feat. Guest)"), featured("Song", "Guest"));
+        assert_eq!(split_featured("Song [Ft Guest]"), featured("Song", "Guest"));
+        assert_eq!(
+            split_featured("Song (FEATURING A & B) (Remix)"),
+            featured("Song (Remix)", "A & B")
+        );
+        assert_eq!(split_featured("Song (feat. Guest"), featured("Song", "Guest"));
+    }
+
+    #[test]
+    fn splits_bare_featured_artists() {
+        let featured = |title: &str, artists: &str| Some((String::from(title), String::from(artists)));
+        assert_eq!(
+            split_featured("Song featuring Guest (Remix)"),
+            featured("Song (Remix)", "Guest")
+        );
+        assert_eq!(
+            split_featured("Song ft. Guest - Live"),
+            featured("Song - Live", "Guest")
+        );
+        assert_eq!(split_featured("Song Feat. Guest"), featured("Song", "Guest"));
+    }
+
+    #[test]
+    fn keeps_titles_without_featured_artists() {
+        assert_eq!(split_featured("A Heroic Feat"), None);
+        assert_eq!(split_featured("Feat of Strength"), None);
+        assert_eq!(split_featured("Song ft Guest"), None);
+        assert_eq!(split_featured("Song (feat. )"), None);
+        assert_eq!(split_featured("Left (Featured)"), None);
+    }
+
+    #[test]
+    fn normalizes_featured_artists() {
+        assert_eq!(
+            normalize_featured("Song FT. Guest - Live").as_deref(),
+            Some("Song (feat. Guest) - Live")
+        );
+        assert_eq!(normalize_featured("Song").as_deref(), None);
+    }
+
+    #[test]
+    fn adds_featured_artists() {
+        assert_eq!(add_featured("Artist", "Guest", ";"), "Artist; Guest");
+        assert_eq!(add_featured("Artist feat. Guest", "guest", ";"), "Artist feat. Guest");
+        assert_eq!(add_featured("", "Guest", ";"), "Guest");
+    }
+}
@@ -1,169 +1,815 @@
-use crate::{album::AlbumOutput, track::TrackOutput};
-use ape::Error as ApeError;
-use id3::{
-    v1::Tag as Id3V1Tag, Content as Id3FrameContent, Error as Id3Error, Frame as Id3Frame, Tag as Id3V2Tag,
-    Version as Id3Version,
+use crate::{
+    album::AlbumOutput,
+    case::title_case,
+    cover::Cover,
+    date::Date,
+    featured::{add_featured, normalize_featured, split_featured, FeaturedMode},
+    finder::Format,
+    nfc::to_nfc,
+    template::FilenameTemplate,
+    track::TrackOutput,
 };
+use id3::Version as Id3Version;
 use std::{
     error::Error as StdError,
+    ffi::OsString,
     fmt,
-    fs::{rename, OpenOptions},
-    io::Error as IoError,
+    fs::{self, rename, File},
+    io::{Error as IoError, ErrorKind as IoErrorKind},
     path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, SystemTime},
 };
 
-#[derive(Debug)]
+/// Separates multiple values of a field read from tags, e.g. artists of a collaboration
+///
+/// [`FileInput::join_values`] replaces it with a separator typed in the editor.
+pub const VALUE_SEPARATOR: char = '\0';
+
+/// Separator of multiple values typed in the editor
+pub const DEFAULT_VALUE_SEPARATOR: &str = ";";
+
+#[derive(Clone, Debug)]
 pub struct FileInput {
     pub path: PathBuf,
+    pub format: Format,
     pub artist: Option<String>,
     pub album_artist: Option<String>,
     pub album: Option<String>,
-    pub year: Option<i32>,
+    pub date: Option<Date>,
+    pub genre: Option<String>,
+    /// A work or a content group, `TIT1` of ID3
+    pub grouping: Option<String>,
     pub title: Option<String>,
+    /// `TIT3` of ID3
+    pub subtitle: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+    /// Unsynchronised lyrics or a transcript, lines are separated with `\n`
+    pub lyrics: Option<String>,
+    /// Beats per minute
+    pub bpm: Option<u32>,
+    /// Rating from 1 to 5 stars
+    pub rating: Option<u8>,
+    /// `TSRC` of ID3
+    pub isrc: Option<String>,
     pub track_number: Option<u32>,
     pub total_tracks: Option<u32>,
     pub disc_number: Option<u32>,
     pub total_discs: Option<u32>,
+    /// A name of a disc of a box set, `TSST` of ID3
+    pub disc_subtitle: Option<String>,
+    pub compilation: Option<bool>,
+    /// Playback duration of the audio stream, `None` when it can not be computed
+    pub duration: Option<Duration>,
 }
 
 impl FileInput {
-    pub fn from_path(path: impl AsRef<Path>) -> Self {
-        let path = path.as_ref();
-        let tag = match Id3V2Tag::read_from_path(path) {
-            Ok(tag) => tag,
-            Err(_) => {
-                return Self::empty(path);
-            }
-        };
+    pub fn from_path(path: impl AsRef<Path>, format: Format) -> Result<Self, Box<dyn StdError + Send + Sync>> {
+        format.backend().read(path.as_ref()).map(Self::into_nfc)
+    }
+
+    /// Joins multiple artists with a separator, e.g. `A; B` for `;`
+    pub fn join_values(self, separator: &str) -> Self {
+        let separator = format!("{} ", separator.trim_end());
+        let join = |value: Option<String>| value.map(|x| x.replace(VALUE_SEPARATOR, &separator));
+        Self {
+            artist: join(self.artist),
+            album_artist: join(self.album_artist),
+            ..self
+        }
+    }
+
+    /// Composes decomposed text, so spellings differing only in Unicode normalization are counted together
+    fn into_nfc(self) -> Self {
+        let nfc = |value: Option<String>| value.map(|x| to_nfc(&x));
         Self {
-            path: path.to_owned(),
-            artist: tag.artist().map(ToOwned::to_owned),
-            album_artist: tag.album_artist().map(ToOwned::to_owned),
-            album: tag.album().map(ToOwned::to_owned),
-            year: tag.date_recorded().map(|x| x.year).or_else(|| tag.year()),
-            title: tag.title().map(ToOwned::to_owned),
-            track_number: tag.track(),
-            total_tracks: tag.total_tracks(),
-            disc_number: tag.disc(),
-            total_discs: tag.total_discs(),
+            artist: nfc(self.artist),
+            album_artist: nfc(self.album_artist),
+            album: nfc(self.album),
+            genre: nfc(self.genre),
+            grouping: nfc(self.grouping),
+            title: nfc(self.title),
+            subtitle: nfc(self.subtitle),
+            composer: nfc(self.composer),
+            comment: nfc(self.comment),
+            lyrics: nfc(self.lyrics),
+            disc_subtitle: nfc(self.disc_subtitle),
+            ..self
         }
     }
 
-    fn empty(path: impl AsRef<Path>) -> Self {
+    pub fn empty(path: impl AsRef<Path>, format: Format) -> Self {
         Self {
             path: path.as_ref().to_owned(),
+            format,
             artist: None,
             album_artist: None,
             album: None,
-            year: None,
+            date: None,
+            genre: None,
+            grouping: None,
             title: None,
+            subtitle: None,
+            composer: None,
+            comment: None,
+            lyrics: None,
+            bpm: None,
+            rating: None,
+            isrc: None,
             track_number: None,
             total_tracks: None,
             disc_number: None,
             total_discs: None,
+            disc_subtitle: None,
+            compilation: None,
+            duration: None,
         }
     }
 }
 
 #[derive(Debug)]
 pub struct FileOutput {
-    path: PathBuf,
-    artist: String,
-    album_artist: String,
-    album: String,
-    year: i32,
-    title: String,
-    track_number: u32,
-    total_tracks: u32,
-    disc_number: u32,
-    total_discs: u32,
-}
-
-impl From<(PathBuf, &AlbumOutput, TrackOutput)> for FileOutput {
-    fn from((path, album_output, track_output): (PathBuf, &AlbumOutput, TrackOutput)) -> Self {
+    pub path: PathBuf,
+    pub format: Format,
+    pub artist: String,
+    pub album_artist: String,
+    pub album: String,
+    pub date: Date,
+    pub genre: Option<String>,
+    pub grouping: Option<String>,
+    pub title: String,
+    /// MP4 files don't store it
+    pub subtitle: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+    pub lyrics: Option<String>,
+    pub bpm: Option<u32>,
+    /// Rating from 1 to 5 stars, MP4 files don't store it
+    pub rating: Option<u8>,
+    /// MP4 files don't store it
+    pub isrc: Option<String>,
+    pub track_number: u32,
+    /// `None` when unknown, a track number is written without a total then
+    pub total_tracks: Option<u32>,
+    pub disc_number: u32,
+    pub total_discs: Option<u32>,
+    /// MP4 files don't store it
+    pub disc_subtitle: Option<String>,
+    /// Whether the file belongs to a compilation of various artists
+    pub compilation: bool,
+    /// Whether embedded pictures (cover art) survive the write, disable for a clean slate
+    pub keep_pictures: bool,
+    /// A front cover to embed, replaces pictures embedded before
+    pub cover: Option<Rc<Cover>>,
+    /// Minimum number of digits of track and disc numbers, numbers are widened to fit their totals
+    pub number_width: Option<usize>,
+}
+
+/// Text is written composed (NFC)
+impl From<(FileInput, &AlbumOutput, TrackOutput)> for FileOutput {
+    fn from((file_input, album_output, track_output): (FileInput, &AlbumOutput, TrackOutput)) -> Self {
         Self {
-            path,
-            artist: album_output.artist.clone(),
-            album_artist: album_output.album_artist.clone(),
-            album: album_output.album.clone(),
-            year: album_output.year,
-            title: track_output.title,
+            path: file_input.path,
+            format: file_input.format,
+            artist: to_nfc(&album_output.track_artist(file_input.artist.as_deref())),
+            album_artist: to_nfc(&album_output.album_artist),
+            album: to_nfc(&album_output.album),
+            date: album_output.date,
+            genre: album_output.genre.as_deref().map(to_nfc),
+            grouping: album_output.grouping.as_deref().map(to_nfc),
+            title: to_nfc(&track_output.title),
+            subtitle: track_output.subtitle.as_deref().map(to_nfc),
+            composer: track_output.composer.as_deref().map(to_nfc),
+            comment: track_output.comment.as_deref().map(to_nfc),
+            lyrics: track_output.lyrics.as_deref().map(to_nfc),
+            bpm: track_output.bpm,
+            rating: track_output.rating,
+            isrc: track_output.isrc,
             track_number: track_output.track_number,
-            total_tracks: album_output.total_tracks,
+            total_tracks: album_output.total_tracks_of(track_output.disc_number),
             disc_number: track_output.disc_number,
             total_discs: album_output.total_discs,
+            disc_subtitle: track_output.disc_subtitle.as_deref().map(to_nfc),
+            compilation: album_output.compilation,
+            keep_pictures: true,
+            cover: None,
+            number_width: None,
         }
     }
 }
 
 impl FileOutput {
-    pub fn write(self) -> Result<PathBuf, FileOutputError> {
-        ape::remove(&self.path).map_err(FileOutputError::RemoveApe)?;
-        {
-            let mut file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&self.path)
-                .map_err(FileOutputError::OpenFile)?;
-            Id3V1Tag::remove(&mut file).map_err(FileOutputError::RemoveId3V1)?;
-            Id3V2Tag::remove_from(&mut file).map_err(FileOutputError::RemoveId3V2)?;
-        }
-        let mut tag = Id3V2Tag::new();
-        tag.add_frame(Id3Frame::with_content("TPE1", Id3FrameContent::Text(self.artist)));
-        tag.add_frame(Id3Frame::with_content("TPE2", Id3FrameContent::Text(self.album_artist)));
-        tag.add_frame(Id3Frame::with_content("TALB", Id3FrameContent::Text(self.album)));
-        tag.add_frame(Id3Frame::with_content(
-            "TDRC",
-            Id3FrameContent::Text(self.year.to_string()),
-        ));
-        tag.add_frame(Id3Frame::with_content(
-            "TIT2",
-            Id3FrameContent::Text(self.title.clone()),
-        ));
-        tag.add_frame(Id3Frame::with_content(
-            "TRCK",
-            Id3FrameContent::Text(format!("{:02}/{:02}", self.track_number, self.total_tracks)),
-        ));
-        tag.add_frame(Id3Frame::with_content(
-            "TPOS",
-            Id3FrameContent::Text(format!("{:02}/{:02}", self.disc_number, self.total_discs)),
-        ));
-        tag.write_to_path(&self.path, Id3Version::Id3v24)
-            .map_err(FileOutputError::WriteId3V2)?;
-
-        let number = if self.total_discs > 1 {
-            format!("{:02}-{:02}", self.disc_number, self.track_number)
-        } else {
-            format!("{:02}", self.track_number)
+    /// Whether a date is written, a zero year without a month is a missing date
+    ///
+    /// Like empty text and zero numbers, which are not written either,
+    /// it keeps values missing in restored tags (see [`crate::manifest`]).
+    pub fn has_date(&self) -> bool {
+        self.date.year != 0 || self.date.month.is_some()
+    }
+
+    /// Whether pictures embedded before should be written back
+    pub fn keeps_pictures(&self) -> bool {
+        self.keep_pictures && self.cover.is_none()
+    }
+
+    /// Returns fields whose values differ from tags read from the file, unchanged fields are omitted
+    ///
+    /// Covers are not compared, since embedded pictures are not read.
+    pub fn changes(&self, input: &FileInput) -> Vec<Change> {
+        let mut result = Vec::new();
+        let mut push = |field: &'static str, old: Option<String>, new: Option<String>| {
+            let old = old.filter(|x| !x.is_empty());
+            let new = new.filter(|x| !x.is_empty());
+            if old != new {
+                result.push(Change { field, old, new });
+            }
+        };
+        let number = |x: Option<u32>| x.map(|x| x.to_string());
+        push("ARTIST", input.artist.clone(), Some(self.artist.clone()));
+        push(
+            "ALBUM ARTIST",
+            input.album_artist.clone(),
+            Some(self.album_artist.clone()),
+        );
+        push("ALBUM", input.album.clone(), Some(self.album.clone()));
+        push("DATE", input.date.map(|x| x.to_string()), Some(self.date.to_string()));
+        push("GENRE", input.genre.clone(), self.genre.clone());
+        push("GROUPING", input.grouping.clone(), self.grouping.clone());
+        push("TITLE", input.title.clone(), Some(self.title.clone()));
+        push("SUBTITLE", input.subtitle.clone(), self.subtitle.clone());
+        push("COMPOSER", input.composer.clone(), self.composer.clone());
+        push("COMMENT", input.comment.clone(), self.comment.clone());
+        push("LYRICS", input.lyrics.clone(), self.lyrics.clone());
+        push("BPM", number(input.bpm), number(self.bpm));
+        let stars = |x: Option<u8>| x.map(|x| format!("{}/5", x));
+        push("RATING", stars(input.rating), stars(self.rating));
+        push("ISRC", input.isrc.clone(), self.isrc.clone());
+        push(
+            "TRACK NUMBER",
+            number(input.track_number),
+            number(Some(self.track_number)),
+        );
+        push("TOTAL TRACKS", number(input.total_tracks), number(self.total_tracks));
+        push("DISC NUMBER", number(input.disc_number), number(Some(self.disc_number)));
+        push("TOTAL DISCS", number(input.total_discs), number(self.total_discs));
+        push("DISC SUBTITLE", input.disc_subtitle.clone(), self.disc_subtitle.clone());
+        // a missing flag means no compilation
+        let flag = |x: bool| Some(String::from(if x { "yes" } else { "no" }));
+        push(
+            "COMPILATION",
+            flag(input.compilation.unwrap_or(false)),
+            flag(self.compilation),
+        );
+        result
+    }
+
+    /// Replaces values of a track, e.g. with values of the track edited again
+    pub fn set_track(&mut self, album_output: &AlbumOutput, track_output: TrackOutput) {
+        self.title = to_nfc(&track_output.title);
+        self.subtitle = track_output.subtitle.as_deref().map(to_nfc);
+        self.composer = track_output.composer.as_deref().map(to_nfc);
+        self.comment = track_output.comment.as_deref().map(to_nfc);
+        self.lyrics = track_output.lyrics.as_deref().map(to_nfc);
+        self.bpm = track_output.bpm;
+        self.rating = track_output.rating;
+        self.isrc = track_output.isrc;
+        self.track_number = track_output.track_number;
+        self.total_tracks = album_output.total_tracks_of(track_output.disc_number);
+        self.disc_number = track_output.disc_number;
+        self.disc_subtitle = track_output.disc_subtitle.as_deref().map(to_nfc);
+    }
+
+    /// Keeps values already stored in the file, only fields missing or empty there are taken from this output
+    pub fn fill_missing(&mut self, options: &WriteOptions) -> Result<(), Box<dyn StdError + Send + Sync>> {
+        let input = FileInput::from_path(&self.path, self.format)?.join_values(&options.value_separator);
+        let text = |existing: Option<String>, value: &mut String| {
+            if let Some(existing) = existing.filter(|x| !x.trim().is_empty()) {
+                *value = existing;
+            }
+        };
+        text(input.artist, &mut self.artist);
+        text(input.album_artist, &mut self.album_artist);
+        text(input.album, &mut self.album);
+        text(input.title, &mut self.title);
+        let optional = |existing: Option<String>, value: &mut Option<String>| {
+            if let Some(existing) = existing.filter(|x| !x.trim().is_empty()) {
+                *value = Some(existing);
+            }
+        };
+        optional(input.genre, &mut self.genre);
+        optional(input.grouping, &mut self.grouping);
+        optional(input.subtitle, &mut self.subtitle);
+        optional(input.composer, &mut self.composer);
+        optional(input.comment, &mut self.comment);
+        optional(input.lyrics, &mut self.lyrics);
+        optional(input.disc_subtitle, &mut self.disc_subtitle);
+        optional(input.isrc, &mut self.isrc);
+        self.date = input.date.unwrap_or(self.date);
+        // zero is what some taggers write for a missing number
+        let number = |existing: Option<u32>, value: &mut u32| {
+            if let Some(existing) = existing.filter(|x| *x > 0) {
+                *value = existing;
+            }
         };
-        let filename = format!("{} - {}.mp3", number, self.title);
-        let new_path = self.path.with_file_name(filename);
-        rename(&self.path, &new_path).map_err(FileOutputError::RenameFile)?;
+        number(input.track_number, &mut self.track_number);
+        self.total_tracks = input.total_tracks.filter(|x| *x > 0).or(self.total_tracks);
+        number(input.disc_number, &mut self.disc_number);
+        self.total_discs = input.total_discs.filter(|x| *x > 0).or(self.total_discs);
+        self.bpm = input.bpm.filter(|x| *x > 0).or(self.bpm);
+        self.rating = input.rating.filter(|x| *x > 0).or(self.rating);
+        self.compilation = input.compilation.unwrap_or(self.compilation);
+        Ok(())
+    }
+
+    /// Converts artists, album and title to title case
+    pub fn apply_title_case(&mut self) {
+        self.artist = title_case(&self.artist);
+        self.album_artist = title_case(&self.album_artist);
+        self.album = title_case(&self.album);
+        self.title = title_case(&self.title);
+    }
+
+    /// Moves featured artists of a title to the artist or rewrites them as `(feat. Guest)`
+    ///
+    /// Moved artists are appended to the artist with a separator of multiple values.
+    pub fn apply_featured(&mut self, mode: FeaturedMode, separator: &str) {
+        match mode {
+            FeaturedMode::Artist => {
+                // a title consisting only of featured artists is kept
+                if let Some((title, featured)) = split_featured(&self.title).filter(|(x, _)| !x.is_empty()) {
+                    self.artist = add_featured(&self.artist, &featured, separator);
+                    self.title = title;
+                }
+            }
+            FeaturedMode::Title => {
+                if let Some(title) = normalize_featured(&self.title) {
+                    self.title = title;
+                }
+            }
+        }
+    }
+
+    /// Returns a track number and a number of tracks zero-padded to the same width, e.g. `007` and `120`
+    pub fn padded_track_number(&self) -> (String, Option<String>) {
+        self.pad(self.track_number, self.total_tracks)
+    }
+
+    /// Returns a disc number and a number of discs zero-padded to the same width
+    pub fn padded_disc_number(&self) -> (String, Option<String>) {
+        self.pad(self.disc_number, self.total_discs)
+    }
+
+    fn pad(&self, number: u32, total: Option<u32>) -> (String, Option<String>) {
+        let width = self
+            .number_width
+            .unwrap_or(DEFAULT_NUMBER_WIDTH)
+            .max(number.max(total.unwrap_or(0)).to_string().len());
+        (
+            format!("{:0width$}", number, width = width),
+            total.map(|x| format!("{:0width$}", x, width = width)),
+        )
+    }
+
+    /// Returns a path the file is renamed to
+    ///
+    /// The path is relative to a destination directory, or to a directory of the file when there is no destination.
+    pub fn target_path(&self, options: &WriteOptions) -> PathBuf {
+        let mut path = match options.destination {
+            Some(ref destination) => destination.clone(),
+            None => self.path.parent().map(ToOwned::to_owned).unwrap_or_default(),
+        };
+        for component in options.template.render(self).split('/').filter(|x| !x.is_empty()) {
+            path.push(component);
+        }
+        path
+    }
+
+    /// Returns a path of a backup copy, e.g. `01 - Title.mp3.bak`
+    fn backup_path(&self) -> PathBuf {
+        backup_path_of(&self.path)
+    }
+
+    /// Returns a path of a temporary file in the same directory, e.g. `.01 - Title.mp3.tmp`
+    fn temp_path(&self) -> PathBuf {
+        temp_path_of(&self.path)
+    }
+
+    /// Writes tags and renames the file according to a template
+    ///
+    /// Tags are written to a temporary copy which then replaces the file,
+    /// so the file is never left half-written.
+    /// A backup copy is made before the file is touched and it is always kept when writing fails.
+    pub fn write(self, options: &WriteOptions) -> Result<PathBuf, FileOutputError> {
+        let new_path = self.target_path(options);
+        self.write_to(new_path, options)
+    }
+
+    /// Writes tags and moves the file to a path, ignoring a template and a destination of options
+    ///
+    /// Paths without a file name (e.g. `/` or `..`), an existing directory and an existing file other than
+    /// the file itself as a new path are rejected.
+    /// When options ask to copy files, the file is copied to the path instead, see [`FileOutput::copy_to`].
+    pub fn write_to(self, new_path: PathBuf, options: &WriteOptions) -> Result<PathBuf, FileOutputError> {
+        check_file_path(&self.path)?;
+        check_file_path(&new_path)?;
+        if new_path.is_dir() {
+            return Err(FileOutputError::TargetIsDirectory(new_path));
+        }
+        if options.copy {
+            return self.copy_to(new_path, options);
+        }
+        let is_renamed = new_path != self.path;
+        // on a case-insensitive filesystem a name differing in case only leads to the file itself
+        let is_case_only = is_renamed && is_same_file(&self.path, &new_path);
+        if is_renamed && !is_case_only && new_path.exists() {
+            return Err(FileOutputError::TargetExists(new_path));
+        }
+        let is_read_only = check_read_only(&self.path, options)?;
+        let modified = modified_time(&self.path, options)?;
+        let backup_path = self.backup_path();
+        if options.backup != Backup::Disabled {
+            fs::copy(&self.path, &backup_path).map_err(FileOutputError::CreateBackup)?;
+        }
+
+        let temp_path = self.temp_path();
+        fs::copy(&self.path, &temp_path).map_err(FileOutputError::CreateTempFile)?;
+        if is_read_only {
+            if let Err(err) = make_writable(&temp_path) {
+                let _ = fs::remove_file(&temp_path);
+                return Err(FileOutputError::MakeWritable(err));
+            }
+        }
+        if let Err(err) = self.format.backend().write(&temp_path, &self, options) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(FileOutputError::WriteTags(err));
+        }
+        if let Some(modified) = modified {
+            if let Err(err) = set_modified_time(&temp_path, modified) {
+                let _ = fs::remove_file(&temp_path);
+                return Err(FileOutputError::SetModifiedTime(err));
+            }
+        }
+
+        if let Some(parent) = new_path.parent() {
+            if let Err(err) = fs::create_dir_all(parent) {
+                let _ = fs::remove_file(&temp_path);
+                return Err(FileOutputError::CreateDir(err));
+            }
+        }
+
+        // the tagged copy is put in place first, so the original is untouched until there is a result
+        if is_case_only {
+            // both names lead to the file, so the copy replaces it and then the file changes its case
+            rename(&temp_path, &self.path).map_err(|err| {
+                let _ = fs::remove_file(&temp_path);
+                FileOutputError::ReplaceFile(err)
+            })?;
+            rename(&self.path, &new_path).map_err(FileOutputError::RenameFile)?;
+        } else {
+            match rename(&temp_path, &new_path) {
+                Ok(()) => {}
+                // a destination on another filesystem can't be renamed into, the tagged copy is copied there
+                Err(ref err) if err.kind() == IoErrorKind::CrossesDevices => {
+                    let result = copy_across_devices(&temp_path, &new_path);
+                    let _ = fs::remove_file(&temp_path);
+                    result.map_err(FileOutputError::CopyFile)?;
+                }
+                Err(err) => {
+                    let _ = fs::remove_file(&temp_path);
+                    return Err(FileOutputError::RenameFile(err));
+                }
+            }
+            if is_renamed {
+                fs::remove_file(&self.path).map_err(FileOutputError::RemoveOriginal)?;
+            }
+        }
+
+        if options.backup == Backup::RemoveOnSuccess {
+            fs::remove_file(&backup_path).map_err(FileOutputError::RemoveBackup)?;
+        }
 
         Ok(new_path)
     }
+
+    /// Writes tags to a copy of the file at a path, the file itself is never touched
+    ///
+    /// The copy is made writable even if the file is read-only, there is no backup as nothing is replaced.
+    fn copy_to(self, new_path: PathBuf, options: &WriteOptions) -> Result<PathBuf, FileOutputError> {
+        if is_same_file(&self.path, &new_path) {
+            return Err(FileOutputError::TargetIsOriginal(new_path));
+        }
+        if new_path.exists() {
+            return Err(FileOutputError::TargetExists(new_path));
+        }
+        let modified = modified_time(&self.path, options)?;
+        if let Some(parent) = new_path.parent() {
+            fs::create_dir_all(parent).map_err(FileOutputError::CreateDir)?;
+        }
+
+        let temp_path = temp_path_of(&new_path);
+        fs::copy(&self.path, &temp_path).map_err(FileOutputError::CreateTempFile)?;
+        if let Err(err) = make_writable(&temp_path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(FileOutputError::MakeWritable(err));
+        }
+        if let Err(err) = self.format.backend().write(&temp_path, &self, options) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(FileOutputError::WriteTags(err));
+        }
+        if let Some(modified) = modified {
+            if let Err(err) = set_modified_time(&temp_path, modified) {
+                let _ = fs::remove_file(&temp_path);
+                return Err(FileOutputError::SetModifiedTime(err));
+            }
+        }
+        if let Err(err) = rename(&temp_path, &new_path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(FileOutputError::ReplaceFile(err));
+        }
+
+        Ok(new_path)
+    }
+}
+
+/// Removes all tags of a file, the file keeps its name
+///
+/// Like [`FileOutput::write`], tags are removed from a temporary copy which then replaces the file.
+pub fn clean(path: &Path, format: Format, options: &WriteOptions) -> Result<(), FileOutputError> {
+    check_file_path(path)?;
+    let is_read_only = check_read_only(path, options)?;
+    let modified = modified_time(path, options)?;
+    let backup_path = backup_path_of(path);
+    if options.backup != Backup::Disabled {
+        fs::copy(path, &backup_path).map_err(FileOutputError::CreateBackup)?;
+    }
+
+    let temp_path = temp_path_of(path);
+    fs::copy(path, &temp_path).map_err(FileOutputError::CreateTempFile)?;
+    if is_read_only {
+        if let Err(err) = make_writable(&temp_path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(FileOutputError::MakeWritable(err));
+        }
+    }
+    if let Err(err) = format.backend().clean(&temp_path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(FileOutputError::RemoveTags(err));
+    }
+    if let Some(modified) = modified {
+        if let Err(err) = set_modified_time(&temp_path, modified) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(FileOutputError::SetModifiedTime(err));
+        }
+    }
+    if let Err(err) = rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(FileOutputError::ReplaceFile(err));
+    }
+
+    if options.backup == Backup::RemoveOnSuccess {
+        fs::remove_file(&backup_path).map_err(FileOutputError::RemoveBackup)?;
+    }
+    Ok(())
+}
+
+/// Fails on a read-only file (e.g. copied off a CD) unless options allow to make it writable
+///
+/// Returns whether the file is read-only, so its tagged copy has to be made writable.
+fn check_read_only(path: &Path, options: &WriteOptions) -> Result<bool, FileOutputError> {
+    let metadata = fs::metadata(path).map_err(FileOutputError::ReadMetadata)?;
+    match metadata.permissions().readonly() {
+        true if options.make_writable => Ok(true),
+        true => Err(FileOutputError::ReadOnly(path.to_owned())),
+        false => Ok(false),
+    }
+}
+
+/// Lets the owner write a file, permissions of others are kept
+fn make_writable(path: &Path) -> Result<(), IoError> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    permissions.set_readonly(false);
+    fs::set_permissions(path, permissions)
+}
+
+/// Returns a modification time of a file when options ask to keep it
+fn modified_time(path: &Path, options: &WriteOptions) -> Result<Option<SystemTime>, FileOutputError> {
+    if !options.preserve_modified_time {
+        return Ok(None);
+    }
+    fs::metadata(path)
+        .and_then(|x| x.modified())
+        .map(Some)
+        .map_err(FileOutputError::ReadMetadata)
+}
+
+fn set_modified_time(path: &Path, modified: SystemTime) -> Result<(), IoError> {
+    File::options().write(true).open(path)?.set_modified(modified)
+}
+
+/// Returns a path of a backup copy of a file, e.g. `01 - Title.mp3.bak`
+fn backup_path_of(path: &Path) -> PathBuf {
+    let mut path = path.to_owned().into_os_string();
+    path.push(BACKUP_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// Parses beats per minute stored as text, e.g. `128` or `127.9`, zero is a missing value
+pub fn parse_bpm(value: &str) -> Option<u32> {
+    let value: f64 = value.trim().parse().ok()?;
+    Some(value.round())
+        .filter(|x| *x >= 1.0 && *x <= f64::from(u32::MAX))
+        .map(|x| x as u32)
+}
+
+/// Splits a value typed with multiple values, e.g. `A; B` gives `A` and `B`
+pub fn split_values<'a>(value: &'a str, separator: &str) -> Vec<&'a str> {
+    if separator.is_empty() {
+        return vec![value];
+    }
+    let values: Vec<&str> = value
+        .split(separator)
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .collect();
+    if values.is_empty() {
+        vec![value]
+    } else {
+        values
+    }
+}
+
+/// Ensures a path has a file name and a parent directory, which temporary and backup copies are named after
+fn check_file_path(path: &Path) -> Result<(), FileOutputError> {
+    match (path.parent(), path.file_name()) {
+        (Some(_), Some(_)) => Ok(()),
+        _ => Err(FileOutputError::NoFileName(path.to_owned())),
+    }
+}
+
+/// Whether both paths exist and lead to the same file
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Returns a path of a hidden temporary file next to a file
+fn temp_path_of(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    if let Some(file_name) = path.file_name() {
+        name.push(file_name);
+    }
+    name.push(TEMP_EXTENSION);
+    path.with_file_name(name)
+}
+
+/// Copies a file to another filesystem
+///
+/// The copy is written next to the target and renamed into place once complete,
+/// so a failure never leaves a truncated file behind. The copy keeps a modification time of the file.
+fn copy_across_devices(from: &Path, to: &Path) -> Result<(), IoError> {
+    let temp_path = temp_path_of(to);
+    let result = fs::copy(from, &temp_path).and_then(|copied| {
+        let metadata = fs::metadata(from)?;
+        if copied == metadata.len() {
+            set_modified_time(&temp_path, metadata.modified()?)?;
+            rename(&temp_path, to)
+        } else {
+            Err(IoError::new(IoErrorKind::WriteZero, "file was copied partially"))
+        }
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+const DEFAULT_NUMBER_WIDTH: usize = 2;
+const BACKUP_EXTENSION: &str = ".bak";
+const TEMP_EXTENSION: &str = ".tmp";
+
+/// Settings shared by all written files
+#[derive(Debug)]
+pub struct WriteOptions {
+    pub template: FilenameTemplate,
+    pub backup: Backup,
+    /// A directory to move files into
+    pub destination: Option<PathBuf>,
+    /// Version of written ID3v2 tags, dates go to `TYER`/`TDAT` in ID3v2.3 and to `TDRC` in ID3v2.4
+    pub id3_version: Id3Version,
+    /// Whether an ID3v1 tag is written along with ID3v2, otherwise ID3v1 is removed
+    pub write_id3v1: bool,
+    /// Separator of multiple artists, they are written as separate values where a format supports it
+    pub value_separator: String,
+    /// Whether written files keep their modification time, otherwise it is the time of writing
+    pub preserve_modified_time: bool,
+    /// Whether read-only files are made writable, otherwise writing them fails
+    pub make_writable: bool,
+    /// Whether tagged files are copied to their new paths, otherwise they are moved and originals are gone
+    pub copy: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            template: FilenameTemplate::default(),
+            backup: Backup::Disabled,
+            destination: None,
+            id3_version: Id3Version::Id3v24,
+            write_id3v1: false,
+            value_separator: String::from(DEFAULT_VALUE_SEPARATOR),
+            preserve_modified_time: false,
+            make_writable: false,
+            copy: false,
+        }
+    }
+}
+
+/// What to do with original files when writing
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Backup {
+    /// Files are changed without a backup
+    Disabled,
+    /// A backup copy is kept next to a file
+    Keep,
+    /// A backup copy is removed once a file is written
+    RemoveOnSuccess,
+}
+
+/// Reads and writes tags of a specific format
+///
+/// Backends are selected by [`Format::backend`], so editors never deal with a tag library directly.
+pub trait TagBackend: Sync {
+    /// Returns tags of a file, a file without tags gives an empty input
+    fn read(&self, path: &Path) -> Result<FileInput, Box<dyn StdError + Send + Sync>>;
+
+    /// Replaces tags of a file at `path` (which may differ from `output.path`) with values of the output
+    fn write(&self, path: &Path, output: &FileOutput, options: &WriteOptions) -> Result<(), Box<dyn StdError>>;
+
+    /// Removes all tags of a file at `path`
+    fn clean(&self, path: &Path) -> Result<(), Box<dyn StdError>>;
+}
+
+/// A field changed by writing an output, `None` is a missing value
+#[derive(Debug)]
+pub struct Change {
+    pub field: &'static str,
+    pub old: Option<String>,
+    pub new: Option<String>,
 }
 
 #[derive(Debug)]
 pub enum FileOutputError {
-    OpenFile(IoError),
-    RemoveApe(ApeError),
-    RemoveId3V1(Id3Error),
-    RemoveId3V2(Id3Error),
+    CopyFile(IoError),
+    CreateBackup(IoError),
+    CreateDir(IoError),
+    CreateTempFile(IoError),
+    MakeWritable(IoError),
+    NoFileName(PathBuf),
+    ReadMetadata(IoError),
+    ReadOnly(PathBuf),
+    RemoveBackup(IoError),
+    RemoveOriginal(IoError),
+    RemoveTags(Box<dyn StdError>),
     RenameFile(IoError),
-    WriteId3V2(Id3Error),
+    ReplaceFile(IoError),
+    SetModifiedTime(IoError),
+    TargetExists(PathBuf),
+    TargetIsDirectory(PathBuf),
+    TargetIsOriginal(PathBuf),
+    WriteTags(Box<dyn StdError>),
 }
 
 impl StdError for FileOutputError {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use self::FileOutputError::*;
         match self {
-            OpenFile(err) => Some(err),
-            RemoveApe(err) => Some(err),
-            RemoveId3V1(err) => Some(err),
-            RemoveId3V2(err) => Some(err),
+            CopyFile(err) => Some(err),
+            CreateBackup(err) => Some(err),
+            CreateDir(err) => Some(err),
+            CreateTempFile(err) => Some(err),
+            MakeWritable(err) => Some(err),
+            NoFileName(_) => None,
+            ReadMetadata(err) => Some(err),
+            ReadOnly(_) => None,
+            RemoveBackup(err) => Some(err),
+            RemoveOriginal(err) => Some(err),
+            RemoveTags(err) => Some(err.as_ref()),
             RenameFile(err) => Some(err),
-            WriteId3V2(err) => Some(err),
+            ReplaceFile(err) => Some(err),
+            SetModifiedTime(err) => Some(err),
+            TargetExists(_) => None,
+            TargetIsDirectory(_) => None,
+            TargetIsOriginal(_) => None,
+            WriteTags(err) => Some(err.as_ref()),
         }
     }
 }
@@ -172,12 +818,61 @@ impl fmt::Display for FileOutputError {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
         use self::FileOutputError::*;
         match self {
-            OpenFile(err) => write!(out, "failed to open file: {}", err),
-            RemoveApe(err) => write!(out, "failed to remove APE tag: {}", err),
-            RemoveId3V1(err) => write!(out, "failed to remove ID3V1 tag: {}", err),
-            RemoveId3V2(err) => write!(out, "failed to remove ID3V2 tag: {}", err),
+            CopyFile(err) => write!(out, "failed to copy file to another filesystem: {}", err),
+            CreateBackup(err) => write!(out, "failed to create backup: {}", err),
+            CreateDir(err) => write!(out, "failed to create directory: {}", err),
+            CreateTempFile(err) => write!(out, "failed to create temporary file: {}", err),
+            MakeWritable(err) => write!(out, "failed to make file writable: {}", err),
+            NoFileName(path) => write!(out, "{} is not a path of a file", path.display()),
+            ReadMetadata(err) => write!(out, "failed to read metadata of file: {}", err),
+            ReadOnly(path) => write!(
+                out,
+                "{} is read-only, make it writable (e.g. chmod u+w) or use --make-writable",
+                path.display()
+            ),
+            RemoveBackup(err) => write!(out, "failed to remove backup: {}", err),
+            RemoveOriginal(err) => write!(out, "failed to remove original file: {}", err),
+            RemoveTags(err) => write!(out, "failed to remove tags: {}", err),
             RenameFile(err) => write!(out, "failed to rename file: {}", err),
-            WriteId3V2(err) => write!(out, "failed to write ID3V2 tag: {}", err),
+            ReplaceFile(err) => write!(out, "failed to replace file with a tagged copy: {}", err),
+            SetModifiedTime(err) => write!(out, "failed to set modification time: {}", err),
+            TargetExists(path) => write!(out, "{} already exists", path.display()),
+            TargetIsDirectory(path) => write!(out, "{} is a directory", path.display()),
+            TargetIsOriginal(path) => write!(
+                out,
+                "{} is the original file, a copy needs another path (e.g. --destination)",
+                path.display()
+            ),
+            WriteTags(err) => write!(out, "failed to write tags: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_paths_without_file_name() {
+        for path in &["/", "..", "/music/..", ""] {
+            assert!(matches!(
+                check_file_path(Path::new(path)),
+                Err(FileOutputError::NoFileName(_))
+            ));
         }
+        assert!(check_file_path(Path::new("/music/01.mp3")).is_ok());
+        assert!(check_file_path(Path::new("01.mp3")).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn accepts_non_utf8_file_name() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let path = Path::new("/music").join(OsStr::from_bytes(b"01 \xff.mp3"));
+        assert!(check_file_path(&path).is_ok());
+        let temp_path = temp_path_of(&path);
+        assert_eq!(temp_path.parent(), Some(Path::new("/music")));
+        assert!(temp_path.as_os_str().as_bytes().ends_with(b"\xff.mp3.tmp"));
     }
 }
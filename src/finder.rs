@@ -1,44 +1,263 @@
-use crate::file::FileInput;
+use crate::{
+    file::{FileInput, TagBackend, DEFAULT_VALUE_SEPARATOR},
+    flac::FlacBackend,
+    mp3::Mp3Backend,
+    mp4::Mp4Backend,
+    ogg::OggBackend,
+};
 use std::{
+    cmp::Ordering,
     error::Error,
     fmt, fs,
     io::Error as IoError,
+    panic,
     path::{Path, PathBuf},
+    thread,
 };
 
+const EXT_FLAC: &str = "flac";
+const EXT_M4A: &str = "m4a";
 const EXT_MP3: &str = "mp3";
+const EXT_OGG: &str = "ogg";
 
-pub fn find(path: impl AsRef<Path>) -> Result<Vec<FileInput>, FindError> {
-    let path = path.as_ref();
-    let mut result = Vec::new();
-    for entry in fs::read_dir(&path).map_err(|err| FindError::ReadDir(path.to_owned(), err))? {
-        let entry = entry.map_err(FindError::ReadEntry)?;
-        let entry_path = entry.path();
-        if !entry_path.is_file() {
-            continue;
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Flac,
+    M4a,
+    Mp3,
+    Ogg,
+}
+
+impl Format {
+    pub const ALL: [Format; 4] = [Format::Flac, Format::M4a, Format::Mp3, Format::Ogg];
+
+    /// Returns a format of a file by its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension().and_then(|x| x.to_str())?.to_lowercase();
+        match extension.as_str() {
+            EXT_FLAC => Some(Format::Flac),
+            EXT_M4A => Some(Format::M4a),
+            EXT_MP3 => Some(Format::Mp3),
+            EXT_OGG => Some(Format::Ogg),
+            _ => None,
         }
-        if !entry_path
-            .extension()
-            .and_then(|x| x.to_str())
-            .map(|x| x.to_lowercase() == EXT_MP3)
-            .unwrap_or(false)
-        {
-            continue;
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Flac => EXT_FLAC,
+            Format::M4a => EXT_M4A,
+            Format::Mp3 => EXT_MP3,
+            Format::Ogg => EXT_OGG,
+        }
+    }
+
+    pub fn backend(self) -> &'static dyn TagBackend {
+        match self {
+            Format::Flac => &FlacBackend,
+            Format::M4a => &Mp4Backend,
+            Format::Mp3 => &Mp3Backend,
+            Format::Ogg => &OggBackend,
         }
-        result.push(FileInput::from_path(&entry_path))
     }
+}
+
+#[derive(Clone, Debug)]
+pub struct FinderOptions {
+    /// Extensions of files to pick up, compared case-insensitively
+    ///
+    /// Extensions without a tag backend are ignored.
+    pub extensions: Vec<String>,
+    /// Whether to descend into subdirectories (symlinked directories are not followed)
+    pub recursive: bool,
+    /// Maximum depth of subdirectories to descend into when searching recursively
+    pub max_depth: Option<usize>,
+    /// Number of threads reading tags, files are read one by one when it is 1
+    pub threads: usize,
+    /// Separator joining multiple artists of a file, e.g. `A; B` for `;`
+    pub value_separator: String,
+}
+
+impl Default for FinderOptions {
+    fn default() -> Self {
+        Self {
+            extensions: Format::ALL.iter().map(|x| x.extension().to_owned()).collect(),
+            recursive: false,
+            max_depth: None,
+            threads: thread::available_parallelism().map(|x| x.get()).unwrap_or(1),
+            value_separator: String::from(DEFAULT_VALUE_SEPARATOR),
+        }
+    }
+}
+
+impl FinderOptions {
+    /// Whether a file has one of extensions, the rest of its name may be invalid UTF-8
+    fn is_accepted(&self, path: &Path) -> bool {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some(extension) => self.extensions.iter().any(|x| x.eq_ignore_ascii_case(extension)),
+            None => false,
+        }
+    }
+
+    fn can_descend(&self, depth: usize) -> bool {
+        self.recursive && self.max_depth.map(|x| depth < x).unwrap_or(true)
+    }
+}
+
+/// Reads tags of files found in a directory, ordered by disc and track numbers and then by paths
+///
+/// Names of files aren't required to be valid UTF-8, only extensions are,
+/// such files are found like others and their names are printed lossily.
+pub fn find(path: impl AsRef<Path>, options: &FinderOptions) -> Result<Vec<FileInput>, FindError> {
+    let mut paths = Vec::new();
+    find_in(path.as_ref(), options, 0, &mut paths)?;
+    let mut result = read_all(&paths, options)?;
     if result.is_empty() {
         Err(FindError::NoTracks)
     } else {
+        result.sort_by(compare_inputs);
         Ok(result)
     }
 }
 
+/// Reads tags of listed files, ordered like files found in a directory
+pub fn read_files(paths: &[PathBuf], options: &FinderOptions) -> Result<Vec<FileInput>, FindError> {
+    let paths = paths
+        .iter()
+        .map(|path| match Format::from_path(path) {
+            Some(format) => Ok((path.clone(), format)),
+            None => Err(FindError::UnsupportedFormat(path.clone())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut result = read_all(&paths, options)?;
+    if result.is_empty() {
+        Err(FindError::NoTracks)
+    } else {
+        result.sort_by(compare_inputs);
+        Ok(result)
+    }
+}
+
+/// Reads tags of files, files are split between threads
+///
+/// Results keep the order of paths and a failure of the first failed path is returned.
+fn read_all(paths: &[(PathBuf, Format)], options: &FinderOptions) -> Result<Vec<FileInput>, FindError> {
+    let threads = options.threads;
+    let read = |(path, format): &(PathBuf, Format)| {
+        FileInput::from_path(path, *format)
+            .map(|x| x.join_values(&options.value_separator))
+            .map_err(|err| FindError::ReadTags(path.clone(), err))
+    };
+    if threads <= 1 || paths.len() < 2 {
+        return paths.iter().map(read).collect();
+    }
+    let chunk_size = paths.len().div_ceil(threads);
+    thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(read).collect::<Vec<_>>()))
+            .collect();
+        let mut result = Vec::with_capacity(paths.len());
+        for handle in handles {
+            result.extend(handle.join().unwrap_or_else(|err| panic::resume_unwind(err)));
+        }
+        result.into_iter().collect()
+    })
+}
+
+/// Orders files by disc and track numbers, then by path
+///
+/// A file without a disc number belongs to the first disc, files without track numbers go after numbered files
+/// of their disc. Paths are compared naturally (`2` goes before `10`).
+fn compare_inputs(a: &FileInput, b: &FileInput) -> Ordering {
+    let key = |x: &FileInput| (x.disc_number.unwrap_or(1), x.track_number.is_none(), x.track_number);
+    key(a)
+        .cmp(&key(b))
+        .then_with(|| natural_cmp(&a.path.to_string_lossy(), &b.path.to_string_lossy()))
+}
+
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chunks = Chunks::new(a);
+    let mut b_chunks = Chunks::new(b);
+    loop {
+        let ordering = match (a_chunks.next(), b_chunks.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match (x.as_bytes()[0].is_ascii_digit(), y.as_bytes()[0].is_ascii_digit()) {
+                (true, true) => {
+                    let x = x.trim_start_matches('0');
+                    let y = y.trim_start_matches('0');
+                    x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+                }
+                _ => x.to_lowercase().cmp(&y.to_lowercase()),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Splits a string into runs of ASCII digits and runs of other characters
+struct Chunks<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Chunks<'a> {
+    fn new(value: &'a str) -> Self {
+        Self { rest: value }
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let is_digit = self.rest.as_bytes().first()?.is_ascii_digit();
+        let len = self
+            .rest
+            .find(|x: char| x.is_ascii_digit() != is_digit)
+            .unwrap_or(self.rest.len());
+        let (chunk, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Some(chunk)
+    }
+}
+
+/// Collects paths of accepted files and their formats
+fn find_in(
+    path: &Path,
+    options: &FinderOptions,
+    depth: usize,
+    result: &mut Vec<(PathBuf, Format)>,
+) -> Result<(), FindError> {
+    for entry in fs::read_dir(path).map_err(|err| FindError::ReadDir(path.to_owned(), err))? {
+        let entry = entry.map_err(FindError::ReadEntry)?;
+        let entry_path = entry.path();
+        if entry.file_type().map_err(FindError::ReadEntry)?.is_dir() {
+            if options.can_descend(depth) {
+                find_in(&entry_path, options, depth + 1, result)?;
+            }
+            continue;
+        }
+        if !entry_path.is_file() || !options.is_accepted(&entry_path) {
+            continue;
+        }
+        if let Some(format) = Format::from_path(&entry_path) {
+            result.push((entry_path, format));
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum FindError {
     NoTracks,
     ReadDir(PathBuf, IoError),
     ReadEntry(IoError),
+    ReadTags(PathBuf, Box<dyn Error + Send + Sync>),
+    UnsupportedFormat(PathBuf),
 }
 
 impl fmt::Display for FindError {
@@ -48,6 +267,8 @@ impl fmt::Display for FindError {
             NoTracks => write!(out, "no tracks found"),
             ReadDir(path, err) => write!(out, "failed to read a directory {}: {}", path.display(), err),
             ReadEntry(err) => write!(out, "failed to read an entry: {}", err),
+            ReadTags(path, err) => write!(out, "failed to read tags from {}: {}", path.display(), err),
+            UnsupportedFormat(path) => write!(out, "unsupported file format: {}", path.display()),
         }
     }
 }
@@ -59,6 +280,68 @@ impl Error for FindError {
             NoTracks => return None,
             ReadDir(_, err) => err,
             ReadEntry(err) => err,
+            ReadTags(_, err) => err.as_ref(),
+            UnsupportedFormat(_) => return None,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(name: &str, disc_number: Option<u32>, track_number: Option<u32>) -> FileInput {
+        FileInput {
+            disc_number,
+            track_number,
+            ..FileInput::empty(name, Format::Mp3)
+        }
+    }
+
+    #[test]
+    fn orders_inputs_by_numbers() {
+        let mut inputs = [
+            input("a", None, None),
+            input("b", Some(2), Some(1)),
+            input("c", None, Some(5)),
+            input("d", Some(1), Some(1)),
+            input("e", Some(2), None),
+            input("track 10", None, None),
+            input("track 2", None, None),
+        ];
+        inputs.sort_by(compare_inputs);
+        let names: Vec<_> = inputs.iter().map(|x| x.path.to_str().unwrap()).collect();
+        assert_eq!(names, ["d", "c", "a", "track 2", "track 10", "b", "e"]);
+    }
+
+    #[test]
+    fn detects_format_regardless_of_case() {
+        assert_eq!(Format::from_path(Path::new("Song.MP3")), Some(Format::Mp3));
+        assert_eq!(Format::from_path(Path::new("Song.Flac")), Some(Format::Flac));
+        assert_eq!(Format::from_path(Path::new("Song.m4a")), Some(Format::M4a));
+        assert_eq!(Format::from_path(Path::new("Song.txt")), None);
+        assert_eq!(Format::from_path(Path::new("MP3")), None);
+        assert!(FinderOptions::default().is_accepted(Path::new("Song.MP3")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn finds_non_utf8_file_names() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt, process};
+
+        let dir = std::env::temp_dir().join(format!("tagg-{}-non-utf8", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(OsStr::from_bytes(b"01 \xff.mp3"));
+        fs::write(&path, b"\xff\xfbaudio").unwrap();
+        let options = FinderOptions {
+            threads: 1,
+            ..FinderOptions::default()
+        };
+        let result = find(&dir, &options);
+        fs::remove_dir_all(&dir).unwrap();
+        let inputs = result.unwrap();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].path, path);
+        assert!(!options.is_accepted(Path::new(OsStr::from_bytes(b"01.mp\xff"))));
+    }
+}
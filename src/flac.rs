@@ -0,0 +1,398 @@
+use crate::{
+    cover::Cover,
+    file::{FileInput, FileOutput, TagBackend, WriteOptions},
+    finder::Format,
+    vorbis::{VorbisComments, VorbisError},
+};
+use std::{
+    error::Error as StdError,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{Error as IoError, ErrorKind as IoErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::Path,
+    time::Duration,
+};
+
+const MAGIC: &[u8; 4] = b"fLaC";
+
+const BLOCK_STREAMINFO: u8 = 0;
+const BLOCK_PADDING: u8 = 1;
+const BLOCK_VORBIS_COMMENT: u8 = 4;
+const BLOCK_PICTURE: u8 = 6;
+const BLOCK_LAST_FLAG: u8 = 0x80;
+const BLOCK_HEADER_LEN: usize = 4;
+const BLOCK_MAX_LEN: usize = 0x00ff_ffff;
+/// Sample rate, channels, bits per sample and total samples of STREAMINFO
+const STREAMINFO_SAMPLES_RANGE: Range<usize> = 10..18;
+
+pub struct FlacBackend;
+
+impl TagBackend for FlacBackend {
+    fn read(&self, path: &Path) -> Result<FileInput, Box<dyn StdError + Send + Sync>> {
+        let mut file = File::open(path).map_err(FlacError::Io)?;
+        let metadata = Metadata::read(&mut file)?;
+        let mut input = metadata.comments()?.to_file_input(path, Format::Flac);
+        input.duration = metadata.duration();
+        Ok(input)
+    }
+
+    fn write(&self, path: &Path, output: &FileOutput, options: &WriteOptions) -> Result<(), Box<dyn StdError>> {
+        let mut comments = read_comments(path)?;
+        comments.set_file_output(output, options);
+        write_comments(path, &comments, output.keeps_pictures(), output.cover.as_deref())?;
+        Ok(())
+    }
+
+    /// Removes vorbis comments and pictures
+    fn clean(&self, path: &Path) -> Result<(), Box<dyn StdError>> {
+        write_comments(path, &VorbisComments::default(), false, None)?;
+        Ok(())
+    }
+}
+
+pub fn read_comments(path: impl AsRef<Path>) -> Result<VorbisComments, FlacError> {
+    let mut file = File::open(path).map_err(FlacError::Io)?;
+    Metadata::read(&mut file)?.comments()
+}
+
+/// Replaces the vorbis comment block of a FLAC file, keeping all other blocks intact
+///
+/// The file is updated in place when the new block fits into the space taken by the old one
+/// and the padding; otherwise the whole file is rewritten.
+/// Picture blocks are dropped when `keep_pictures` is false, a cover is added as a new picture block.
+pub fn write_comments(
+    path: impl AsRef<Path>,
+    comments: &VorbisComments,
+    keep_pictures: bool,
+    cover: Option<&Cover>,
+) -> Result<(), FlacError> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(FlacError::Io)?;
+    let metadata = Metadata::read(&mut file)?;
+    let comment = comments.serialize();
+    let mut blocks: Vec<Block> = metadata
+        .blocks
+        .into_iter()
+        .filter(|x| x.kind != BLOCK_VORBIS_COMMENT && x.kind != BLOCK_PADDING)
+        .filter(|x| keep_pictures || x.kind != BLOCK_PICTURE)
+        .collect();
+    blocks.insert(
+        1.min(blocks.len()),
+        Block {
+            kind: BLOCK_VORBIS_COMMENT,
+            data: comment,
+        },
+    );
+    if let Some(cover) = cover {
+        blocks.push(Block {
+            kind: BLOCK_PICTURE,
+            data: cover.to_picture_block(),
+        });
+    }
+    let used: usize = blocks.iter().map(|x| BLOCK_HEADER_LEN + x.data.len()).sum();
+    let available = (metadata.audio_offset - metadata.start) as usize - MAGIC.len();
+    let in_place = used == available
+        || (used + BLOCK_HEADER_LEN <= available && available - used - BLOCK_HEADER_LEN <= BLOCK_MAX_LEN);
+    if in_place {
+        if used < available {
+            blocks.push(Block {
+                kind: BLOCK_PADDING,
+                data: vec![0; available - used - BLOCK_HEADER_LEN],
+            });
+        }
+        let data = Metadata::serialize(&blocks)?;
+        file.seek(SeekFrom::Start(metadata.start)).map_err(FlacError::Io)?;
+        file.write_all(&data).map_err(FlacError::Io)?;
+    } else {
+        let mut audio = Vec::new();
+        file.seek(SeekFrom::Start(metadata.audio_offset))
+            .map_err(FlacError::Io)?;
+        file.read_to_end(&mut audio).map_err(FlacError::Io)?;
+        let data = Metadata::serialize(&blocks)?;
+        file.seek(SeekFrom::Start(metadata.start)).map_err(FlacError::Io)?;
+        file.write_all(&data).map_err(FlacError::Io)?;
+        file.write_all(&audio).map_err(FlacError::Io)?;
+        let len = file.stream_position().map_err(FlacError::Io)?;
+        file.set_len(len).map_err(FlacError::Io)?;
+    }
+    Ok(())
+}
+
+struct Block {
+    kind: u8,
+    data: Vec<u8>,
+}
+
+struct Metadata {
+    /// Offset of the `fLaC` marker (non-zero when the file is prefixed with an ID3v2 tag)
+    start: u64,
+    /// Offset of the first audio frame
+    audio_offset: u64,
+    blocks: Vec<Block>,
+}
+
+impl Metadata {
+    fn read(file: &mut File) -> Result<Self, FlacError> {
+        let start = skip_id3v2(file)?;
+        let mut magic = [0; 4];
+        read_exact(file, &mut magic)?;
+        if &magic != MAGIC {
+            return Err(FlacError::NotFlac);
+        }
+        let mut blocks = Vec::new();
+        let mut offset = start + MAGIC.len() as u64;
+        loop {
+            let mut header = [0; BLOCK_HEADER_LEN];
+            read_exact(file, &mut header)?;
+            let is_last = header[0] & BLOCK_LAST_FLAG != 0;
+            let kind = header[0] & !BLOCK_LAST_FLAG;
+            let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+            let mut data = vec![0; len];
+            read_exact(file, &mut data)?;
+            offset += (BLOCK_HEADER_LEN + len) as u64;
+            blocks.push(Block { kind, data });
+            if is_last {
+                break;
+            }
+        }
+        if blocks.first().map(|x| x.kind) != Some(BLOCK_STREAMINFO) {
+            return Err(FlacError::NoStreamInfo);
+        }
+        Ok(Self {
+            start,
+            audio_offset: offset,
+            blocks,
+        })
+    }
+
+    fn comments(&self) -> Result<VorbisComments, FlacError> {
+        match self.blocks.iter().find(|x| x.kind == BLOCK_VORBIS_COMMENT) {
+            Some(block) => VorbisComments::parse(&block.data).map_err(FlacError::Comments),
+            None => Ok(VorbisComments::default()),
+        }
+    }
+
+    /// Returns a duration computed from STREAMINFO, `None` when a number of samples is unknown
+    fn duration(&self) -> Option<Duration> {
+        let data = self.blocks.first()?.data.get(STREAMINFO_SAMPLES_RANGE)?;
+        let sample_rate = (u64::from(data[0]) << 12) | (u64::from(data[1]) << 4) | (u64::from(data[2]) >> 4);
+        let samples =
+            (u64::from(data[3] & 0x0f) << 32) | u64::from(u32::from_be_bytes([data[4], data[5], data[6], data[7]]));
+        if sample_rate == 0 || samples == 0 {
+            return None;
+        }
+        Some(Duration::from_micros(samples * 1_000_000 / sample_rate))
+    }
+
+    fn serialize(blocks: &[Block]) -> Result<Vec<u8>, FlacError> {
+        let mut data = Vec::from(&MAGIC[..]);
+        for (idx, block) in blocks.iter().enumerate() {
+            if block.data.len() > BLOCK_MAX_LEN {
+                return Err(FlacError::BlockTooLarge);
+            }
+            let mut kind = block.kind;
+            if idx + 1 == blocks.len() {
+                kind |= BLOCK_LAST_FLAG;
+            }
+            let len = (block.data.len() as u32).to_be_bytes();
+            data.extend_from_slice(&[kind, len[1], len[2], len[3]]);
+            data.extend_from_slice(&block.data);
+        }
+        Ok(data)
+    }
+}
+
+/// Returns an offset right after an ID3v2 tag, or zero when there is no such tag
+fn skip_id3v2(file: &mut File) -> Result<u64, FlacError> {
+    let mut header = [0; 10];
+    read_exact(file, &mut header)?;
+    let offset = if &header[0..3] == b"ID3" {
+        let size = header[6..10]
+            .iter()
+            .fold(0u64, |acc, x| (acc << 7) | u64::from(x & 0x7f));
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        10 + size + footer
+    } else {
+        0
+    };
+    file.seek(SeekFrom::Start(offset)).map_err(FlacError::Io)?;
+    Ok(offset)
+}
+
+fn read_exact(reader: &mut impl Read, buf: &mut [u8]) -> Result<(), FlacError> {
+    reader.read_exact(buf).map_err(|err| match err.kind() {
+        IoErrorKind::UnexpectedEof => FlacError::UnexpectedEof,
+        _ => FlacError::Io(err),
+    })
+}
+
+#[derive(Debug)]
+pub enum FlacError {
+    BlockTooLarge,
+    Comments(VorbisError),
+    Io(IoError),
+    NoStreamInfo,
+    NotFlac,
+    UnexpectedEof,
+}
+
+impl StdError for FlacError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::FlacError::*;
+        match self {
+            BlockTooLarge => None,
+            Comments(err) => Some(err),
+            Io(err) => Some(err),
+            NoStreamInfo => None,
+            NotFlac => None,
+            UnexpectedEof => None,
+        }
+    }
+}
+
+impl fmt::Display for FlacError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::FlacError::*;
+        match self {
+            BlockTooLarge => write!(out, "metadata block is too large"),
+            Comments(err) => write!(out, "{}", err),
+            Io(err) => write!(out, "{}", err),
+            NoStreamInfo => write!(out, "STREAMINFO block is missing"),
+            NotFlac => write!(out, "not a FLAC file"),
+            UnexpectedEof => write!(out, "unexpected end of file"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf, process};
+
+    const AUDIO: &[u8] = b"\xff\xf8 audio frames";
+
+    struct TestFile(PathBuf);
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn stream_info() -> Vec<u8> {
+        let mut data = vec![0; 34];
+        // 44100 Hz, 2 channels, 16 bits per sample and 88200 samples
+        data[10..18].copy_from_slice(&[0x0a, 0xc4, 0x42, 0xf0, 0x00, 0x01, 0x58, 0x88]);
+        data
+    }
+
+    fn title_comments(title: &str) -> VorbisComments {
+        let mut comments = VorbisComments::default();
+        comments.set("TITLE", title);
+        comments
+    }
+
+    /// Writes a file of the prefix, STREAMINFO, comments, a picture and padding blocks and audio frames
+    fn build_file(name: &str, prefix: &[u8], padding: usize) -> TestFile {
+        let blocks = [
+            Block {
+                kind: BLOCK_STREAMINFO,
+                data: stream_info(),
+            },
+            Block {
+                kind: BLOCK_VORBIS_COMMENT,
+                data: title_comments("Old title").serialize(),
+            },
+            Block {
+                kind: BLOCK_PICTURE,
+                data: b"picture".to_vec(),
+            },
+            Block {
+                kind: BLOCK_PADDING,
+                data: vec![0; padding],
+            },
+        ];
+        let mut data = prefix.to_vec();
+        data.extend(Metadata::serialize(&blocks).unwrap());
+        data.extend_from_slice(AUDIO);
+        let path = std::env::temp_dir().join(format!("tagg-{}-{}.flac", process::id(), name));
+        fs::write(&path, data).unwrap();
+        TestFile(path)
+    }
+
+    fn block_kinds(path: &Path) -> Vec<u8> {
+        let mut file = File::open(path).unwrap();
+        Metadata::read(&mut file)
+            .unwrap()
+            .blocks
+            .iter()
+            .map(|x| x.kind)
+            .collect()
+    }
+
+    #[test]
+    fn writes_comments_into_padding() {
+        let file = build_file("padding", &[], 1024);
+        let len = fs::metadata(&file.0).unwrap().len();
+        write_comments(&file.0, &title_comments("A much longer title"), true, None).unwrap();
+
+        assert_eq!(fs::metadata(&file.0).unwrap().len(), len);
+        assert_eq!(
+            read_comments(&file.0).unwrap().get("TITLE"),
+            Some("A much longer title")
+        );
+        assert_eq!(
+            block_kinds(&file.0),
+            vec![BLOCK_STREAMINFO, BLOCK_VORBIS_COMMENT, BLOCK_PICTURE, BLOCK_PADDING]
+        );
+        assert!(fs::read(&file.0).unwrap().ends_with(AUDIO));
+
+        let input = FlacBackend.read(&file.0).unwrap();
+        assert_eq!(input.duration, Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn rewrites_file_when_padding_is_too_small() {
+        let file = build_file("rewrite", &[], 2);
+        let len = fs::metadata(&file.0).unwrap().len();
+        let title = "t".repeat(100);
+        write_comments(&file.0, &title_comments(&title), true, None).unwrap();
+
+        let data = fs::read(&file.0).unwrap();
+        assert!(data.len() as u64 > len);
+        assert!(data.ends_with(AUDIO));
+        assert_eq!(read_comments(&file.0).unwrap().get("TITLE"), Some(title.as_str()));
+        assert_eq!(
+            block_kinds(&file.0),
+            vec![BLOCK_STREAMINFO, BLOCK_VORBIS_COMMENT, BLOCK_PICTURE]
+        );
+        let mut reader = File::open(&file.0).unwrap();
+        let metadata = Metadata::read(&mut reader).unwrap();
+        assert_eq!(&data[metadata.audio_offset as usize..], AUDIO);
+        assert_eq!(metadata.blocks[0].data, stream_info());
+    }
+
+    #[test]
+    fn cleans_tags_keeping_id3v2_prefix() {
+        // an empty ID3v2.4 tag with 4 bytes of padding
+        let prefix = b"ID3\x04\x00\x00\x00\x00\x00\x04\x00\x00\x00\x00";
+        let file = build_file("clean", prefix, 16);
+        let len = fs::metadata(&file.0).unwrap().len();
+        FlacBackend.clean(&file.0).unwrap();
+
+        let data = fs::read(&file.0).unwrap();
+        assert_eq!(data.len() as u64, len);
+        assert!(data.starts_with(prefix));
+        assert_eq!(&data[prefix.len()..prefix.len() + 4], MAGIC);
+        assert!(data.ends_with(AUDIO));
+        assert_eq!(
+            block_kinds(&file.0),
+            vec![BLOCK_STREAMINFO, BLOCK_VORBIS_COMMENT, BLOCK_PADDING]
+        );
+        assert_eq!(read_comments(&file.0).unwrap().get("TITLE"), None);
+    }
+}
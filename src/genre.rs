@@ -0,0 +1,248 @@
+/// Genres of ID3v1 with Winamp extensions, an index is a genre number, e.g. `17` is `Rock`
+pub const GENRES: [&str; 192] = [
+    "Blues",
+    "Classic Rock",
+    "Country",
+    "Dance",
+    "Disco",
+    "Funk",
+    "Grunge",
+    "Hip-Hop",
+    "Jazz",
+    "Metal",
+    "New Age",
+    "Oldies",
+    "Other",
+    "Pop",
+    "R&B",
+    "Rap",
+    "Reggae",
+    "Rock",
+    "Techno",
+    "Industrial",
+    "Alternative",
+    "Ska",
+    "Death Metal",
+    "Pranks",
+    "Soundtrack",
+    "Euro-Techno",
+    "Ambient",
+    "Trip-Hop",
+    "Vocal",
+    "Jazz+Funk",
+    "Fusion",
+    "Trance",
+    "Classical",
+    "Instrumental",
+    "Acid",
+    "House",
+    "Game",
+    "Sound Clip",
+    "Gospel",
+    "Noise",
+    "Alternative Rock",
+    "Bass",
+    "Soul",
+    "Punk",
+    "Space",
+    "Meditative",
+    "Instrumental Pop",
+    "Instrumental Rock",
+    "Ethnic",
+    "Gothic",
+    "Darkwave",
+    "Techno-Industrial",
+    "Electronic",
+    "Pop-Folk",
+    "Eurodance",
+    "Dream",
+    "Southern Rock",
+    "Comedy",
+    "Cult",
+    "Gangsta",
+    "Top 40",
+    "Christian Rap",
+    "Pop/Funk",
+    "Jungle",
+    "Native US",
+    "Cabaret",
+    "New Wave",
+    "Psychadelic",
+    "Rave",
+    "Showtunes",
+    "Trailer",
+    "Lo-Fi",
+    "Tribal",
+    "Acid Punk",
+    "Acid Jazz",
+    "Polka",
+    "Retro",
+    "Musical",
+    "Rock & Roll",
+    "Hard Rock",
+    "Folk",
+    "Folk-Rock",
+    "National Folk",
+    "Swing",
+    "Fast Fusion",
+    "Bebob",
+    "Latin",
+    "Revival",
+    "Celtic",
+    "Bluegrass",
+    "Avantgarde",
+    "Gothic Rock",
+    "Progressive Rock",
+    "Psychedelic Rock",
+    "Symphonic Rock",
+    "Slow Rock",
+    "Big Band",
+    "Chorus",
+    "Easy Listening",
+    "Acoustic",
+    "Humour",
+    "Speech",
+    "Chanson",
+    "Opera",
+    "Chamber Music",
+    "Sonata",
+    "Symphony",
+    "Booty Bass",
+    "Primus",
+    "Porn Groove",
+    "Satire",
+    "Slow Jam",
+    "Club",
+    "Tango",
+    "Samba",
+    "Folklore",
+    "Ballad",
+    "Power Ballad",
+    "Rhytmic Soul",
+    "Freestyle",
+    "Duet",
+    "Punk Rock",
+    "Drum Solo",
+    "Acapella",
+    "Euro-House",
+    "Dance Hall",
+    "Goa",
+    "Drum & Bass",
+    "Club-House",
+    "Hardcore",
+    "Terror",
+    "Indie",
+    "BritPop",
+    "Negerpunk",
+    "Polsk Punk",
+    "Beat",
+    "Christian Gangsta",
+    "Heavy Metal",
+    "Black Metal",
+    "Crossover",
+    "Contemporary C",
+    "Christian Rock",
+    "Merengue",
+    "Salsa",
+    "Thrash Metal",
+    "Anime",
+    "JPop",
+    "SynthPop",
+    "Abstract",
+    "Art Rock",
+    "Baroque",
+    "Bhangra",
+    "Big Beat",
+    "Breakbeat",
+    "Chillout",
+    "Downtempo",
+    "Dub",
+    "EBM",
+    "Eclectic",
+    "Electro",
+    "Electroclash",
+    "Emo",
+    "Experimental",
+    "Garage",
+    "Global",
+    "IDM",
+    "Illbient",
+    "Industro-Goth",
+    "Jam Band",
+    "Krautrock",
+    "Leftfield",
+    "Lounge",
+    "Math Rock",
+    "New Romantic",
+    "Nu-Breakz",
+    "Post-Punk",
+    "Post-Rock",
+    "Psytrance",
+    "Shoegaze",
+    "Space Rock",
+    "Trop Rock",
+    "World Music",
+    "Neoclassical",
+    "Audiobook",
+    "Audio Theatre",
+    "Neue Deutsche Welle",
+    "Podcast",
+    "Indie Rock",
+    "G-Funk",
+    "Dubstep",
+    "Garage Rock",
+    "Psybient",
+];
+
+/// Returns a genre of the standard list matching a value case-insensitively, e.g. `Hip-Hop` for `hip-hop`
+pub fn find_genre(value: &str) -> Option<&'static str> {
+    GENRES.iter().find(|x| x.eq_ignore_ascii_case(value.trim())).copied()
+}
+
+/// Returns a number of a genre of the standard list
+pub fn genre_number(value: &str) -> Option<u8> {
+    GENRES
+        .iter()
+        .position(|x| x.eq_ignore_ascii_case(value.trim()))
+        .map(|x| x as u8)
+}
+
+/// Decodes references to genre numbers of ID3v2 `TCON`, e.g. `(17)`, `(17)Rock` or `17` give `Rock`
+///
+/// A refinement following a reference wins, `(RX)` and `(CR)` stand for `Remix` and `Cover`.
+/// Other values are returned as is.
+pub fn decode_tcon(value: &str) -> String {
+    let value = value.trim();
+    if let Ok(number) = value.parse::<usize>() {
+        if let Some(genre) = GENRES.get(number) {
+            return (*genre).to_owned();
+        }
+    }
+    let mut rest = value;
+    let mut genre = None;
+    while let Some(reference) = rest.strip_prefix('(') {
+        // "((" escapes a refinement starting with a bracket
+        if reference.starts_with('(') {
+            break;
+        }
+        let end = match reference.find(')') {
+            Some(end) => end,
+            None => break,
+        };
+        genre = genre.or(match &reference[..end] {
+            "RX" => Some("Remix"),
+            "CR" => Some("Cover"),
+            number => number.parse::<usize>().ok().and_then(|x| GENRES.get(x).copied()),
+        });
+        rest = &reference[end + 1..];
+    }
+    match (rest.trim(), genre) {
+        ("", Some(genre)) => genre.to_owned(),
+        ("", None) => value.to_owned(),
+        (refinement, _) => refinement
+            .strip_prefix('(')
+            .filter(|x| x.starts_with('('))
+            .unwrap_or(refinement)
+            .to_owned(),
+    }
+}
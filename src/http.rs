@@ -0,0 +1,68 @@
+//! GET requests made with curl, so no HTTP client has to be linked in
+
+use std::{error::Error as StdError, fmt, io::Error as IoError, process::Command, string::FromUtf8Error};
+
+/// Identifies requests to web services, MusicBrainz asks for a name, a version and a contact
+pub const USER_AGENT: &str = concat!(
+    "tagg/",
+    env!("CARGO_PKG_VERSION"),
+    " ( https://github.com/rossnomann/tagg )"
+);
+
+/// Maximum time of a request in seconds
+const TIMEOUT: &str = "15";
+
+/// Returns a body of a response, a status other than 2xx is an error
+pub fn get(url: &str) -> Result<String, HttpError> {
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--max-time", TIMEOUT, "--user-agent", USER_AGENT, "--url", url])
+        .output()
+        .map_err(HttpError::RunCurl)?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(HttpError::Request(message));
+    }
+    String::from_utf8(output.stdout).map_err(HttpError::Body)
+}
+
+/// Percent-encodes a value of a query string
+pub fn encode_query(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => result.push(char::from(byte)),
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+#[derive(Debug)]
+pub enum HttpError {
+    Body(FromUtf8Error),
+    Request(String),
+    RunCurl(IoError),
+}
+
+impl StdError for HttpError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::HttpError::*;
+        match self {
+            Body(err) => Some(err),
+            Request(_) => None,
+            RunCurl(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::HttpError::*;
+        match self {
+            Body(err) => write!(out, "response is not UTF-8: {}", err),
+            Request(message) => write!(out, "request failed: {}", message),
+            RunCurl(err) => write!(out, "failed to run curl: {}", err),
+        }
+    }
+}
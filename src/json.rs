@@ -0,0 +1,358 @@
+use std::{char, error::Error as StdError, fmt, str::FromStr};
+
+/// A JSON value, objects keep the order of keys
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_owned())
+    }
+}
+
+impl From<u32> for Json {
+    fn from(value: u32) -> Self {
+        Json::Number(f64::from(value))
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Json::Null)
+    }
+}
+
+impl Json {
+    /// Returns a value of an object key, `None` for a missing key or a value other than an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(items) => items.iter().find(|(x, _)| x == key).map(|(_, x)| x),
+            _ => None,
+        }
+    }
+
+    fn write(&self, out: &mut fmt::Formatter, indent: Option<usize>) -> fmt::Result {
+        match self {
+            Json::Null => write!(out, "null"),
+            Json::Bool(value) => write!(out, "{}", value),
+            Json::Number(value) => write!(out, "{}", value),
+            Json::String(value) => write_string(out, value),
+            Json::Array(items) => {
+                write!(out, "[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    write_separator(out, idx, indent.map(|x| x + 1))?;
+                    item.write(out, indent.map(|x| x + 1))?;
+                }
+                write_end(out, items.is_empty(), indent)?;
+                write!(out, "]")
+            }
+            Json::Object(items) => {
+                write!(out, "{{")?;
+                for (idx, (key, value)) in items.iter().enumerate() {
+                    write_separator(out, idx, indent.map(|x| x + 1))?;
+                    write_string(out, key)?;
+                    write!(out, "{}", if indent.is_some() { ": " } else { ":" })?;
+                    value.write(out, indent.map(|x| x + 1))?;
+                }
+                write_end(out, items.is_empty(), indent)?;
+                write!(out, "}}")
+            }
+        }
+    }
+}
+
+/// Writes a compact value, the alternate form (`{:#}`) is indented with two spaces
+impl fmt::Display for Json {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        let indent = if out.alternate() { Some(0) } else { None };
+        self.write(out, indent)
+    }
+}
+
+fn write_separator(out: &mut fmt::Formatter, idx: usize, indent: Option<usize>) -> fmt::Result {
+    if idx > 0 {
+        write!(out, ",")?;
+    }
+    if let Some(indent) = indent {
+        write!(out, "\n{:width$}", "", width = indent * 2)?;
+    }
+    Ok(())
+}
+
+fn write_end(out: &mut fmt::Formatter, is_empty: bool, indent: Option<usize>) -> fmt::Result {
+    match indent {
+        Some(indent) if !is_empty => write!(out, "\n{:width$}", "", width = indent * 2),
+        _ => Ok(()),
+    }
+}
+
+fn write_string(out: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    write!(out, "\"")?;
+    for x in value.chars() {
+        match x {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            x if x.is_control() => write!(out, "\\u{:04x}", u32::from(x))?,
+            x => write!(out, "{}", x)?,
+        }
+    }
+    write!(out, "\"")
+}
+
+impl FromStr for Json {
+    type Err = JsonError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            value,
+            pos: 0,
+            depth: 0,
+        };
+        let result = parser.parse_value()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            Some(x) => Err(JsonError::UnexpectedChar(x, parser.pos)),
+            None => Ok(result),
+        }
+    }
+}
+
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    value: &'a str,
+    /// Byte offset of the next character
+    pos: usize,
+    /// Number of arrays and objects being parsed
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.value[self.pos..].chars().next()
+    }
+
+    fn next_char(&mut self) -> Result<char, JsonError> {
+        let x = self.peek().ok_or(JsonError::UnexpectedEnd)?;
+        self.pos += x.len_utf8();
+        Ok(x)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        let pos = self.pos;
+        match self.next_char()? {
+            x if x == expected => Ok(()),
+            x => Err(JsonError::UnexpectedChar(x, pos)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.value[self.pos..];
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn parse_value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        match self.peek().ok_or(JsonError::UnexpectedEnd)? {
+            'n' => self.parse_literal("null", Json::Null),
+            't' => self.parse_literal("true", Json::Bool(true)),
+            'f' => self.parse_literal("false", Json::Bool(false)),
+            '"' => self.parse_string().map(Json::String),
+            '[' => self.parse_nested(Self::parse_array),
+            '{' => self.parse_nested(Self::parse_object),
+            '-' | '0'..='9' => self.parse_number(),
+            x => Err(JsonError::UnexpectedChar(x, self.pos)),
+        }
+    }
+
+    fn parse_nested(&mut self, parse: fn(&mut Self) -> Result<Json, JsonError>) -> Result<Json, JsonError> {
+        if self.depth == MAX_DEPTH {
+            return Err(JsonError::TooDeep(self.pos));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, JsonError> {
+        if self.value[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            Err(JsonError::InvalidLiteral(self.pos))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        let rest = &self.value[start..];
+        let len = rest
+            .find(|x: char| !(x.is_ascii_digit() || ['-', '+', '.', 'e', 'E'].contains(&x)))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        rest[..len]
+            .parse()
+            .map(Json::Number)
+            .map_err(|_| JsonError::InvalidNumber(start))
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut result = String::new();
+        loop {
+            let pos = self.pos;
+            match self.next_char()? {
+                '"' => return Ok(result),
+                '\\' => match self.next_char()? {
+                    '"' => result.push('"'),
+                    '\\' => result.push('\\'),
+                    '/' => result.push('/'),
+                    'b' => result.push('\u{8}'),
+                    'f' => result.push('\u{c}'),
+                    'n' => result.push('\n'),
+                    'r' => result.push('\r'),
+                    't' => result.push('\t'),
+                    'u' => result.push(self.parse_unicode_escape(pos)?),
+                    _ => return Err(JsonError::InvalidEscape(pos)),
+                },
+                x if x.is_control() => return Err(JsonError::UnexpectedChar(x, pos)),
+                x => result.push(x),
+            }
+        }
+    }
+
+    /// Parses hex digits of `\uXXXX`, a surrogate pair takes two escapes
+    fn parse_unicode_escape(&mut self, start: usize) -> Result<char, JsonError> {
+        let high = self.parse_hex(start)?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or(JsonError::InvalidEscape(start));
+        }
+        if !self.value[self.pos..].starts_with("\\u") {
+            return Err(JsonError::InvalidEscape(start));
+        }
+        self.pos += 2;
+        let low = self.parse_hex(start)?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(JsonError::InvalidEscape(start));
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)).ok_or(JsonError::InvalidEscape(start))
+    }
+
+    fn parse_hex(&mut self, start: usize) -> Result<u32, JsonError> {
+        let digits = self
+            .value
+            .get(self.pos..self.pos + 4)
+            .ok_or(JsonError::InvalidEscape(start))?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| JsonError::InvalidEscape(start))?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn parse_array(&mut self) -> Result<Json, JsonError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.parse_value()?);
+            self.skip_whitespace();
+            let pos = self.pos;
+            match self.next_char()? {
+                ',' => continue,
+                ']' => return Ok(Json::Array(items)),
+                x => return Err(JsonError::UnexpectedChar(x, pos)),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Json, JsonError> {
+        self.expect('{')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(items));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            items.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            let pos = self.pos;
+            match self.next_char()? {
+                ',' => continue,
+                '}' => return Ok(Json::Object(items)),
+                x => return Err(JsonError::UnexpectedChar(x, pos)),
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum JsonError {
+    InvalidEscape(usize),
+    InvalidLiteral(usize),
+    InvalidNumber(usize),
+    TooDeep(usize),
+    UnexpectedChar(char, usize),
+    UnexpectedEnd,
+}
+
+impl StdError for JsonError {}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::JsonError::*;
+        match self {
+            InvalidEscape(pos) => write!(out, "invalid escape sequence at {}", pos),
+            InvalidLiteral(pos) => write!(out, "invalid literal at {}", pos),
+            InvalidNumber(pos) => write!(out, "invalid number at {}", pos),
+            TooDeep(pos) => write!(out, "nesting is too deep at {}", pos),
+            UnexpectedChar(x, pos) => write!(out, "unexpected character {:?} at {}", x, pos),
+            UnexpectedEnd => write!(out, "unexpected end of input"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_values() {
+        let value: Json = r#"{"a": [1, {"b": [null, true]}]}"#.parse().unwrap();
+        assert!(matches!(value, Json::Object(_)));
+        let nested = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(nested.parse::<Json>().is_ok());
+    }
+
+    #[test]
+    fn rejects_too_deep_nesting() {
+        let nested = format!("{}{}", "[".repeat(MAX_DEPTH + 1), "]".repeat(MAX_DEPTH + 1));
+        assert!(matches!(nested.parse::<Json>(), Err(JsonError::TooDeep(_))));
+        assert!(matches!(
+            "[".repeat(200_000).parse::<Json>(),
+            Err(JsonError::TooDeep(_))
+        ));
+        assert!(matches!(
+            "{\"a\":".repeat(200_000).parse::<Json>(),
+            Err(JsonError::TooDeep(_))
+        ));
+    }
+}
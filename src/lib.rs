@@ -1,8 +1,56 @@
+//! A tool to manage a music collection
+//!
+//! The binary is a thin interactive layer ([`app`]) on top of the library.
+//! Stable entry points for other programs are:
+//!
+//! - [`find`] with [`FinderOptions`] to collect [`FileInput`] of an album directory;
+//! - [`AlbumInput::from_file_input`] to infer album-wide values from the most common tags;
+//! - [`FileOutput`] built from [`FileInput`], [`AlbumOutput`] and [`TrackOutput`],
+//!   written with [`FileOutput::write`] and [`WriteOptions`];
+//! - [`Counter`] to pick the most common value of a key.
+//!
+//! Editors ([`album::AlbumEditor`], [`track::TrackEditor`]) read from a terminal
+//! and are only useful for interactive programs.
+
+#[cfg(feature = "acoustid")]
+pub mod acoustid;
+pub mod album;
 pub mod app;
+pub mod case;
+pub mod config;
+pub mod counter;
+pub mod cover;
+pub mod csv;
+pub mod date;
+pub mod editor;
+pub mod exchange;
+pub mod featured;
+pub mod file;
+pub mod finder;
+pub mod genre;
+pub mod http;
+pub mod json;
+pub mod manifest;
+pub mod musicbrainz;
+pub mod playlist;
+pub mod template;
+pub mod track;
+pub mod validate;
+
+mod flac;
+mod mp3;
+mod mp4;
+mod mpeg;
+mod nfc;
+mod ogg;
+mod vorbis;
 
-mod album;
-mod counter;
-mod editor;
-mod file;
-mod finder;
-mod track;
+pub use self::{
+    album::{AlbumInput, AlbumOutput},
+    counter::Counter,
+    date::Date,
+    file::{Backup, FileInput, FileOutput, TagBackend, WriteOptions},
+    finder::{find, FinderOptions, Format},
+    template::FilenameTemplate,
+    track::{TrackInput, TrackOutput},
+};
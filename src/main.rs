@@ -1,7 +1,21 @@
+use std::process;
 use tagg::app;
 
 fn main() {
-    if let Err(err) = app::run() {
-        println!("{}", err);
+    match app::run() {
+        Ok(summary) => {
+            print!("{}", summary);
+            if let Some(ref err) = summary.error {
+                eprintln!("{}", err);
+            }
+            // albums or files skipped because of errors, or the run stopped
+            if !summary.errors.is_empty() || summary.error.is_some() {
+                process::exit(1);
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
     }
 }
@@ -0,0 +1,302 @@
+//! A journal of a tagging run: original tags and paths of written files,
+//! so the run can be undone by restoring tags and moving files back, or by removing tagged copies
+
+use crate::{
+    date::Date,
+    exchange,
+    file::{FileInput, FileOutput},
+    finder::Format,
+    json::{Json, JsonError},
+};
+use std::{
+    convert::TryFrom,
+    error::Error as StdError,
+    fmt, fs,
+    io::Error as IoError,
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Number of manifests kept in the default directory, older ones are removed
+pub const KEPT_MANIFESTS: usize = 50;
+
+const EXTENSION: &str = "json";
+
+/// Written files of a run, in the order of writing
+#[derive(Debug, Default)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// A written file
+#[derive(Debug)]
+pub struct ManifestEntry {
+    /// Tags read before writing, the path is the original path of the file
+    pub original: FileInput,
+    /// A path the file was moved to
+    pub new_path: PathBuf,
+    /// Whether the file was copied to the new path, undoing removes the copy and leaves the original alone
+    pub copy: bool,
+}
+
+impl Manifest {
+    /// Returns a directory manifests are recorded to by default (e.g. `~/.local/share/tagg/manifests`)
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|x| x.join("tagg").join("manifests"))
+    }
+
+    /// Returns a path of a new manifest in the default directory (e.g. `1700000000-42.json`)
+    pub fn default_path() -> Option<PathBuf> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or(0);
+        let name = format!("{}-{}.{}", timestamp, process::id(), EXTENSION);
+        Self::default_dir().map(|x| x.join(name))
+    }
+
+    /// Removes the oldest manifests of a directory, so at most `keep` manifests are left
+    ///
+    /// Only files named like default manifests are removed, other files of the directory are never touched.
+    pub fn prune(dir: &Path, keep: usize) -> Result<(), IoError> {
+        let mut manifests = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if let Some(key) = default_name_key(&path) {
+                manifests.push((key, path));
+            }
+        }
+        manifests.sort();
+        let len = manifests.len().saturating_sub(keep);
+        for (_, path) in manifests.drain(..len) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, ManifestError> {
+        let data = fs::read_to_string(path).map_err(ManifestError::Read)?;
+        Self::parse(&data)
+    }
+
+    /// Writes the manifest as a whole, creating missing directories
+    pub fn write(&self, path: &Path) -> Result<(), ManifestError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(ManifestError::Write)?;
+        }
+        fs::write(path, format!("{:#}\n", self.to_json())).map_err(ManifestError::Write)
+    }
+
+    /// Returns a JSON array of exported tags (see [`exchange::to_json`]) with `new_path`, `copy` and `compilation` keys
+    pub fn to_json(&self) -> Json {
+        let entries = self.entries.iter().map(|entry| {
+            let mut value = exchange::file_input_to_json(&entry.original);
+            if let Json::Object(ref mut items) = value {
+                items.push((
+                    String::from("compilation"),
+                    entry.original.compilation.map(Json::Bool).unwrap_or(Json::Null),
+                ));
+                items.push((
+                    String::from("new_path"),
+                    Json::from(entry.new_path.to_string_lossy().as_ref()),
+                ));
+                items.push((String::from("copy"), Json::Bool(entry.copy)));
+            }
+            value
+        });
+        Json::Array(entries.collect())
+    }
+
+    pub fn parse(data: &str) -> Result<Self, ManifestError> {
+        let entries = match data.parse().map_err(ManifestError::Json)? {
+            Json::Array(entries) => entries,
+            _ => return Err(ManifestError::NotArray),
+        };
+        let entries = entries
+            .iter()
+            .enumerate()
+            .map(|(idx, x)| parse_entry(x).ok_or(ManifestError::InvalidEntry(idx + 1)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { entries })
+    }
+}
+
+impl ManifestEntry {
+    /// Returns an output restoring original tags of the file at its new path
+    ///
+    /// Missing values are left empty, so they are not written.
+    pub fn restore_output(&self) -> FileOutput {
+        let original = &self.original;
+        FileOutput {
+            path: self.new_path.clone(),
+            format: original.format,
+            artist: original.artist.clone().unwrap_or_default(),
+            album_artist: original.album_artist.clone().unwrap_or_default(),
+            album: original.album.clone().unwrap_or_default(),
+            date: original.date.unwrap_or_else(|| Date::from_year(0)),
+            genre: original.genre.clone(),
+            grouping: original.grouping.clone(),
+            title: original.title.clone().unwrap_or_default(),
+            subtitle: original.subtitle.clone(),
+            composer: original.composer.clone(),
+            comment: original.comment.clone(),
+            lyrics: original.lyrics.clone(),
+            bpm: original.bpm,
+            rating: original.rating,
+            isrc: original.isrc.clone(),
+            track_number: original.track_number.unwrap_or(0),
+            total_tracks: original.total_tracks,
+            disc_number: original.disc_number.unwrap_or(0),
+            total_discs: original.total_discs,
+            disc_subtitle: original.disc_subtitle.clone(),
+            compilation: original.compilation.unwrap_or(false),
+            keep_pictures: true,
+            cover: None,
+            number_width: Some(1),
+        }
+    }
+}
+
+/// Returns a timestamp and a process ID of a name of a default manifest, `None` for other files
+fn default_name_key(path: &Path) -> Option<(u64, u32)> {
+    if path.extension()? != EXTENSION {
+        return None;
+    }
+    let (timestamp, pid) = path.file_stem()?.to_str()?.split_once('-')?;
+    Some((timestamp.parse().ok()?, pid.parse().ok()?))
+}
+
+fn parse_entry(value: &Json) -> Option<ManifestEntry> {
+    let text = |key: &str| match value.get(key) {
+        Some(Json::String(value)) => Some(Some(value.clone())),
+        Some(Json::Null) | None => Some(None),
+        Some(_) => None,
+    };
+    let number = |key: &str| match value.get(key) {
+        Some(Json::Number(value)) if value.fract() == 0.0 && *value >= 0.0 => Some(Some(*value as u32)),
+        Some(Json::Null) | None => Some(None),
+        Some(_) => None,
+    };
+    let path = PathBuf::from(text("path")??);
+    let format = Format::from_path(&path)?;
+    let date = match text("date")? {
+        Some(date) => Some(date.parse().ok()?),
+        None => None,
+    };
+    let compilation = match value.get("compilation") {
+        Some(Json::Bool(value)) => Some(*value),
+        Some(Json::Null) | None => None,
+        Some(_) => return None,
+    };
+    // manifests written before copies were recorded have no flag
+    let copy = match value.get("copy") {
+        Some(Json::Bool(value)) => *value,
+        Some(Json::Null) | None => false,
+        Some(_) => return None,
+    };
+    let original = FileInput {
+        artist: text("artist")?,
+        album_artist: text("album_artist")?,
+        album: text("album")?,
+        date,
+        genre: text("genre")?,
+        grouping: text("grouping")?,
+        title: text("title")?,
+        subtitle: text("subtitle")?,
+        composer: text("composer")?,
+        comment: text("comment")?,
+        lyrics: text("lyrics")?,
+        bpm: number("bpm")?,
+        rating: number("rating")?.map(u8::try_from).transpose().ok()?,
+        isrc: text("isrc")?,
+        track_number: number("track_number")?,
+        total_tracks: number("total_tracks")?,
+        disc_number: number("disc_number")?,
+        total_discs: number("total_discs")?,
+        disc_subtitle: text("disc_subtitle")?,
+        compilation,
+        ..FileInput::empty(path, format)
+    };
+    Some(ManifestEntry {
+        original,
+        new_path: PathBuf::from(text("new_path")??),
+        copy,
+    })
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    InvalidEntry(usize),
+    Json(JsonError),
+    NotArray,
+    Read(IoError),
+    Write(IoError),
+}
+
+impl StdError for ManifestError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::ManifestError::*;
+        match self {
+            InvalidEntry(_) => None,
+            Json(err) => Some(err),
+            NotArray => None,
+            Read(err) => Some(err),
+            Write(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::ManifestError::*;
+        match self {
+            InvalidEntry(idx) => write!(out, "invalid manifest entry {}", idx),
+            Json(err) => write!(out, "failed to parse manifest: {}", err),
+            NotArray => write!(out, "manifest is not an array"),
+            Read(err) => write!(out, "failed to read manifest: {}", err),
+            Write(err) => write!(out, "failed to write manifest: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_copies() {
+        let manifest = Manifest {
+            entries: vec![ManifestEntry {
+                original: FileInput::empty("/music/01.mp3", Format::Mp3),
+                new_path: PathBuf::from("/music/01 - Title.mp3"),
+                copy: true,
+            }],
+        };
+        let manifest = Manifest::parse(&manifest.to_json().to_string()).unwrap();
+        assert!(manifest.entries[0].copy);
+        assert_eq!(manifest.entries[0].new_path, Path::new("/music/01 - Title.mp3"));
+        // a manifest written before copies were recorded
+        let manifest = Manifest::parse(r#"[{"path": "/music/01.mp3", "new_path": "/music/02.mp3"}]"#).unwrap();
+        assert!(!manifest.entries[0].copy);
+    }
+
+    #[test]
+    fn prunes_oldest_manifests() {
+        let dir = std::env::temp_dir().join(format!("tagg-{}-manifests", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let names = ["10-1.json", "9-2.json", "11-1.json", "8-1.txt", "notes.json"];
+        for name in &names {
+            fs::write(dir.join(name), "[]").unwrap();
+        }
+        let result = Manifest::prune(&dir, 2);
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+        left.sort();
+        assert_eq!(left, ["10-1.json", "11-1.json", "8-1.txt", "notes.json"]);
+    }
+}
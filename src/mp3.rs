@@ -0,0 +1,900 @@
+use crate::{
+    date::Date,
+    file::{parse_bpm, split_values, FileInput, FileOutput, TagBackend, WriteOptions, VALUE_SEPARATOR},
+    finder::Format,
+    genre::{decode_tcon, genre_number},
+    mpeg,
+};
+use ape::Error as ApeError;
+use id3::{
+    frame::{Comment as Id3Comment, Lyrics as Id3Lyrics, Picture as Id3Picture, PictureType as Id3PictureType},
+    v1::Tag as Id3V1Tag,
+    Content as Id3FrameContent, Error as Id3Error, ErrorKind as Id3ErrorKind, Frame as Id3Frame, Tag as Id3V2Tag,
+    Version as Id3Version,
+};
+use std::{
+    convert::TryFrom,
+    error::Error as StdError,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{Error as IoError, ErrorKind as IoErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// Language of written comments and lyrics (ISO 639-2), a language of existing lyrics is kept
+const COMMENT_LANG: &str = "eng";
+
+/// Frames written from an editor output
+const MANAGED_FRAMES: [&str; 17] = [
+    "TALB", "TBPM", "TCMP", "TCOM", "TCON", "TDAT", "TDRC", "TIT1", "TIT2", "TIT3", "TPE1", "TPE2", "TPOS", "TRCK",
+    "TSRC", "TSST", "TYER",
+];
+
+/// Frames which only exist in ID3v2.4, kept frames are dropped from ID3v2.3 tags
+///
+/// `TIT1` (a grouping) means the same in both versions, so it is written to either.
+const ID3V24_FRAMES: [&str; 18] = [
+    "ASPI", "EQU2", "RVA2", "SEEK", "SIGN", "TDEN", "TDOR", "TDRC", "TDRL", "TDTG", "TIPL", "TMCL", "TMOO", "TPRO",
+    "TSOA", "TSOP", "TSOT", "TSST",
+];
+
+/// A description of a `TXXX` frame storing a date without a day in ID3v2.3, `TDAT` requires a day
+const TXXX_DATE: &str = "DATE";
+/// A description of a `TXXX` frame storing a disc subtitle in ID3v2.3, which lacks `TSST`
+const TXXX_DISC_SUBTITLE: &str = "SETSUBTITLE";
+
+const ID3V1_LEN: usize = 128;
+const ID3V2_HEADER_LEN: usize = 10;
+const ID3V2_FLAG_UNSYNCHRONISATION: u8 = 0x80;
+const ID3V2_FLAG_EXTENDED_HEADER: u8 = 0x40;
+const ID3V2_FLAG_FOOTER: u8 = 0x10;
+/// Compression, encryption, unsynchronisation and data length indicator flags of an ID3v2.4 frame
+const ID3V2_FRAME_FORMAT_FLAGS: u8 = 0x0f;
+const ID3V1_GENRE_UNKNOWN: u8 = 255;
+
+pub struct Mp3Backend;
+
+impl TagBackend for Mp3Backend {
+    fn read(&self, path: &Path) -> Result<FileInput, Box<dyn StdError + Send + Sync>> {
+        let mut file = File::open(path).map_err(Mp3Error::OpenFile)?;
+        let mut data = vec![0; id3v2_len(&mut file).map_err(Mp3Error::ReadTag)? as usize];
+        file.seek(SeekFrom::Start(0)).map_err(Mp3Error::Seek)?;
+        file.read_exact(&mut data).map_err(Mp3Error::ReadTag)?;
+        let audio_len = audio_len(&mut file, data.len() as u64).map_err(Mp3Error::ReadAudio)?;
+        let duration = mpeg::duration(&mut file, audio_len).map_err(Mp3Error::ReadAudio)?;
+        let tag = match Id3V2Tag::read_from(data.as_slice()) {
+            Ok(tag) => tag,
+            Err(Id3Error {
+                kind: Id3ErrorKind::NoTag,
+                ..
+            }) => {
+                return Ok(FileInput {
+                    duration,
+                    ..FileInput::empty(path, Format::Mp3)
+                });
+            }
+            Err(err) => return Err(Box::new(Mp3Error::ReadId3V2(err))),
+        };
+        let comment = tag
+            .comments()
+            .find(|x| x.description.is_empty())
+            .map(|x| x.text.clone());
+        let lyrics = tag.lyrics().find(|x| x.description.is_empty()).map(|x| x.text.clone());
+        let rating = tag
+            .frames()
+            .find_map(Popularimeter::from_frame)
+            .map(|x| popm_to_stars(x.rating))
+            .filter(|x| *x > 0);
+        Ok(FileInput {
+            path: path.to_owned(),
+            format: Format::Mp3,
+            artist: read_text_values(&data, b"TPE1").or_else(|| tag.artist().map(ToOwned::to_owned)),
+            album_artist: read_text_values(&data, b"TPE2").or_else(|| tag.album_artist().map(ToOwned::to_owned)),
+            album: tag.album().map(ToOwned::to_owned),
+            date: tag
+                .date_recorded()
+                .map(|x| Date {
+                    year: x.year,
+                    month: x.month,
+                    day: x.day,
+                })
+                .or_else(|| tag.year().map(|year| read_id3v23_date(&tag, year))),
+            genre: tag.genre().map(decode_tcon),
+            grouping: tag.get("TIT1").and_then(|x| x.content().text()).map(ToOwned::to_owned),
+            title: tag.title().map(ToOwned::to_owned),
+            subtitle: tag.get("TIT3").and_then(|x| x.content().text()).map(ToOwned::to_owned),
+            composer: tag.get("TCOM").and_then(|x| x.content().text()).map(ToOwned::to_owned),
+            comment,
+            lyrics,
+            rating,
+            isrc: tag.get("TSRC").and_then(|x| x.content().text()).map(ToOwned::to_owned),
+            bpm: tag.get("TBPM").and_then(|x| x.content().text()).and_then(parse_bpm),
+            track_number: tag.track(),
+            total_tracks: tag.total_tracks(),
+            disc_number: tag.disc(),
+            total_discs: tag.total_discs(),
+            disc_subtitle: tag
+                .get("TSST")
+                .and_then(|x| x.content().text())
+                .or_else(|| read_extended_text(&tag, TXXX_DISC_SUBTITLE))
+                .map(ToOwned::to_owned),
+            compilation: tag
+                .get("TCMP")
+                .and_then(|x| x.content().text())
+                .map(|x| x.trim() == "1"),
+            duration,
+        })
+    }
+
+    /// Replaces tags of a file
+    ///
+    /// APE tags can only be removed by a path, everything else goes through a single open file:
+    /// the old ID3v2 tag is read, both ID3 tags are stripped and the file is rewritten once
+    /// as a new ID3v2 tag, audio data and an optional ID3v1 tag.
+    fn write(&self, path: &Path, output: &FileOutput, options: &WriteOptions) -> Result<(), Box<dyn StdError>> {
+        remove_ape(path).map_err(Mp3Error::RemoveApe)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(Mp3Error::OpenFile)?;
+        let (mut kept_frames, lyrics_lang): (Vec<Id3Frame>, _) = match Id3V2Tag::read_from(&mut file) {
+            Ok(tag) => (
+                tag.frames().filter(|x| is_kept(x, output, options)).cloned().collect(),
+                tag.lyrics().find(|x| x.description.is_empty()).map(|x| x.lang.clone()),
+            ),
+            Err(Id3Error {
+                kind: Id3ErrorKind::NoTag,
+                ..
+            }) => (Vec::new(), None),
+            Err(err) => return Err(Box::new(Mp3Error::ReadId3V2(err))),
+        };
+        let audio = read_audio(&mut file)?;
+        set_rating(&mut kept_frames, output.rating);
+        let mut tag = Id3V2Tag::new();
+        add_text(&mut tag, "TPE1", join_values(&output.artist, options));
+        add_text(&mut tag, "TPE2", join_values(&output.album_artist, options));
+        add_text(&mut tag, "TALB", output.album.clone());
+        match options.id3_version {
+            _ if !output.has_date() => {}
+            Id3Version::Id3v24 => {
+                tag.add_frame(Id3Frame::with_content(
+                    "TDRC",
+                    Id3FrameContent::Text(output.date.to_string()),
+                ));
+            }
+            _ => {
+                tag.add_frame(Id3Frame::with_content(
+                    "TYER",
+                    Id3FrameContent::Text(format!("{:04}", output.date.year)),
+                ));
+                match (output.date.month, output.date.day) {
+                    (Some(month), Some(day)) => {
+                        tag.add_frame(Id3Frame::with_content(
+                            "TDAT",
+                            Id3FrameContent::Text(format!("{:02}{:02}", day, month)),
+                        ));
+                    }
+                    (Some(_), None) => tag.add_extended_text(TXXX_DATE, output.date.to_string()),
+                    _ => {}
+                }
+            }
+        }
+        if let Some(ref genre) = output.genre {
+            tag.add_frame(Id3Frame::with_content("TCON", Id3FrameContent::Text(genre.clone())));
+        }
+        if let Some(ref grouping) = output.grouping {
+            add_text(&mut tag, "TIT1", grouping.clone());
+        }
+        add_text(&mut tag, "TIT2", output.title.clone());
+        if let Some(ref subtitle) = output.subtitle {
+            add_text(&mut tag, "TIT3", subtitle.clone());
+        }
+        if let Some(ref composer) = output.composer {
+            tag.add_frame(Id3Frame::with_content("TCOM", Id3FrameContent::Text(composer.clone())));
+        }
+        if let Some(ref comment) = output.comment {
+            tag.add_frame(Id3Frame::with_content(
+                "COMM",
+                Id3FrameContent::Comment(Id3Comment {
+                    lang: String::from(COMMENT_LANG),
+                    description: String::new(),
+                    text: comment.clone(),
+                }),
+            ));
+        }
+        if let Some(ref lyrics) = output.lyrics {
+            tag.add_frame(Id3Frame::with_content(
+                "USLT",
+                Id3FrameContent::Lyrics(Id3Lyrics {
+                    lang: lyrics_lang.unwrap_or_else(|| String::from(COMMENT_LANG)),
+                    description: String::new(),
+                    text: lyrics.clone(),
+                }),
+            ));
+        }
+        if let Some(bpm) = output.bpm {
+            add_text(&mut tag, "TBPM", bpm.to_string());
+        }
+        if output.track_number > 0 {
+            add_text(&mut tag, "TRCK", number_pair(output.padded_track_number()));
+        }
+        if output.disc_number > 0 {
+            add_text(&mut tag, "TPOS", number_pair(output.padded_disc_number()));
+        }
+        if let Some(ref disc_subtitle) = output.disc_subtitle {
+            match options.id3_version {
+                Id3Version::Id3v24 => add_text(&mut tag, "TSST", disc_subtitle.clone()),
+                _ => tag.add_extended_text(TXXX_DISC_SUBTITLE, disc_subtitle.clone()),
+            }
+        }
+        if let Some(ref isrc) = output.isrc {
+            add_text(&mut tag, "TSRC", isrc.clone());
+        }
+        if output.compilation {
+            tag.add_frame(Id3Frame::with_content("TCMP", Id3FrameContent::Text(String::from("1"))));
+        }
+        for frame in kept_frames {
+            tag.add_frame(frame);
+        }
+        if let Some(ref cover) = output.cover {
+            tag.add_frame(Id3Frame::with_content(
+                "APIC",
+                Id3FrameContent::Picture(Id3Picture {
+                    mime_type: String::from(cover.mime_type),
+                    picture_type: Id3PictureType::CoverFront,
+                    description: String::new(),
+                    data: cover.data.clone(),
+                }),
+            ));
+        }
+        let mut data = Vec::with_capacity(audio.len());
+        tag.write_to(&mut data, options.id3_version)
+            .map_err(Mp3Error::WriteId3V2)?;
+        data.extend_from_slice(&audio);
+        if options.write_id3v1 {
+            data.extend_from_slice(&build_id3v1(output));
+        }
+        file.seek(SeekFrom::Start(0)).map_err(Mp3Error::Seek)?;
+        file.write_all(&data).map_err(Mp3Error::WriteFile)?;
+        file.set_len(data.len() as u64).map_err(Mp3Error::WriteFile)?;
+        Ok(())
+    }
+
+    /// Removes APE, ID3v1 and ID3v2 tags, leaving audio data only
+    fn clean(&self, path: &Path) -> Result<(), Box<dyn StdError>> {
+        remove_ape(path).map_err(Mp3Error::RemoveApe)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(Mp3Error::OpenFile)?;
+        let audio = read_audio(&mut file)?;
+        file.seek(SeekFrom::Start(0)).map_err(Mp3Error::Seek)?;
+        file.write_all(&audio).map_err(Mp3Error::WriteFile)?;
+        file.set_len(audio.len() as u64).map_err(Mp3Error::WriteFile)?;
+        Ok(())
+    }
+}
+
+/// Removes an ID3v1 tag and returns audio data following an ID3v2 tag
+fn read_audio(file: &mut File) -> Result<Vec<u8>, Mp3Error> {
+    file.seek(SeekFrom::Start(0)).map_err(Mp3Error::Seek)?;
+    skip_missing_id3(Id3V1Tag::remove(file)).map_err(Mp3Error::RemoveId3V1)?;
+    let audio_start = id3v2_len(file).map_err(Mp3Error::ReadAudio)?;
+    let mut audio = Vec::new();
+    file.seek(SeekFrom::Start(audio_start)).map_err(Mp3Error::Seek)?;
+    file.read_to_end(&mut audio).map_err(Mp3Error::ReadAudio)?;
+    // some writers put padding after the declared size of a tag
+    let padding = audio.iter().take_while(|x| **x == 0).count();
+    audio.drain(..padding);
+    Ok(audio)
+}
+
+/// Returns a length of audio data from `audio_start` to an ID3v1 tag or the end of a file
+///
+/// The file is left at `audio_start`.
+fn audio_len(file: &mut File, audio_start: u64) -> Result<u64, IoError> {
+    let mut end = file.metadata()?.len();
+    if end >= audio_start + ID3V1_LEN as u64 {
+        let mut marker = [0; 3];
+        file.seek(SeekFrom::Start(end - ID3V1_LEN as u64))?;
+        file.read_exact(&mut marker)?;
+        if &marker == b"TAG" {
+            end -= ID3V1_LEN as u64;
+        }
+    }
+    file.seek(SeekFrom::Start(audio_start))?;
+    Ok(end.saturating_sub(audio_start))
+}
+
+/// Removes an APE tag, a file without one is not an error
+fn remove_ape(path: &Path) -> Result<(), ApeError> {
+    match ape::remove(path) {
+        Ok(()) | Err(ApeError::TagNotFound) => Ok(()),
+        // a file shorter than an APE footer can't be probed for a tag
+        Err(ApeError::Io(ref err)) if [IoErrorKind::InvalidInput, IoErrorKind::UnexpectedEof].contains(&err.kind()) => {
+            Ok(())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns a size of an ID3v2 tag at the start of a file, 0 when there is no tag
+fn id3v2_len(file: &mut (impl Read + Seek)) -> Result<u64, IoError> {
+    let mut header = [0; ID3V2_HEADER_LEN];
+    file.seek(SeekFrom::Start(0))?;
+    let read = file.read(&mut header)?;
+    if read < ID3V2_HEADER_LEN || &header[..3] != b"ID3" {
+        return Ok(0);
+    }
+    let size = decode_syncsafe(&header[6..]);
+    let footer = if header[5] & ID3V2_FLAG_FOOTER == 0 {
+        0
+    } else {
+        ID3V2_HEADER_LEN as u64
+    };
+    Ok(ID3V2_HEADER_LEN as u64 + size + footer)
+}
+
+/// Decodes a 28-bit integer stored in 4 bytes with the highest bit of every byte unset
+fn decode_syncsafe(data: &[u8]) -> u64 {
+    data.iter().fold(0, |size, x| (size << 7) | u64::from(x & 0x7f))
+}
+
+/// Returns values of a text frame joined with [`VALUE_SEPARATOR`], `None` for a single value
+///
+/// ID3v2.4 separates multiple values with NUL and the id3 crate only decodes the first one,
+/// so frames of an ID3v2.4 tag are scanned here.
+/// Unsynchronised, compressed or encrypted frames are left to the id3 crate.
+fn read_text_values(tag: &[u8], frame_id: &[u8; 4]) -> Option<String> {
+    if tag.len() < ID3V2_HEADER_LEN || tag[3] != 4 || tag[5] & ID3V2_FLAG_UNSYNCHRONISATION != 0 {
+        return None;
+    }
+    let mut pos = ID3V2_HEADER_LEN;
+    if tag[5] & ID3V2_FLAG_EXTENDED_HEADER != 0 {
+        pos += decode_syncsafe(tag.get(pos..pos + 4)?) as usize;
+    }
+    while let Some(header) = tag.get(pos..pos + ID3V2_HEADER_LEN) {
+        if header[0] == 0 {
+            // padding
+            return None;
+        }
+        let size = decode_syncsafe(&header[4..8]) as usize;
+        let body = tag.get(pos + ID3V2_HEADER_LEN..pos + ID3V2_HEADER_LEN + size)?;
+        if &header[..4] == frame_id {
+            if header[9] & ID3V2_FRAME_FORMAT_FLAGS != 0 {
+                return None;
+            }
+            let values = decode_text(body)?;
+            let values: Vec<&str> = values.split('\0').filter(|x| !x.is_empty()).collect();
+            return Some(values.join(&VALUE_SEPARATOR.to_string())).filter(|_| values.len() > 1);
+        }
+        pos += ID3V2_HEADER_LEN + size;
+    }
+    None
+}
+
+/// Decodes a body of a text frame: an encoding byte followed by text
+fn decode_text(body: &[u8]) -> Option<String> {
+    let (encoding, text) = body.split_first()?;
+    let value = match encoding {
+        0 => text.iter().map(|x| char::from(*x)).collect(),
+        1 | 2 => {
+            let big_endian = *encoding == 2 || text.starts_with(&[0xfe, 0xff]);
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|x| {
+                    if big_endian {
+                        u16::from_be_bytes([x[0], x[1]])
+                    } else {
+                        u16::from_le_bytes([x[0], x[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16(&units).ok()?.replace('\u{feff}', "")
+        }
+        3 => String::from_utf8(text.to_vec()).ok()?,
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Adds a text frame, an empty value is not written
+fn add_text(tag: &mut Id3V2Tag, id: &str, value: String) {
+    if !value.is_empty() {
+        tag.add_frame(Id3Frame::with_content(id, Id3FrameContent::Text(value)));
+    }
+}
+
+/// Returns a value of `TRCK` or `TPOS`, e.g. `03/12`, or `3` without a total
+fn number_pair((number, total): (String, Option<String>)) -> String {
+    match total {
+        Some(total) => format!("{}/{}", number, total),
+        None => number,
+    }
+}
+
+/// Returns a value of a text frame, values split by a separator are NUL-separated in ID3v2.4
+fn join_values(value: &str, options: &WriteOptions) -> String {
+    match options.id3_version {
+        Id3Version::Id3v24 => split_values(value, &options.value_separator).join("\0"),
+        _ => value.to_owned(),
+    }
+}
+
+/// Treats a missing ID3 tag as removed
+fn skip_missing_id3(result: Result<bool, Id3Error>) -> Result<(), Id3Error> {
+    match result {
+        Ok(_)
+        | Err(Id3Error {
+            kind: Id3ErrorKind::NoTag,
+            ..
+        }) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Whether a frame of an existing tag is written back
+///
+/// Frames managed by the editor are replaced with its output, all other frames are kept,
+/// except for frames of ID3v2.4 written to an ID3v2.3 tag.
+fn is_kept(frame: &Id3Frame, output: &FileOutput, options: &WriteOptions) -> bool {
+    match frame.id() {
+        id if options.id3_version != Id3Version::Id3v24 && ID3V24_FRAMES.contains(&id) => false,
+        "APIC" => output.keeps_pictures(),
+        // only a comment without description is edited, others (e.g. iTunNORM) are kept
+        "COMM" => frame.content().comment().is_none_or(|x| !x.description.is_empty()),
+        // likewise for lyrics, lyrics with a description are kept
+        "USLT" => frame.content().lyrics().is_none_or(|x| !x.description.is_empty()),
+        "TXXX" => frame
+            .content()
+            .extended_text()
+            .is_none_or(|x| x.description != TXXX_DATE && x.description != TXXX_DISC_SUBTITLE),
+        id => !MANAGED_FRAMES.contains(&id),
+    }
+}
+
+/// Returns a `POPM` value of a rating from 0 to 5 stars, as Windows Media Player writes it
+pub fn stars_to_popm(stars: u8) -> u8 {
+    match stars {
+        0 => 0,
+        1 => 1,
+        2 => 64,
+        3 => 128,
+        4 => 196,
+        _ => 255,
+    }
+}
+
+/// Returns a rating from 0 to 5 stars of a `POPM` value, each number of stars covers a range of values
+pub fn popm_to_stars(value: u8) -> u8 {
+    match value {
+        0 => 0,
+        1..=31 => 1,
+        32..=95 => 2,
+        96..=159 => 3,
+        160..=223 => 4,
+        _ => 5,
+    }
+}
+
+/// A popularimeter (`POPM`): a rating by a user identified with an email and a play count
+#[derive(Debug)]
+struct Popularimeter {
+    email: Vec<u8>,
+    rating: u8,
+    /// A big-endian play count of any length, it may be missing
+    counter: Vec<u8>,
+}
+
+impl Popularimeter {
+    /// Decodes a frame, which the id3 crate keeps as raw data
+    fn from_frame(frame: &Id3Frame) -> Option<Self> {
+        if frame.id() != "POPM" {
+            return None;
+        }
+        let data = frame.content().unknown()?;
+        let email_end = data.iter().position(|x| *x == 0)?;
+        Some(Self {
+            email: data[..email_end].to_vec(),
+            rating: *data.get(email_end + 1)?,
+            counter: data[email_end + 2..].to_vec(),
+        })
+    }
+
+    fn to_frame(&self) -> Id3Frame {
+        let mut data = self.email.clone();
+        data.push(0);
+        data.push(self.rating);
+        data.extend_from_slice(&self.counter);
+        Id3Frame::with_content("POPM", Id3FrameContent::Unknown(data))
+    }
+}
+
+/// Sets a rating of the first popularimeter, keeping its email and play count
+///
+/// A popularimeter is only added for a non-zero rating. A missing rating removes the popularimeter,
+/// unless it has a play count, which is kept with a zero (unknown) rating.
+fn set_rating(frames: &mut Vec<Id3Frame>, stars: Option<u8>) {
+    let rating = stars.map_or(0, stars_to_popm);
+    let existing = frames
+        .iter()
+        .enumerate()
+        .find_map(|(idx, frame)| Some((idx, Popularimeter::from_frame(frame)?)));
+    match existing {
+        Some((idx, ref popularimeter)) if rating == 0 && popularimeter.counter.is_empty() => {
+            frames.remove(idx);
+        }
+        Some((idx, mut popularimeter)) => {
+            popularimeter.rating = rating;
+            frames[idx] = popularimeter.to_frame();
+        }
+        None if rating > 0 => frames.push(
+            Popularimeter {
+                email: Vec::new(),
+                rating,
+                counter: Vec::new(),
+            }
+            .to_frame(),
+        ),
+        None => {}
+    }
+}
+
+/// Returns an ID3v1.1 tag, text fields are truncated to 30 characters (28 for a comment)
+///
+/// Characters out of Latin-1 are replaced with `?`.
+fn build_id3v1(output: &FileOutput) -> [u8; ID3V1_LEN] {
+    let mut tag = [0; ID3V1_LEN];
+    tag[..3].copy_from_slice(b"TAG");
+    write_id3v1_text(&mut tag[3..33], &output.title);
+    write_id3v1_text(&mut tag[33..63], &output.artist);
+    write_id3v1_text(&mut tag[63..93], &output.album);
+    write_id3v1_text(&mut tag[93..97], &format!("{:04}", output.date.year));
+    write_id3v1_text(&mut tag[97..125], output.comment.as_deref().unwrap_or_default());
+    // tag[125] stays zero to mark ID3v1.1
+    tag[126] = u8::try_from(output.track_number).unwrap_or(0);
+    tag[127] = output
+        .genre
+        .as_deref()
+        .and_then(genre_number)
+        .unwrap_or(ID3V1_GENRE_UNKNOWN);
+    tag
+}
+
+fn write_id3v1_text(field: &mut [u8], value: &str) {
+    for (target, x) in field.iter_mut().zip(value.chars()) {
+        *target = u8::try_from(u32::from(x)).unwrap_or(b'?');
+    }
+}
+
+/// Returns a date stored in ID3v2.3 `TYER` and `TDAT` (`DDMM`) frames, or a month stored in `TXXX`
+fn read_id3v23_date(tag: &Id3V2Tag, year: i32) -> Date {
+    let day_month = tag
+        .get("TDAT")
+        .and_then(|x| x.content().text())
+        .filter(|x| x.len() == 4)
+        .and_then(|x| format!("{}-{}-{}", year, &x[2..], &x[..2]).parse().ok());
+    let month = || {
+        read_extended_text(tag, TXXX_DATE)
+            .and_then(|x| x.parse::<Date>().ok())
+            .filter(|x| x.year == year)
+    };
+    day_month.or_else(month).unwrap_or_else(|| Date::from_year(year))
+}
+
+/// Returns a value of a `TXXX` frame with a description
+fn read_extended_text<'a>(tag: &'a Id3V2Tag, description: &str) -> Option<&'a str> {
+    tag.extended_texts()
+        .find(|x| x.description == description)
+        .map(|x| x.value.as_str())
+}
+
+#[derive(Debug)]
+pub enum Mp3Error {
+    OpenFile(IoError),
+    ReadAudio(IoError),
+    ReadId3V2(Id3Error),
+    ReadTag(IoError),
+    RemoveApe(ApeError),
+    RemoveId3V1(Id3Error),
+    Seek(IoError),
+    WriteFile(IoError),
+    WriteId3V2(Id3Error),
+}
+
+impl StdError for Mp3Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::Mp3Error::*;
+        match self {
+            OpenFile(err) => Some(err),
+            ReadAudio(err) => Some(err),
+            ReadId3V2(err) => Some(err),
+            ReadTag(err) => Some(err),
+            RemoveApe(err) => Some(err),
+            RemoveId3V1(err) => Some(err),
+            Seek(err) => Some(err),
+            WriteFile(err) => Some(err),
+            WriteId3V2(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for Mp3Error {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::Mp3Error::*;
+        match self {
+            OpenFile(err) => write!(out, "failed to open file: {}", err),
+            ReadAudio(err) => write!(out, "failed to read audio data: {}", err),
+            ReadId3V2(err) => write!(out, "failed to read ID3V2 tag: {}", err),
+            ReadTag(err) => write!(out, "failed to read tag: {}", err),
+            RemoveApe(err) => write!(out, "failed to remove APE tag: {}", err),
+            RemoveId3V1(err) => write!(out, "failed to remove ID3V1 tag: {}", err),
+            Seek(err) => write!(out, "failed to seek file: {}", err),
+            WriteFile(err) => write!(out, "failed to write file: {}", err),
+            WriteId3V2(err) => write!(out, "failed to write ID3V2 tag: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf, process};
+
+    /// Audio data of a test file, it isn't decoded when tags are written
+    const AUDIO: &[u8] = b"\xff\xfbaudio";
+
+    fn output(path: PathBuf) -> FileOutput {
+        FileOutput {
+            path,
+            format: Format::Mp3,
+            artist: String::from("Artist"),
+            album_artist: String::from("Artist"),
+            album: String::from("Album"),
+            date: Date::from_year(2000),
+            genre: Some(String::from("Rock")),
+            grouping: None,
+            title: String::from("Title"),
+            subtitle: None,
+            composer: None,
+            comment: None,
+            lyrics: None,
+            bpm: None,
+            rating: None,
+            isrc: None,
+            track_number: 3,
+            total_tracks: Some(10),
+            disc_number: 1,
+            total_discs: Some(1),
+            disc_subtitle: None,
+            compilation: false,
+            keep_pictures: true,
+            cover: None,
+            number_width: None,
+        }
+    }
+
+    /// Returns a path of a file with audio data only, removed when dropped
+    fn audio_file(name: &str) -> TestFile {
+        let path = std::env::temp_dir().join(format!("tagg-{}-{}.mp3", process::id(), name));
+        fs::write(&path, AUDIO).unwrap();
+        TestFile(path)
+    }
+
+    struct TestFile(PathBuf);
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn popm(email: &[u8], rating: u8, counter: &[u8]) -> Id3Frame {
+        Popularimeter {
+            email: email.to_vec(),
+            rating,
+            counter: counter.to_vec(),
+        }
+        .to_frame()
+    }
+
+    #[test]
+    fn converts_stars_and_popm_values() {
+        for stars in 0..=5 {
+            assert_eq!(popm_to_stars(stars_to_popm(stars)), stars);
+        }
+        assert_eq!(stars_to_popm(6), 255);
+        assert_eq!(popm_to_stars(31), 1);
+        assert_eq!(popm_to_stars(32), 2);
+        assert_eq!(popm_to_stars(223), 4);
+        assert_eq!(popm_to_stars(224), 5);
+    }
+
+    #[test]
+    fn decodes_popularimeter() {
+        let popularimeter = Popularimeter::from_frame(&popm(b"user@example.com", 196, &[0, 0, 1, 0])).unwrap();
+        assert_eq!(popularimeter.email, b"user@example.com");
+        assert_eq!(popularimeter.rating, 196);
+        assert_eq!(popularimeter.counter, [0, 0, 1, 0]);
+        // a play count is optional
+        let popularimeter = Popularimeter::from_frame(&popm(b"", 1, &[])).unwrap();
+        assert_eq!(popularimeter.rating, 1);
+        assert!(popularimeter.counter.is_empty());
+    }
+
+    #[test]
+    fn sets_rating_keeping_play_count() {
+        let mut frames = vec![popm(b"user@example.com", 64, &[5])];
+        set_rating(&mut frames, Some(5));
+        let popularimeter = Popularimeter::from_frame(&frames[0]).unwrap();
+        assert_eq!(popularimeter.email, b"user@example.com");
+        assert_eq!(popularimeter.rating, 255);
+        assert_eq!(popularimeter.counter, [5]);
+    }
+
+    #[test]
+    fn clears_rating() {
+        let mut frames = vec![popm(b"", 64, &[])];
+        set_rating(&mut frames, None);
+        assert!(frames.is_empty());
+        // a play count is kept
+        let mut frames = vec![popm(b"user@example.com", 64, &[5])];
+        set_rating(&mut frames, None);
+        let popularimeter = Popularimeter::from_frame(&frames[0]).unwrap();
+        assert_eq!(popularimeter.rating, 0);
+        assert_eq!(popularimeter.counter, [5]);
+        let mut frames = Vec::new();
+        set_rating(&mut frames, None);
+        set_rating(&mut frames, Some(0));
+        assert!(frames.is_empty());
+        set_rating(&mut frames, Some(3));
+        assert_eq!(Popularimeter::from_frame(&frames[0]).unwrap().rating, 128);
+    }
+
+    #[test]
+    fn removes_rating_of_file() {
+        let file = audio_file("rating");
+        let output = FileOutput {
+            rating: Some(4),
+            ..output(file.0.clone())
+        };
+        Mp3Backend.write(&file.0, &output, &WriteOptions::default()).unwrap();
+        assert_eq!(Mp3Backend.read(&file.0).unwrap().rating, Some(4));
+        let output = FileOutput { rating: None, ..output };
+        Mp3Backend.write(&file.0, &output, &WriteOptions::default()).unwrap();
+        assert_eq!(Mp3Backend.read(&file.0).unwrap().rating, None);
+        assert!(Id3V2Tag::read_from_path(&file.0).unwrap().get("POPM").is_none());
+    }
+
+    #[test]
+    fn edits_comment_without_description() {
+        let file = audio_file("comment");
+        let mut tag = Id3V2Tag::new();
+        tag.add_frame(Id3Frame::with_content(
+            "COMM",
+            Id3FrameContent::Comment(Id3Comment {
+                lang: String::from(COMMENT_LANG),
+                description: String::from("iTunNORM"),
+                text: String::from(" 00000A2B 00000A2B"),
+            }),
+        ));
+        tag.write_to_path(&file.0, Id3Version::Id3v24).unwrap();
+        assert_eq!(Mp3Backend.read(&file.0).unwrap().comment, None);
+
+        let output = FileOutput {
+            comment: Some(String::from("Comment")),
+            ..output(file.0.clone())
+        };
+        Mp3Backend.write(&file.0, &output, &WriteOptions::default()).unwrap();
+        Mp3Backend.write(&file.0, &output, &WriteOptions::default()).unwrap();
+        assert_eq!(Mp3Backend.read(&file.0).unwrap().comment.as_deref(), Some("Comment"));
+        let tag = Id3V2Tag::read_from_path(&file.0).unwrap();
+        let mut comments: Vec<_> = tag
+            .comments()
+            .map(|x| (x.description.as_str(), x.text.as_str()))
+            .collect();
+        comments.sort();
+        assert_eq!(comments, vec![("", "Comment"), ("iTunNORM", " 00000A2B 00000A2B")]);
+    }
+
+    #[test]
+    fn writes_id3v23_values_without_frames() {
+        let file = audio_file("id3v23");
+        let output = FileOutput {
+            date: "2000-05".parse().unwrap(),
+            disc_subtitle: Some(String::from("Live")),
+            ..output(file.0.clone())
+        };
+        let options = WriteOptions {
+            id3_version: Id3Version::Id3v23,
+            ..WriteOptions::default()
+        };
+        Mp3Backend.write(&file.0, &output, &options).unwrap();
+        let tag = Id3V2Tag::read_from_path(&file.0).unwrap();
+        assert!(tag.get("TDRC").is_none());
+        assert!(tag.get("TSST").is_none());
+        let input = Mp3Backend.read(&file.0).unwrap();
+        assert_eq!(input.date, Some(output.date));
+        assert_eq!(input.disc_subtitle.as_deref(), Some("Live"));
+        // a rewrite replaces values instead of adding them
+        let output = FileOutput {
+            date: "2001-06-07".parse().unwrap(),
+            disc_subtitle: None,
+            ..output
+        };
+        Mp3Backend.write(&file.0, &output, &options).unwrap();
+        let tag = Id3V2Tag::read_from_path(&file.0).unwrap();
+        assert_eq!(tag.extended_texts().count(), 0);
+        assert_eq!(Mp3Backend.read(&file.0).unwrap().date, Some(output.date));
+    }
+
+    #[test]
+    fn drops_id3v24_frames_from_id3v23_tag() {
+        let file = audio_file("id3v24-frames");
+        let mut tag = Id3V2Tag::new();
+        tag.add_frame(Id3Frame::with_content(
+            "TSOP",
+            Id3FrameContent::Text(String::from("Artist, The")),
+        ));
+        tag.add_frame(Id3Frame::with_content(
+            "TDOR",
+            Id3FrameContent::Text(String::from("1990")),
+        ));
+        tag.add_frame(Id3Frame::with_content(
+            "TMED",
+            Id3FrameContent::Text(String::from("CD")),
+        ));
+        tag.write_to_path(&file.0, Id3Version::Id3v24).unwrap();
+        let options = WriteOptions {
+            id3_version: Id3Version::Id3v23,
+            ..WriteOptions::default()
+        };
+        Mp3Backend.write(&file.0, &output(file.0.clone()), &options).unwrap();
+        let tag = Id3V2Tag::read_from_path(&file.0).unwrap();
+        assert!(tag.get("TSOP").is_none());
+        assert!(tag.get("TDOR").is_none());
+        assert!(tag.get("TMED").is_some());
+    }
+
+    #[test]
+    fn writes_id3v1_tag() {
+        let file = audio_file("id3v1");
+        let output = FileOutput {
+            title: "T".repeat(40),
+            ..output(file.0.clone())
+        };
+        let options = WriteOptions {
+            write_id3v1: true,
+            ..WriteOptions::default()
+        };
+        Mp3Backend.write(&file.0, &output, &options).unwrap();
+        let tag = Id3V1Tag::read_from_path(&file.0).unwrap();
+        assert_eq!(tag.title, "T".repeat(30));
+        assert_eq!(tag.artist, "Artist");
+        assert_eq!(tag.album, "Album");
+        assert_eq!(tag.year, "2000");
+        assert_eq!(tag.track, Some(3));
+        assert_eq!(tag.genre(), Some("Rock"));
+        // a rewrite replaces the tag instead of appending another one
+        Mp3Backend.write(&file.0, &output, &options).unwrap();
+        let data = fs::read(&file.0).unwrap();
+        assert_eq!(&data[data.len() - ID3V1_LEN..][..3], b"TAG");
+        assert!(data[..data.len() - ID3V1_LEN].ends_with(AUDIO));
+    }
+
+    #[test]
+    fn removes_id3v1_tag_by_default() {
+        let file = audio_file("no-id3v1");
+        let output = output(file.0.clone());
+        let options = WriteOptions {
+            write_id3v1: true,
+            ..WriteOptions::default()
+        };
+        Mp3Backend.write(&file.0, &output, &options).unwrap();
+        Mp3Backend.write(&file.0, &output, &WriteOptions::default()).unwrap();
+        assert!(!Id3V1Tag::is_candidate(File::open(&file.0).unwrap()).unwrap());
+        assert!(fs::read(&file.0).unwrap().ends_with(AUDIO));
+    }
+}
@@ -0,0 +1,912 @@
+use crate::{
+    cover::Cover,
+    date::Date,
+    file::{FileInput, FileOutput, TagBackend, WriteOptions},
+    finder::Format,
+};
+use std::{
+    convert::TryFrom,
+    error::Error as StdError,
+    fmt,
+    fs::{self, File},
+    io::{Error as IoError, ErrorKind as IoErrorKind, Read, Seek, SeekFrom},
+    ops::Range,
+    path::Path,
+    str,
+    time::Duration,
+};
+
+const ALBUM: [u8; 4] = *b"\xa9alb";
+const ALBUM_ARTIST: [u8; 4] = *b"aART";
+const ARTIST: [u8; 4] = *b"\xa9ART";
+const COMMENT: [u8; 4] = *b"\xa9cmt";
+const COMPILATION: [u8; 4] = *b"cpil";
+const COMPOSER: [u8; 4] = *b"\xa9wrt";
+const COVER: [u8; 4] = *b"covr";
+const DISC_NUMBER: [u8; 4] = *b"disk";
+const GENRE: [u8; 4] = *b"\xa9gen";
+const GROUPING: [u8; 4] = *b"\xa9grp";
+const LYRICS: [u8; 4] = *b"\xa9lyr";
+const TEMPO: [u8; 4] = *b"tmpo";
+const TITLE: [u8; 4] = *b"\xa9nam";
+const TRACK_NUMBER: [u8; 4] = *b"trkn";
+const YEAR: [u8; 4] = *b"\xa9day";
+
+/// Items written from an editor output, all other items (e.g. `----` freeform atoms) are kept
+const MANAGED_ITEMS: [[u8; 4]; 14] = [
+    ALBUM,
+    ALBUM_ARTIST,
+    ARTIST,
+    COMMENT,
+    COMPILATION,
+    COMPOSER,
+    DISC_NUMBER,
+    GENRE,
+    GROUPING,
+    LYRICS,
+    TEMPO,
+    TITLE,
+    TRACK_NUMBER,
+    YEAR,
+];
+
+const ATOM_CO64: [u8; 4] = *b"co64";
+const ATOM_DATA: [u8; 4] = *b"data";
+const ATOM_FTYP: [u8; 4] = *b"ftyp";
+const ATOM_HDLR: [u8; 4] = *b"hdlr";
+const ATOM_ILST: [u8; 4] = *b"ilst";
+const ATOM_MDIA: [u8; 4] = *b"mdia";
+const ATOM_META: [u8; 4] = *b"meta";
+const ATOM_MINF: [u8; 4] = *b"minf";
+const ATOM_MOOV: [u8; 4] = *b"moov";
+const ATOM_MVHD: [u8; 4] = *b"mvhd";
+const ATOM_STBL: [u8; 4] = *b"stbl";
+const ATOM_STCO: [u8; 4] = *b"stco";
+const ATOM_TRAK: [u8; 4] = *b"trak";
+const ATOM_UDTA: [u8; 4] = *b"udta";
+
+const HEADER_LEN: usize = 8;
+const LARGE_HEADER_LEN: usize = 16;
+/// Version and flags of a full atom
+const FULL_ATOM_LEN: usize = 4;
+
+const DATA_TYPE_IMPLICIT: u32 = 0;
+const DATA_TYPE_UTF8: u32 = 1;
+const DATA_TYPE_JPEG: u32 = 13;
+const DATA_TYPE_INTEGER: u32 = 21;
+const DATA_TYPE_PNG: u32 = 14;
+
+pub struct Mp4Backend;
+
+impl TagBackend for Mp4Backend {
+    fn read(&self, path: &Path) -> Result<FileInput, Box<dyn StdError + Send + Sync>> {
+        let tag = Mp4Tag::read_from_path(path)?;
+        let (track_number, total_tracks) = tag.pair(TRACK_NUMBER);
+        let (disc_number, total_discs) = tag.pair(DISC_NUMBER);
+        Ok(FileInput {
+            path: path.to_owned(),
+            format: Format::M4a,
+            artist: tag.text(ARTIST).map(ToOwned::to_owned),
+            album_artist: tag.text(ALBUM_ARTIST).map(ToOwned::to_owned),
+            album: tag.text(ALBUM).map(ToOwned::to_owned),
+            date: tag.text(YEAR).and_then(Date::from_tag),
+            genre: tag.text(GENRE).map(ToOwned::to_owned),
+            grouping: tag.text(GROUPING).map(ToOwned::to_owned),
+            title: tag.text(TITLE).map(ToOwned::to_owned),
+            // iTunes has no atom of a subtitle
+            subtitle: None,
+            composer: tag.text(COMPOSER).map(ToOwned::to_owned),
+            comment: tag.text(COMMENT).map(ToOwned::to_owned),
+            lyrics: tag.text(LYRICS).map(ToOwned::to_owned),
+            bpm: tag.integer(TEMPO).filter(|x| *x > 0),
+            // iTunes keeps ratings in its library rather than in files
+            rating: None,
+            // an ISRC is kept in a freeform atom, which is not supported
+            isrc: None,
+            track_number,
+            total_tracks,
+            disc_number,
+            total_discs,
+            disc_subtitle: None,
+            compilation: tag.flag(COMPILATION),
+            duration: read_duration(path).ok().flatten(),
+        })
+    }
+
+    fn write(&self, path: &Path, output: &FileOutput, _options: &WriteOptions) -> Result<(), Box<dyn StdError>> {
+        let mut tag = Mp4Tag::read_from_path(path)?;
+        for ident in MANAGED_ITEMS {
+            tag.remove(ident);
+        }
+        if !output.keeps_pictures() {
+            tag.remove(COVER);
+        }
+        tag.set_text(ARTIST, output.artist.as_str());
+        tag.set_text(ALBUM_ARTIST, output.album_artist.as_str());
+        tag.set_text(ALBUM, output.album.as_str());
+        if output.has_date() {
+            tag.set_text(YEAR, output.date.to_string());
+        }
+        if let Some(ref genre) = output.genre {
+            tag.set_text(GENRE, genre.as_str());
+        }
+        if let Some(ref grouping) = output.grouping {
+            tag.set_text(GROUPING, grouping.as_str());
+        }
+        tag.set_text(TITLE, output.title.as_str());
+        if let Some(ref composer) = output.composer {
+            tag.set_text(COMPOSER, composer.as_str());
+        }
+        if let Some(ref comment) = output.comment {
+            tag.set_text(COMMENT, comment.as_str());
+        }
+        if let Some(ref lyrics) = output.lyrics {
+            tag.set_text(LYRICS, lyrics.as_str());
+        }
+        if let Some(bpm) = output.bpm {
+            tag.set_integer(TEMPO, bpm);
+        }
+        // zero is a missing number
+        if output.track_number > 0 {
+            tag.set_pair(TRACK_NUMBER, output.track_number, output.total_tracks.unwrap_or(0));
+        }
+        if output.disc_number > 0 {
+            tag.set_pair(DISC_NUMBER, output.disc_number, output.total_discs.unwrap_or(0));
+        }
+        if output.compilation {
+            tag.set_flag(COMPILATION, true);
+        }
+        if let Some(ref cover) = output.cover {
+            tag.set_cover(cover);
+        }
+        tag.write_to_path(path)?;
+        Ok(())
+    }
+
+    /// Removes all metadata items, covers included
+    fn clean(&self, path: &Path) -> Result<(), Box<dyn StdError>> {
+        let mut tag = Mp4Tag::read_from_path(path)?;
+        tag.clear();
+        tag.write_to_path(path)?;
+        Ok(())
+    }
+}
+
+/// iTunes-style metadata items stored in `moov.udta.meta.ilst`
+#[derive(Debug, Default)]
+pub struct Mp4Tag {
+    items: Vec<Item>,
+}
+
+#[derive(Debug)]
+struct Item {
+    ident: [u8; 4],
+    /// A type of the first `data` atom
+    data_type: u32,
+    /// A value of the first `data` atom
+    value: Vec<u8>,
+    /// A payload of an item read from a file, written back as is
+    ///
+    /// It keeps atoms other than the first `data` one, such as `mean` and `name` of freeform items.
+    raw: Option<Vec<u8>>,
+}
+
+impl Mp4Tag {
+    pub fn read_from_path(path: impl AsRef<Path>) -> Result<Self, Mp4Error> {
+        let mut file = File::open(path).map_err(Mp4Error::Io)?;
+        let moov = read_moov(&mut file)?;
+        match find_path(&moov, &[ATOM_UDTA, ATOM_META, ATOM_ILST])? {
+            Some(ilst) => Self::parse(ilst),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn text(&self, ident: [u8; 4]) -> Option<&str> {
+        self.items
+            .iter()
+            .find(|x| x.ident == ident && x.data_type == DATA_TYPE_UTF8)
+            .and_then(|x| str::from_utf8(&x.value).ok())
+    }
+
+    /// Replaces a text item, an empty value just removes it
+    pub fn set_text(&mut self, ident: [u8; 4], value: impl Into<String>) {
+        let value = value.into();
+        if value.is_empty() {
+            self.items.retain(|x| x.ident != ident);
+        } else {
+            self.set(ident, DATA_TYPE_UTF8, value.into_bytes());
+        }
+    }
+
+    /// Returns a number and total of `trkn` or `disk` items
+    pub fn pair(&self, ident: [u8; 4]) -> (Option<u32>, Option<u32>) {
+        let value = match self.items.iter().find(|x| x.ident == ident) {
+            Some(item) if item.value.len() >= 6 => &item.value,
+            _ => return (None, None),
+        };
+        let number = u32::from(u16::from_be_bytes([value[2], value[3]]));
+        let total = u32::from(u16::from_be_bytes([value[4], value[5]]));
+        let non_zero = |x: u32| if x == 0 { None } else { Some(x) };
+        (non_zero(number), non_zero(total))
+    }
+
+    pub fn set_pair(&mut self, ident: [u8; 4], number: u32, total: u32) {
+        let number = u16::try_from(number).unwrap_or(u16::MAX).to_be_bytes();
+        let total = u16::try_from(total).unwrap_or(u16::MAX).to_be_bytes();
+        let mut value = vec![0, 0, number[0], number[1], total[0], total[1]];
+        if ident == TRACK_NUMBER {
+            value.extend_from_slice(&[0, 0]);
+        }
+        self.set(ident, DATA_TYPE_IMPLICIT, value);
+    }
+
+    /// Returns a value of a one-byte integer item such as `cpil`
+    pub fn flag(&self, ident: [u8; 4]) -> Option<bool> {
+        self.items
+            .iter()
+            .find(|x| x.ident == ident)
+            .and_then(|x| x.value.last())
+            .map(|x| *x != 0)
+    }
+
+    /// Sets a one-byte integer item such as `cpil`
+    pub fn set_flag(&mut self, ident: [u8; 4], value: bool) {
+        self.set(ident, DATA_TYPE_INTEGER, vec![u8::from(value)]);
+    }
+
+    /// Returns a value of a big-endian integer item such as `tmpo`
+    pub fn integer(&self, ident: [u8; 4]) -> Option<u32> {
+        self.items
+            .iter()
+            .find(|x| x.ident == ident && x.data_type == DATA_TYPE_INTEGER)
+            .filter(|x| !x.value.is_empty() && x.value.len() <= 4)
+            .map(|x| x.value.iter().fold(0, |acc, byte| (acc << 8) | u32::from(*byte)))
+    }
+
+    /// Sets a two-byte integer item such as `tmpo`, larger values are clamped
+    pub fn set_integer(&mut self, ident: [u8; 4], value: u32) {
+        let value = u16::try_from(value).unwrap_or(u16::MAX).to_be_bytes();
+        self.set(ident, DATA_TYPE_INTEGER, value.to_vec());
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Replaces all cover images with the given one
+    pub fn set_cover(&mut self, cover: &Cover) {
+        let data_type = if cover.mime_type == "image/png" {
+            DATA_TYPE_PNG
+        } else {
+            DATA_TYPE_JPEG
+        };
+        self.set(COVER, data_type, cover.data.clone());
+    }
+
+    /// Removes all items with the given identifier
+    pub fn remove(&mut self, ident: [u8; 4]) {
+        self.items.retain(|x| x.ident != ident);
+    }
+
+    /// Replaces the `ilst` atom of a file
+    ///
+    /// The file is rewritten as a whole and chunk offsets are shifted
+    /// when the size of the `moov` atom changes.
+    pub fn write_to_path(&self, path: impl AsRef<Path>) -> Result<(), Mp4Error> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(Mp4Error::Io)?;
+        let top = parse_atoms(&data)?;
+        if top.first().map(|x| x.kind) != Some(ATOM_FTYP) {
+            return Err(Mp4Error::NotMp4);
+        }
+        let moov = top.iter().find(|x| x.kind == ATOM_MOOV).ok_or(Mp4Error::NoMoov)?;
+        let mut new_moov = build_atom(
+            ATOM_MOOV,
+            &replace_child(
+                moov.payload(&data),
+                &[ATOM_UDTA, ATOM_META, ATOM_ILST],
+                &self.serialize(),
+            )?,
+        );
+        let old_len = moov.end - moov.start;
+        let delta = new_moov.len() as i64 - old_len as i64;
+        if delta != 0 {
+            shift_chunk_offsets(&mut new_moov[HEADER_LEN..], moov.end as u64, delta)?;
+        }
+        let mut result = Vec::with_capacity(data.len() + new_moov.len());
+        result.extend_from_slice(&data[..moov.start]);
+        result.extend_from_slice(&new_moov);
+        result.extend_from_slice(&data[moov.end..]);
+        fs::write(path, result).map_err(Mp4Error::Io)
+    }
+
+    fn set(&mut self, ident: [u8; 4], data_type: u32, value: Vec<u8>) {
+        self.items.retain(|x| x.ident != ident);
+        self.items.push(Item {
+            ident,
+            data_type,
+            value,
+            raw: None,
+        });
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, Mp4Error> {
+        let mut items = Vec::new();
+        for atom in parse_atoms(data)? {
+            let payload = atom.payload(data);
+            let (data_type, value) = match parse_atoms(payload)?.into_iter().find(|x| x.kind == ATOM_DATA) {
+                Some(data_atom) => {
+                    let value = data_atom.payload(payload);
+                    if value.len() < 8 {
+                        return Err(Mp4Error::InvalidAtom(ATOM_DATA));
+                    }
+                    (
+                        u32::from_be_bytes([0, value[1], value[2], value[3]]),
+                        value[8..].to_vec(),
+                    )
+                }
+                None => (DATA_TYPE_IMPLICIT, Vec::new()),
+            };
+            items.push(Item {
+                ident: atom.kind,
+                data_type,
+                value,
+                raw: Some(payload.to_vec()),
+            });
+        }
+        Ok(Self { items })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for item in &self.items {
+            if let Some(ref raw) = item.raw {
+                data.extend(build_atom(item.ident, raw));
+                continue;
+            }
+            let mut value = Vec::with_capacity(item.value.len() + 8);
+            value.extend_from_slice(&item.data_type.to_be_bytes());
+            value.extend_from_slice(&[0; 4]);
+            value.extend_from_slice(&item.value);
+            data.extend(build_atom(item.ident, &build_atom(ATOM_DATA, &value)));
+        }
+        build_atom(ATOM_ILST, &data)
+    }
+}
+
+/// Returns a duration of a movie stored in `moov.mvhd`, `None` when it is unknown
+pub fn read_duration(path: impl AsRef<Path>) -> Result<Option<Duration>, Mp4Error> {
+    let mut file = File::open(path).map_err(Mp4Error::Io)?;
+    let moov = read_moov(&mut file)?;
+    let mvhd = find_path(&moov, &[ATOM_MVHD])?.ok_or(Mp4Error::InvalidAtom(ATOM_MVHD))?;
+    let number = |range: Range<usize>| {
+        mvhd.get(range)
+            .map(|x| x.iter().fold(0u64, |acc, x| (acc << 8) | u64::from(*x)))
+            .ok_or(Mp4Error::InvalidAtom(ATOM_MVHD))
+    };
+    // version 1 has 64-bit times and a duration, version 0 has 32-bit ones
+    let (timescale, duration) = match mvhd.first() {
+        Some(1) => (number(20..24)?, number(24..32)?),
+        Some(0) => (number(12..16)?, number(16..20)?),
+        _ => return Err(Mp4Error::InvalidAtom(ATOM_MVHD)),
+    };
+    // all bits set means an unknown duration
+    if timescale == 0 || duration == 0 || duration == u64::from(u32::MAX) || duration == u64::MAX {
+        return Ok(None);
+    }
+    Ok(Some(Duration::from_micros(
+        (u128::from(duration) * 1_000_000 / u128::from(timescale)) as u64,
+    )))
+}
+
+struct Atom {
+    kind: [u8; 4],
+    start: usize,
+    header_len: usize,
+    end: usize,
+}
+
+impl Atom {
+    fn payload<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.start + self.header_len..self.end]
+    }
+}
+
+fn parse_atoms(data: &[u8]) -> Result<Vec<Atom>, Mp4Error> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    while start + HEADER_LEN <= data.len() {
+        let size = u32::from_be_bytes([data[start], data[start + 1], data[start + 2], data[start + 3]]) as usize;
+        let mut kind = [0; 4];
+        kind.copy_from_slice(&data[start + 4..start + 8]);
+        let (header_len, size) = match size {
+            0 => (HEADER_LEN, data.len() - start),
+            1 => {
+                if start + LARGE_HEADER_LEN > data.len() {
+                    return Err(Mp4Error::InvalidAtom(kind));
+                }
+                let mut buf = [0; 8];
+                buf.copy_from_slice(&data[start + 8..start + 16]);
+                let size = usize::try_from(u64::from_be_bytes(buf)).map_err(|_| Mp4Error::InvalidAtom(kind))?;
+                (LARGE_HEADER_LEN, size)
+            }
+            size => (HEADER_LEN, size),
+        };
+        let end = match start.checked_add(size) {
+            Some(end) if size >= header_len && end <= data.len() => end,
+            _ => return Err(Mp4Error::InvalidAtom(kind)),
+        };
+        result.push(Atom {
+            kind,
+            start,
+            header_len,
+            end,
+        });
+        start = end;
+    }
+    Ok(result)
+}
+
+fn build_atom(kind: [u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(payload.len() + HEADER_LEN);
+    data.extend_from_slice(&((payload.len() + HEADER_LEN) as u32).to_be_bytes());
+    data.extend_from_slice(&kind);
+    data.extend_from_slice(payload);
+    data
+}
+
+/// Returns the length of version and flags of a `meta` atom
+///
+/// ISO files store `meta` as a full atom, but QuickTime files do not.
+fn meta_prefix_len(payload: &[u8]) -> usize {
+    match payload.get(4..8) {
+        Some(kind) if kind == ATOM_HDLR => 0,
+        _ => FULL_ATOM_LEN.min(payload.len()),
+    }
+}
+
+/// Finds a payload of a nested atom
+fn find_path<'a>(data: &'a [u8], path: &[[u8; 4]]) -> Result<Option<&'a [u8]>, Mp4Error> {
+    let (kind, rest) = match path.split_first() {
+        Some(x) => x,
+        None => return Ok(Some(data)),
+    };
+    match parse_atoms(data)?.into_iter().find(|x| x.kind == *kind) {
+        Some(atom) => {
+            let mut payload = atom.payload(data);
+            if *kind == ATOM_META {
+                payload = &payload[meta_prefix_len(payload)..];
+            }
+            find_path(payload, rest)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Rebuilds a container payload replacing (or creating) a nested atom
+fn replace_child(data: &[u8], path: &[[u8; 4]], replacement: &[u8]) -> Result<Vec<u8>, Mp4Error> {
+    let (kind, rest) = match path.split_first() {
+        Some(x) => x,
+        None => return Ok(replacement.to_vec()),
+    };
+    let atoms = parse_atoms(data)?;
+    let mut result = Vec::with_capacity(data.len() + replacement.len());
+    let mut found = false;
+    for atom in &atoms {
+        if atom.kind != *kind || found {
+            result.extend_from_slice(&data[atom.start..atom.end]);
+            continue;
+        }
+        found = true;
+        if rest.is_empty() {
+            result.extend_from_slice(replacement);
+        } else if *kind == ATOM_META {
+            let payload = atom.payload(data);
+            let prefix_len = meta_prefix_len(payload);
+            let mut new_payload = payload[..prefix_len].to_vec();
+            new_payload.extend(replace_child(&payload[prefix_len..], rest, replacement)?);
+            result.extend(build_atom(*kind, &new_payload));
+        } else {
+            result.extend(build_atom(
+                *kind,
+                &replace_child(atom.payload(data), rest, replacement)?,
+            ));
+        }
+    }
+    if !found {
+        if rest.is_empty() {
+            result.extend_from_slice(replacement);
+        } else if *kind == ATOM_META {
+            let mut payload = vec![0; FULL_ATOM_LEN];
+            payload.extend(build_meta_handler());
+            payload.extend(replace_child(&[], rest, replacement)?);
+            result.extend(build_atom(*kind, &payload));
+        } else {
+            result.extend(build_atom(*kind, &replace_child(&[], rest, replacement)?));
+        }
+    }
+    Ok(result)
+}
+
+fn build_meta_handler() -> Vec<u8> {
+    let mut payload = vec![0; FULL_ATOM_LEN + 4];
+    payload.extend_from_slice(b"mdirappl");
+    payload.extend_from_slice(&[0; 9]);
+    build_atom(ATOM_HDLR, &payload)
+}
+
+/// Shifts offsets in `stco`/`co64` atoms pointing past `threshold`
+fn shift_chunk_offsets(data: &mut [u8], threshold: u64, delta: i64) -> Result<(), Mp4Error> {
+    for atom in parse_atoms(data)? {
+        let payload_start = atom.start + atom.header_len;
+        let payload = &mut data[payload_start..atom.end];
+        match atom.kind {
+            ATOM_TRAK | ATOM_MDIA | ATOM_MINF | ATOM_STBL => shift_chunk_offsets(payload, threshold, delta)?,
+            ATOM_STCO | ATOM_CO64 => {
+                let entry_len = if atom.kind == ATOM_STCO { 4 } else { 8 };
+                if payload.len() < 8 {
+                    return Err(Mp4Error::InvalidAtom(atom.kind));
+                }
+                let count = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]) as usize;
+                if payload.len() < 8 + count * entry_len {
+                    return Err(Mp4Error::InvalidAtom(atom.kind));
+                }
+                for entry in payload[8..8 + count * entry_len].chunks_mut(entry_len) {
+                    let mut buf = [0; 8];
+                    buf[8 - entry_len..].copy_from_slice(entry);
+                    let offset = u64::from_be_bytes(buf);
+                    if offset < threshold {
+                        continue;
+                    }
+                    let offset = (offset as i64 + delta) as u64;
+                    if entry_len == 4 {
+                        let offset = u32::try_from(offset).map_err(|_| Mp4Error::InvalidAtom(atom.kind))?;
+                        entry.copy_from_slice(&offset.to_be_bytes());
+                    } else {
+                        entry.copy_from_slice(&offset.to_be_bytes());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Reads a `moov` atom payload without loading media data
+fn read_moov(file: &mut File) -> Result<Vec<u8>, Mp4Error> {
+    let len = file.metadata().map_err(Mp4Error::Io)?.len();
+    let mut offset = 0;
+    let mut is_first = true;
+    while offset + HEADER_LEN as u64 <= len {
+        file.seek(SeekFrom::Start(offset)).map_err(Mp4Error::Io)?;
+        let mut header = [0; HEADER_LEN];
+        read_exact(file, &mut header)?;
+        let mut kind = [0; 4];
+        kind.copy_from_slice(&header[4..8]);
+        if is_first && kind != ATOM_FTYP {
+            return Err(Mp4Error::NotMp4);
+        }
+        is_first = false;
+        let (header_len, size) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => (HEADER_LEN as u64, len - offset),
+            1 => {
+                let mut buf = [0; 8];
+                read_exact(file, &mut buf)?;
+                (LARGE_HEADER_LEN as u64, u64::from_be_bytes(buf))
+            }
+            size => (HEADER_LEN as u64, u64::from(size)),
+        };
+        let end = match offset.checked_add(size) {
+            Some(end) if size >= header_len && end <= len => end,
+            _ => return Err(Mp4Error::InvalidAtom(kind)),
+        };
+        if kind == ATOM_MOOV {
+            let payload_len = usize::try_from(size - header_len).map_err(|_| Mp4Error::InvalidAtom(kind))?;
+            let mut payload = vec![0; payload_len];
+            read_exact(file, &mut payload)?;
+            return Ok(payload);
+        }
+        offset = end;
+    }
+    Err(Mp4Error::NoMoov)
+}
+
+fn read_exact(reader: &mut impl Read, buf: &mut [u8]) -> Result<(), Mp4Error> {
+    reader.read_exact(buf).map_err(|err| match err.kind() {
+        IoErrorKind::UnexpectedEof => Mp4Error::UnexpectedEof,
+        _ => Mp4Error::Io(err),
+    })
+}
+
+#[derive(Debug)]
+pub enum Mp4Error {
+    InvalidAtom([u8; 4]),
+    Io(IoError),
+    NoMoov,
+    NotMp4,
+    UnexpectedEof,
+}
+
+impl StdError for Mp4Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::Mp4Error::*;
+        match self {
+            InvalidAtom(_) => None,
+            Io(err) => Some(err),
+            NoMoov => None,
+            NotMp4 => None,
+            UnexpectedEof => None,
+        }
+    }
+}
+
+impl fmt::Display for Mp4Error {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::Mp4Error::*;
+        match self {
+            InvalidAtom(kind) => write!(out, "invalid atom: {}", String::from_utf8_lossy(kind)),
+            Io(err) => write!(out, "{}", err),
+            NoMoov => write!(out, "moov atom is missing"),
+            NotMp4 => write!(out, "not an MP4 file"),
+            UnexpectedEof => write!(out, "unexpected end of file"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{path::PathBuf, process};
+
+    const AUDIO: &[u8] = b"audio data";
+
+    fn output(path: PathBuf) -> FileOutput {
+        FileOutput {
+            path,
+            format: Format::M4a,
+            artist: String::from("Artist"),
+            album_artist: String::from("Artist"),
+            album: String::from("Album"),
+            date: Date::from_year(2000),
+            genre: Some(String::from("Rock")),
+            grouping: None,
+            title: String::from("Title"),
+            subtitle: None,
+            composer: None,
+            comment: None,
+            lyrics: None,
+            bpm: Some(120),
+            rating: None,
+            isrc: None,
+            track_number: 3,
+            total_tracks: Some(10),
+            disc_number: 1,
+            total_discs: Some(2),
+            disc_subtitle: None,
+            compilation: false,
+            keep_pictures: true,
+            cover: None,
+            number_width: None,
+        }
+    }
+
+    struct TestFile(PathBuf);
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn full_atom(kind: [u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; FULL_ATOM_LEN];
+        data.extend_from_slice(payload);
+        build_atom(kind, &data)
+    }
+
+    fn freeform_item() -> Vec<u8> {
+        let mut payload = full_atom(*b"mean", b"com.apple.iTunes");
+        payload.extend(full_atom(*b"name", b"ISRC"));
+        let mut value = DATA_TYPE_UTF8.to_be_bytes().to_vec();
+        value.extend_from_slice(&[0; 4]);
+        value.extend_from_slice(b"USRC17607839");
+        payload.extend(build_atom(ATOM_DATA, &value));
+        build_atom(*b"----", &payload)
+    }
+
+    fn chunk_offsets(kind: [u8; 4], offset: u64) -> Vec<u8> {
+        let mut payload = vec![0; FULL_ATOM_LEN];
+        payload.extend_from_slice(&1u32.to_be_bytes());
+        if kind == ATOM_STCO {
+            payload.extend_from_slice(&(offset as u32).to_be_bytes());
+        } else {
+            payload.extend_from_slice(&offset.to_be_bytes());
+        }
+        build_atom(kind, &payload)
+    }
+
+    /// Builds `ftyp`, `moov` and `mdat` atoms, `udta` is the payload of `moov.udta` if any
+    fn build_file(offsets_kind: [u8; 4], udta: Option<&[u8]>) -> Vec<u8> {
+        let ftyp = build_atom(ATOM_FTYP, b"M4A \0\0\0\0M4A mp42");
+        let mut mvhd = vec![0; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&5000u32.to_be_bytes());
+        // the length of `moov` doesn't depend on offsets, so it is built twice
+        let build_moov = |offset: u64| {
+            let stbl = build_atom(ATOM_STBL, &chunk_offsets(offsets_kind, offset));
+            let trak = build_atom(ATOM_TRAK, &build_atom(ATOM_MDIA, &build_atom(ATOM_MINF, &stbl)));
+            let mut payload = build_atom(ATOM_MVHD, &mvhd);
+            payload.extend(trak);
+            if let Some(udta) = udta {
+                payload.extend(build_atom(ATOM_UDTA, udta));
+            }
+            build_atom(ATOM_MOOV, &payload)
+        };
+        let audio_offset = (ftyp.len() + build_moov(0).len() + HEADER_LEN) as u64;
+        let mut data = ftyp;
+        data.extend(build_moov(audio_offset));
+        data.extend(build_atom(*b"mdat", AUDIO));
+        data
+    }
+
+    fn test_file(name: &str, data: &[u8]) -> TestFile {
+        let path = std::env::temp_dir().join(format!("tagg-{}-{}.m4a", process::id(), name));
+        fs::write(&path, data).unwrap();
+        TestFile(path)
+    }
+
+    /// Returns the first chunk offset and checks that it points to the audio data
+    fn check_chunk_offset(data: &[u8], offsets_kind: [u8; 4]) -> u64 {
+        let top = parse_atoms(data).unwrap();
+        let moov = top.iter().find(|x| x.kind == ATOM_MOOV).unwrap();
+        let offsets = find_path(
+            moov.payload(data),
+            &[ATOM_TRAK, ATOM_MDIA, ATOM_MINF, ATOM_STBL, offsets_kind],
+        )
+        .unwrap()
+        .unwrap();
+        let offset = if offsets_kind == ATOM_STCO {
+            u64::from(u32::from_be_bytes([offsets[8], offsets[9], offsets[10], offsets[11]]))
+        } else {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(&offsets[8..16]);
+            u64::from_be_bytes(buf)
+        };
+        assert_eq!(&data[offset as usize..offset as usize + AUDIO.len()], AUDIO);
+        offset
+    }
+
+    fn write_and_read(name: &str, offsets_kind: [u8; 4], udta: Option<&[u8]>) {
+        let data = build_file(offsets_kind, udta);
+        let old_offset = check_chunk_offset(&data, offsets_kind);
+        let file = test_file(name, &data);
+        Mp4Backend
+            .write(&file.0, &output(file.0.clone()), &WriteOptions::default())
+            .unwrap();
+
+        let new_data = fs::read(&file.0).unwrap();
+        let delta = (new_data.len() - data.len()) as u64;
+        assert!(delta > 0);
+        assert_eq!(check_chunk_offset(&new_data, offsets_kind), old_offset + delta);
+        assert!(new_data.ends_with(&build_atom(*b"mdat", AUDIO)));
+
+        let input = Mp4Backend.read(&file.0).unwrap();
+        assert_eq!(input.artist.as_deref(), Some("Artist"));
+        assert_eq!(input.title.as_deref(), Some("Title"));
+        assert_eq!(input.genre.as_deref(), Some("Rock"));
+        assert_eq!(input.date, Date::from_tag("2000"));
+        assert_eq!(input.track_number, Some(3));
+        assert_eq!(input.total_tracks, Some(10));
+        assert_eq!(input.disc_number, Some(1));
+        assert_eq!(input.total_discs, Some(2));
+        assert_eq!(input.bpm, Some(120));
+        assert_eq!(input.duration, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn writes_tags_and_shifts_chunk_offsets() {
+        write_and_read("stco", ATOM_STCO, None);
+        write_and_read("co64", ATOM_CO64, None);
+    }
+
+    #[test]
+    fn keeps_unmanaged_items() {
+        let mut ilst_payload = build_atom(TITLE, &{
+            let mut value = DATA_TYPE_UTF8.to_be_bytes().to_vec();
+            value.extend_from_slice(&[0; 4]);
+            value.extend_from_slice(b"Old title");
+            build_atom(ATOM_DATA, &value)
+        });
+        ilst_payload.extend(freeform_item());
+        let mut meta = vec![0; FULL_ATOM_LEN];
+        meta.extend(build_meta_handler());
+        meta.extend(build_atom(ATOM_ILST, &ilst_payload));
+        let udta = build_atom(ATOM_META, &meta);
+
+        let data = build_file(ATOM_STCO, Some(&udta));
+        let file = test_file("freeform", &data);
+        assert_eq!(Mp4Tag::read_from_path(&file.0).unwrap().text(TITLE), Some("Old title"));
+        Mp4Backend
+            .write(&file.0, &output(file.0.clone()), &WriteOptions::default())
+            .unwrap();
+
+        let tag = Mp4Tag::read_from_path(&file.0).unwrap();
+        assert_eq!(tag.text(TITLE), Some("Title"));
+        let freeform: Vec<_> = tag.items.iter().filter(|x| x.ident == *b"----").collect();
+        assert_eq!(freeform.len(), 1);
+        assert_eq!(freeform[0].raw.as_deref(), Some(&freeform_item()[HEADER_LEN..]));
+        check_chunk_offset(&fs::read(&file.0).unwrap(), ATOM_STCO);
+    }
+
+    #[test]
+    fn creates_meta_with_handler() {
+        let moov = replace_child(&[], &[ATOM_UDTA, ATOM_META, ATOM_ILST], &build_atom(ATOM_ILST, &[])).unwrap();
+        let meta = find_path(&moov, &[ATOM_UDTA, ATOM_META]).unwrap().unwrap();
+        let atoms = parse_atoms(meta).unwrap();
+        assert_eq!(
+            atoms.iter().map(|x| x.kind).collect::<Vec<_>>(),
+            vec![ATOM_HDLR, ATOM_ILST]
+        );
+        assert_eq!(&atoms[0].payload(meta)[8..12], b"mdir");
+        assert!(find_path(&moov, &[ATOM_UDTA, ATOM_META, ATOM_ILST]).unwrap().is_some());
+    }
+
+    #[test]
+    fn detects_meta_without_version_and_flags() {
+        let handler = build_meta_handler();
+        let mut full = vec![0; FULL_ATOM_LEN];
+        full.extend_from_slice(&handler);
+        assert_eq!(meta_prefix_len(&full), FULL_ATOM_LEN);
+        // QuickTime files start `meta` with `hdlr` right away
+        assert_eq!(meta_prefix_len(&handler), 0);
+
+        let mut quicktime = handler;
+        quicktime.extend(build_atom(ATOM_ILST, &freeform_item()));
+        let udta = build_atom(ATOM_META, &quicktime);
+        let ilst = find_path(&udta, &[ATOM_META, ATOM_ILST]).unwrap().unwrap();
+        assert_eq!(Mp4Tag::parse(ilst).unwrap().items.len(), 1);
+    }
+
+    #[test]
+    fn encodes_numbers() {
+        let mut tag = Mp4Tag::default();
+        tag.set_pair(TRACK_NUMBER, 3, 10);
+        tag.set_pair(DISC_NUMBER, 1, 70_000);
+        tag.set_integer(TEMPO, 120);
+        tag.set_flag(COMPILATION, true);
+        let values: Vec<_> = tag.items.iter().map(|x| (x.data_type, x.value.clone())).collect();
+        assert_eq!(
+            values,
+            vec![
+                (DATA_TYPE_IMPLICIT, vec![0, 0, 0, 3, 0, 10, 0, 0]),
+                (DATA_TYPE_IMPLICIT, vec![0, 0, 0, 1, 0xff, 0xff]),
+                (DATA_TYPE_INTEGER, vec![0, 120]),
+                (DATA_TYPE_INTEGER, vec![1]),
+            ]
+        );
+
+        let tag = Mp4Tag::parse(&tag.serialize()[HEADER_LEN..]).unwrap();
+        assert_eq!(tag.pair(TRACK_NUMBER), (Some(3), Some(10)));
+        assert_eq!(tag.pair(DISC_NUMBER), (Some(1), Some(u32::from(u16::MAX))));
+        assert_eq!(tag.integer(TEMPO), Some(120));
+        assert_eq!(tag.flag(COMPILATION), Some(true));
+    }
+
+    #[test]
+    fn rejects_overflowing_atom_sizes() {
+        let mut data = 1u32.to_be_bytes().to_vec();
+        data.extend_from_slice(&ATOM_MOOV);
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        data.extend_from_slice(&[0; 16]);
+        assert!(matches!(parse_atoms(&data), Err(Mp4Error::InvalidAtom(ATOM_MOOV))));
+
+        let mut file_data = build_atom(ATOM_FTYP, b"M4A \0\0\0\0");
+        file_data.extend_from_slice(&data);
+        let file = test_file("overflow", &file_data);
+        assert!(matches!(
+            Mp4Tag::read_from_path(&file.0),
+            Err(Mp4Error::InvalidAtom(ATOM_MOOV))
+        ));
+    }
+}
@@ -0,0 +1,337 @@
+//! Duration of MPEG audio streams (MP3 and its layers I and II)
+
+use std::{
+    io::{Error as IoError, ErrorKind as IoErrorKind, Read},
+    time::Duration,
+};
+
+const FRAME_HEADER_LEN: usize = 4;
+/// Frames are never longer, at most 1441 bytes of MPEG-1 layer III and 1729 bytes of layer II
+const MAX_FRAME_LEN: usize = 4096;
+/// Size of a buffer audio data is read through
+const BUFFER_LEN: usize = 64 * 1024;
+/// Offset of a VBRI header from the start of a frame, it follows a header and 32 bytes of side information
+const VBRI_OFFSET: usize = FRAME_HEADER_LEN + 32;
+/// A number of frames sharing a bitrate to consider a stream without a VBR header a CBR one
+const CBR_FRAMES: u32 = 100;
+
+/// Bitrates in kbit/s of MPEG-1 layers I, II, III and MPEG-2/2.5 layers I, II and III by an index of a header
+const BITRATES: [[u32; 15]; 4] = [
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+const MPEG2_LAYER1_BITRATES: [u32; 15] = [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256];
+
+/// Sample rates of MPEG-1 by an index of a header, MPEG-2 halves and MPEG-2.5 quarters them
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+/// A flag of a Xing (or LAME `Info`) header telling that a number of frames follows the flags
+const XING_FLAG_FRAMES: u32 = 0x01;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Version {
+    Mpeg1,
+    Mpeg2,
+    Mpeg25,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FrameHeader {
+    version: Version,
+    layer: u8,
+    is_mono: bool,
+    /// Bits per second
+    bitrate: u32,
+    sample_rate: u32,
+    /// Samples per channel
+    samples: u32,
+    len: usize,
+}
+
+impl FrameHeader {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let data = data.get(..FRAME_HEADER_LEN)?;
+        if data[0] != 0xff || data[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+        let version = match (data[1] >> 3) & 0x03 {
+            0 => Version::Mpeg25,
+            2 => Version::Mpeg2,
+            3 => Version::Mpeg1,
+            _ => return None,
+        };
+        let layer = match (data[1] >> 1) & 0x03 {
+            0 => return None,
+            x => 4 - x,
+        };
+        let bitrate_idx = usize::from(data[2] >> 4);
+        let sample_rate_idx = usize::from((data[2] >> 2) & 0x03);
+        // free format streams are not supported
+        if bitrate_idx == 0 || bitrate_idx == 15 || sample_rate_idx == 3 {
+            return None;
+        }
+        let bitrate = match (version, layer) {
+            (Version::Mpeg1, layer) => BITRATES[usize::from(layer) - 1][bitrate_idx],
+            (_, 1) => MPEG2_LAYER1_BITRATES[bitrate_idx],
+            _ => BITRATES[3][bitrate_idx],
+        } * 1000;
+        let sample_rate = match version {
+            Version::Mpeg1 => SAMPLE_RATES[sample_rate_idx],
+            Version::Mpeg2 => SAMPLE_RATES[sample_rate_idx] / 2,
+            Version::Mpeg25 => SAMPLE_RATES[sample_rate_idx] / 4,
+        };
+        let samples = match (version, layer) {
+            (_, 1) => 384,
+            (Version::Mpeg1, _) | (_, 2) => 1152,
+            _ => 576,
+        };
+        let padding = u32::from((data[2] >> 1) & 0x01);
+        let len = if layer == 1 {
+            (12 * bitrate / sample_rate + padding) * 4
+        } else {
+            samples / 8 * bitrate / sample_rate + padding
+        };
+        Some(Self {
+            version,
+            layer,
+            is_mono: data[3] >> 6 == 3,
+            bitrate,
+            sample_rate,
+            samples,
+            len: len as usize,
+        })
+    }
+
+    /// Returns a number of frames of a stream stored in a Xing header of this frame
+    fn xing_frames(&self, frame: &[u8]) -> Option<u32> {
+        if self.layer != 3 {
+            return None;
+        }
+        let side_info_len = match (self.version, self.is_mono) {
+            (Version::Mpeg1, false) => 32,
+            (Version::Mpeg1, true) | (_, false) => 17,
+            (_, true) => 9,
+        };
+        let xing = frame.get(FRAME_HEADER_LEN + side_info_len..)?;
+        let tag = xing.get(..4)?;
+        if tag != b"Xing" && tag != b"Info" {
+            return None;
+        }
+        let flags = u32::from_be_bytes([*xing.get(4)?, *xing.get(5)?, *xing.get(6)?, *xing.get(7)?]);
+        if flags & XING_FLAG_FRAMES == 0 {
+            return None;
+        }
+        Some(u32::from_be_bytes([
+            *xing.get(8)?,
+            *xing.get(9)?,
+            *xing.get(10)?,
+            *xing.get(11)?,
+        ]))
+    }
+}
+
+/// Returns a number of frames of a stream stored in a VBRI header (written by Fraunhofer encoders)
+fn vbri_frames(frame: &[u8]) -> Option<u32> {
+    let vbri = frame.get(VBRI_OFFSET..)?;
+    if vbri.get(..4)? != b"VBRI" {
+        return None;
+    }
+    Some(u32::from_be_bytes([
+        *vbri.get(14)?,
+        *vbri.get(15)?,
+        *vbri.get(16)?,
+        *vbri.get(17)?,
+    ]))
+}
+
+/// Returns a duration of audio data following an ID3v2 tag, `len` is the length of the data
+///
+/// A number of frames is taken from a Xing or VBRI header of a VBR stream.
+/// When the first frames share a bitrate, the duration is estimated from the bitrate and `len`,
+/// otherwise every frame is counted. Bytes not belonging to frames (e.g. APE tags) are skipped.
+/// Audio data is read through a buffer of a fixed size, only frame headers are looked at.
+pub fn duration(reader: impl Read, len: u64) -> Result<Option<Duration>, IoError> {
+    let mut stream = Stream::new(reader);
+    let first = loop {
+        let data = stream.fill()?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+        match FrameHeader::parse(data) {
+            Some(header) => break header,
+            None => stream.advance(1),
+        }
+    };
+    let data = stream.fill()?;
+    if let Some(frames) = first.xing_frames(data).or_else(|| vbri_frames(data)) {
+        let samples = u64::from(frames) * u64::from(first.samples);
+        return Ok(Some(samples_to_duration(samples, first.sample_rate)));
+    }
+    let start = stream.position();
+    let mut samples = 0;
+    let mut frames = 0;
+    let mut is_constant = true;
+    loop {
+        let data = stream.fill()?;
+        if data.is_empty() {
+            break;
+        }
+        match FrameHeader::parse(data) {
+            Some(header) if header.sample_rate == first.sample_rate && header.len <= data.len() => {
+                samples += u64::from(header.samples);
+                frames += 1;
+                is_constant = is_constant && header.bitrate == first.bitrate;
+                if is_constant && frames == CBR_FRAMES {
+                    let bits = u128::from(len.saturating_sub(start)) * 8;
+                    return Ok(Some(Duration::from_micros(
+                        (bits * 1_000_000 / u128::from(first.bitrate)) as u64,
+                    )));
+                }
+                stream.advance(header.len);
+            }
+            _ => stream.advance(1),
+        }
+    }
+    Ok(Some(samples_to_duration(samples, first.sample_rate)))
+}
+
+/// Reads data through a buffer, keeping at least a frame ahead of a position until the end
+struct Stream<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    pos: usize,
+    /// Length of data dropped from the buffer
+    dropped: u64,
+    is_end: bool,
+}
+
+impl<R: Read> Stream<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: Vec::with_capacity(BUFFER_LEN),
+            pos: 0,
+            dropped: 0,
+            is_end: false,
+        }
+    }
+
+    /// Returns data from a position, at least [`MAX_FRAME_LEN`] bytes unless the stream ends, empty at the end
+    fn fill(&mut self) -> Result<&[u8], IoError> {
+        if self.buffer.len() - self.pos < MAX_FRAME_LEN && !self.is_end {
+            self.buffer.drain(..self.pos);
+            self.dropped += self.pos as u64;
+            self.pos = 0;
+            let mut len = self.buffer.len();
+            self.buffer.resize(BUFFER_LEN, 0);
+            while len < BUFFER_LEN {
+                match self.reader.read(&mut self.buffer[len..]) {
+                    Ok(0) => {
+                        self.is_end = true;
+                        break;
+                    }
+                    Ok(read) => len += read,
+                    Err(ref err) if err.kind() == IoErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            self.buffer.truncate(len);
+        }
+        Ok(&self.buffer[self.pos..])
+    }
+
+    /// Returns an offset of a position from the start of the stream
+    fn position(&self) -> u64 {
+        self.dropped + self.pos as u64
+    }
+
+    fn advance(&mut self, len: usize) {
+        self.pos = (self.pos + len).min(self.buffer.len());
+    }
+}
+
+fn samples_to_duration(samples: u64, sample_rate: u32) -> Duration {
+    Duration::from_micros(samples * 1_000_000 / u64::from(sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A header of an MPEG-1 layer III frame of 128 kbit/s and 44.1 kHz, 417 bytes long
+    const HEADER: [u8; FRAME_HEADER_LEN] = [0xff, 0xfb, 0x90, 0x00];
+    const FRAME_LEN: usize = 417;
+    /// A header of a frame of 160 kbit/s, 522 bytes long
+    const HEADER_160: [u8; FRAME_HEADER_LEN] = [0xff, 0xfb, 0xa0, 0x00];
+    const FRAME_160_LEN: usize = 522;
+
+    fn frame(fields: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut frame = vec![0; FRAME_LEN];
+        frame[..FRAME_HEADER_LEN].copy_from_slice(&HEADER);
+        for (offset, data) in fields {
+            frame[*offset..*offset + data.len()].copy_from_slice(data);
+        }
+        frame
+    }
+
+    fn frames_duration(frames: u64) -> Option<Duration> {
+        Some(samples_to_duration(frames * 1152, 44100))
+    }
+
+    fn audio_duration(audio: &[u8]) -> Option<Duration> {
+        duration(audio, audio.len() as u64).unwrap()
+    }
+
+    #[test]
+    fn estimates_duration_of_constant_bitrate() {
+        let mut audio = b"garbage".to_vec();
+        for _ in 0..500 {
+            audio.extend(frame(&[]));
+        }
+        let len = audio.len() as u64;
+        // trailing data is not read, so it isn't counted
+        audio.extend(vec![0xff; BUFFER_LEN * 2]);
+        let expected = Duration::from_micros((len - 7) * 8 * 1_000_000 / 128_000);
+        assert_eq!(duration(audio.as_slice(), len).unwrap(), Some(expected));
+    }
+
+    #[test]
+    fn counts_frames_of_variable_bitrate() {
+        let mut audio = b"garbage".to_vec();
+        for idx in 0..500 {
+            if idx % 50 == 49 {
+                let mut frame = vec![0; FRAME_160_LEN];
+                frame[..FRAME_HEADER_LEN].copy_from_slice(&HEADER_160);
+                audio.extend(frame);
+            } else {
+                audio.extend(frame(&[]));
+            }
+        }
+        audio.extend_from_slice(b"TAG");
+        audio.extend(vec![0; 125]);
+        assert!(audio.len() > BUFFER_LEN * 3);
+        assert_eq!(audio_duration(&audio), frames_duration(500));
+    }
+
+    #[test]
+    fn counts_frames_of_short_stream() {
+        let audio: Vec<u8> = (0..10).flat_map(|_| frame(&[])).collect();
+        assert_eq!(audio_duration(&audio), frames_duration(10));
+    }
+
+    #[test]
+    fn reads_number_of_frames_of_vbr_header() {
+        let xing = frame(&[(36, b"Xing"), (40, &[0, 0, 0, 1]), (44, &1000u32.to_be_bytes())]);
+        assert_eq!(audio_duration(&xing), frames_duration(1000));
+        let vbri = frame(&[(36, b"VBRI"), (50, &2000u32.to_be_bytes())]);
+        assert_eq!(audio_duration(&vbri), frames_duration(2000));
+    }
+
+    #[test]
+    fn skips_data_without_frames() {
+        assert_eq!(audio_duration(b""), None);
+        assert_eq!(audio_duration(&[0u8; 10000]), None);
+    }
+}
@@ -0,0 +1,214 @@
+//! Lookup of releases in MusicBrainz to fill values missing in tags
+
+use crate::{
+    date::Date,
+    file::FileInput,
+    http::{self, HttpError},
+    json::{Json, JsonError},
+};
+use std::{error::Error as StdError, fmt, thread, time::Duration};
+
+/// Root of the MusicBrainz web service
+pub const DEFAULT_URL: &str = "https://musicbrainz.org/ws/2";
+
+/// Minimum score (0 to 100) of a found release to accept it
+const MIN_SCORE: f64 = 90.0;
+
+/// MusicBrainz allows one request per second
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A client of the MusicBrainz web service
+#[derive(Clone, Debug)]
+pub struct MusicBrainz {
+    url: String,
+}
+
+impl Default for MusicBrainz {
+    fn default() -> Self {
+        Self::new(DEFAULT_URL)
+    }
+}
+
+impl MusicBrainz {
+    /// Creates a client of a service at `url`, e.g. a mirror
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    /// Returns a release best matching an album title and an artist, `None` when there is no good match
+    pub fn find_release(&self, artist: Option<&str>, album: &str) -> Result<Option<Release>, MusicBrainzError> {
+        let mut query = format!("release:{}", quote(album));
+        if let Some(artist) = artist.filter(|x| !x.trim().is_empty()) {
+            query.push_str(" AND artist:");
+            query.push_str(&quote(artist));
+        }
+        let url = format!(
+            "{}/release?query={}&limit=1&fmt=json",
+            self.url.trim_end_matches('/'),
+            http::encode_query(&query)
+        );
+        let found = request(&url)?;
+        let id = match found.get("releases") {
+            Some(Json::Array(releases)) => releases
+                .iter()
+                .find(|x| matches!(x.get("score"), Some(Json::Number(score)) if *score >= MIN_SCORE))
+                .and_then(|x| text(x, "id")),
+            _ => return Err(MusicBrainzError::InvalidResponse),
+        };
+        let id = match id {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        thread::sleep(REQUEST_INTERVAL);
+        let url = format!(
+            "{}/release/{}?inc=recordings+artist-credits&fmt=json",
+            self.url.trim_end_matches('/'),
+            http::encode_query(id)
+        );
+        let release = request(&url)?;
+        Release::from_json(&release)
+            .map(Some)
+            .ok_or(MusicBrainzError::InvalidResponse)
+    }
+}
+
+/// A release found in MusicBrainz
+#[derive(Clone, Debug)]
+pub struct Release {
+    pub id: String,
+    /// Credited artists joined as MusicBrainz shows them, e.g. `A & B`
+    pub artist: String,
+    pub title: String,
+    pub date: Option<Date>,
+    /// Titles of tracks of every disc
+    pub discs: Vec<Vec<String>>,
+}
+
+impl Release {
+    fn from_json(value: &Json) -> Option<Self> {
+        let artist = match value.get("artist-credit") {
+            Some(Json::Array(credits)) => credits
+                .iter()
+                .map(|x| {
+                    format!(
+                        "{}{}",
+                        text(x, "name").unwrap_or_default(),
+                        text(x, "joinphrase").unwrap_or_default()
+                    )
+                })
+                .collect(),
+            _ => String::new(),
+        };
+        let discs = match value.get("media") {
+            Some(Json::Array(media)) => media
+                .iter()
+                .map(|medium| match medium.get("tracks") {
+                    Some(Json::Array(tracks)) => tracks
+                        .iter()
+                        .map(|x| text(x, "title").unwrap_or_default().to_owned())
+                        .collect(),
+                    _ => Vec::new(),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Some(Self {
+            id: text(value, "id")?.to_owned(),
+            artist,
+            title: text(value, "title")?.to_owned(),
+            date: text(value, "date").and_then(Date::from_tag),
+            discs,
+        })
+    }
+
+    /// Fills values missing in tags of files of the album, values already set are kept
+    ///
+    /// A file is matched to a track by its disc and track numbers,
+    /// a file without a track number is matched by its position.
+    pub fn fill_missing(&self, inputs: &mut [FileInput]) {
+        let positions: Vec<(u32, u32)> = self
+            .discs
+            .iter()
+            .enumerate()
+            .flat_map(|(disc, tracks)| (0..tracks.len()).map(move |track| (disc as u32 + 1, track as u32 + 1)))
+            .collect();
+        for (idx, input) in inputs.iter_mut().enumerate() {
+            fill(&mut input.artist, &self.artist);
+            fill(&mut input.album, &self.title);
+            input.date = input.date.or(self.date);
+            if self.discs.len() > 1 {
+                input.total_discs = input.total_discs.or(Some(self.discs.len() as u32));
+            }
+            let (disc_number, track_number) = match input.track_number {
+                Some(track_number) => (input.disc_number.unwrap_or(1), track_number),
+                None => match positions.get(idx) {
+                    Some(position) => *position,
+                    None => continue,
+                },
+            };
+            let tracks = match self.discs.get((disc_number as usize).wrapping_sub(1)) {
+                Some(tracks) => tracks,
+                None => continue,
+            };
+            if let Some(title) = tracks.get((track_number as usize).wrapping_sub(1)) {
+                fill(&mut input.title, title);
+                input.track_number = Some(track_number);
+                input.disc_number = input.disc_number.or(Some(disc_number));
+            }
+            input.total_tracks = input.total_tracks.or(Some(tracks.len() as u32));
+        }
+    }
+}
+
+/// Sets a missing or blank value
+fn fill(value: &mut Option<String>, found: &str) {
+    if value.as_deref().is_none_or(|x| x.trim().is_empty()) && !found.is_empty() {
+        *value = Some(found.to_owned());
+    }
+}
+
+fn request(url: &str) -> Result<Json, MusicBrainzError> {
+    let data = http::get(url).map_err(MusicBrainzError::Http)?;
+    data.parse().map_err(MusicBrainzError::Json)
+}
+
+fn text<'a>(value: &'a Json, key: &str) -> Option<&'a str> {
+    match value.get(key) {
+        Some(Json::String(value)) => Some(value),
+        _ => None,
+    }
+}
+
+/// Returns a phrase of a search query
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[derive(Debug)]
+pub enum MusicBrainzError {
+    Http(HttpError),
+    InvalidResponse,
+    Json(JsonError),
+}
+
+impl StdError for MusicBrainzError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::MusicBrainzError::*;
+        match self {
+            Http(err) => Some(err),
+            InvalidResponse => None,
+            Json(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for MusicBrainzError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::MusicBrainzError::*;
+        match self {
+            Http(err) => write!(out, "{}", err),
+            InvalidResponse => write!(out, "unexpected response"),
+            Json(err) => write!(out, "failed to parse response: {}", err),
+        }
+    }
+}
@@ -0,0 +1,486 @@
+use crate::{
+    file::{FileInput, FileOutput, TagBackend, WriteOptions},
+    finder::Format,
+    vorbis::{VorbisComments, VorbisError},
+};
+use std::{
+    error::Error as StdError,
+    fmt,
+    fs::{self, File},
+    io::{BufReader, Error as IoError, ErrorKind as IoErrorKind, Read, Seek, SeekFrom},
+    path::Path,
+    time::Duration,
+};
+
+const CAPTURE_PATTERN: &[u8; 4] = b"OggS";
+const PAGE_HEADER_LEN: usize = 27;
+const MAX_SEGMENTS: usize = 255;
+const MAX_SEGMENT_LEN: usize = 255;
+const MAX_PAGE_LEN: usize = PAGE_HEADER_LEN + MAX_SEGMENTS * (1 + MAX_SEGMENT_LEN);
+
+const FLAG_CONTINUED: u8 = 0x01;
+
+/// Granule position of a page where no packet finishes
+const GRANULE_NONE: u64 = u64::MAX;
+
+const PACKET_IDENTIFICATION: &[u8; 7] = b"\x01vorbis";
+const PACKET_COMMENT: &[u8; 7] = b"\x03vorbis";
+/// Offset of a sample rate in the identification packet
+const SAMPLE_RATE_OFFSET: usize = 12;
+const HEADER_PACKETS: usize = 3;
+const FRAMING_BIT: u8 = 0x01;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+pub struct OggBackend;
+
+impl TagBackend for OggBackend {
+    fn read(&self, path: &Path) -> Result<FileInput, Box<dyn StdError + Send + Sync>> {
+        let mut reader = BufReader::new(File::open(path).map_err(OggError::Io)?);
+        let headers = Headers::read(&mut reader)?;
+        let mut input = headers.comments()?.to_file_input(path, Format::Ogg);
+        input.duration = headers.duration(reader.get_mut()).ok().flatten();
+        Ok(input)
+    }
+
+    fn write(&self, path: &Path, output: &FileOutput, options: &WriteOptions) -> Result<(), Box<dyn StdError>> {
+        let mut comments = read_comments(path)?;
+        comments.set_file_output(output, options);
+        if let Some(ref cover) = output.cover {
+            comments.add_picture(cover);
+        }
+        write_comments(path, &comments)?;
+        Ok(())
+    }
+
+    /// Removes vorbis comments, pictures included
+    fn clean(&self, path: &Path) -> Result<(), Box<dyn StdError>> {
+        write_comments(path, &VorbisComments::default())?;
+        Ok(())
+    }
+}
+
+pub fn read_comments(path: impl AsRef<Path>) -> Result<VorbisComments, OggError> {
+    let mut reader = BufReader::new(File::open(path).map_err(OggError::Io)?);
+    let headers = Headers::read(&mut reader)?;
+    headers.comments()
+}
+
+/// Replaces the comment header of a Vorbis stream
+///
+/// Header packets are paginated again, so the whole file is rewritten
+/// with renumbered pages when the number of header pages changes.
+pub fn write_comments(path: impl AsRef<Path>, comments: &VorbisComments) -> Result<(), OggError> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(OggError::Io)?;
+    let mut reader = &data[..];
+    let headers = Headers::read(&mut reader)?;
+
+    let mut comment_packet = PACKET_COMMENT.to_vec();
+    comment_packet.extend(comments.serialize());
+    comment_packet.push(FRAMING_BIT);
+    let packets = [comment_packet, headers.packets[2].clone()];
+    let mut pages = vec![headers.pages[0].clone()];
+    pages.extend(paginate(&packets, headers.serial, 1));
+
+    let shift = pages.len() as i64 - headers.pages.len() as i64;
+    let mut result = Vec::with_capacity(data.len());
+    for page in &pages {
+        result.extend(page.serialize());
+    }
+    while !reader.is_empty() {
+        let mut page = Page::read(&mut reader)?;
+        if page.serial == headers.serial && shift != 0 {
+            page.sequence = (i64::from(page.sequence) + shift) as u32;
+        }
+        result.extend(page.serialize());
+    }
+    fs::write(path, result).map_err(OggError::Io)
+}
+
+/// Pages and packets of the Vorbis headers (identification, comment and setup)
+struct Headers {
+    serial: u32,
+    pages: Vec<Page>,
+    packets: Vec<Vec<u8>>,
+}
+
+impl Headers {
+    fn read(reader: &mut impl Read) -> Result<Self, OggError> {
+        let mut pages = Vec::new();
+        let mut packets = Vec::new();
+        let mut packet = Vec::new();
+        let mut serial = None;
+        while packets.len() < HEADER_PACKETS {
+            let page = Page::read(reader)?;
+            match serial {
+                Some(serial) if serial != page.serial => return Err(OggError::UnsupportedStream),
+                Some(_) => {}
+                None => serial = Some(page.serial),
+            }
+            let mut offset = 0;
+            for &len in &page.segments {
+                let len = usize::from(len);
+                packet.extend_from_slice(&page.data[offset..offset + len]);
+                offset += len;
+                if len < MAX_SEGMENT_LEN {
+                    packets.push(packet);
+                    packet = Vec::new();
+                }
+            }
+            pages.push(page);
+        }
+        if packets.len() != HEADER_PACKETS || !packet.is_empty() {
+            return Err(OggError::InvalidHeaders);
+        }
+        if !packets[0].starts_with(PACKET_IDENTIFICATION) || !packets[1].starts_with(PACKET_COMMENT) {
+            return Err(OggError::NotVorbis);
+        }
+        Ok(Self {
+            serial: serial.unwrap_or_default(),
+            pages,
+            packets,
+        })
+    }
+
+    fn comments(&self) -> Result<VorbisComments, OggError> {
+        VorbisComments::parse(&self.packets[1][PACKET_COMMENT.len()..]).map_err(OggError::Comments)
+    }
+
+    /// Returns a duration computed from a granule position of the last page of the stream
+    fn duration(&self, file: &mut File) -> Result<Option<Duration>, OggError> {
+        let sample_rate = match self.packets[0].get(SAMPLE_RATE_OFFSET..SAMPLE_RATE_OFFSET + 4) {
+            Some(x) => u64::from(u32::from_le_bytes([x[0], x[1], x[2], x[3]])),
+            None => return Err(OggError::InvalidHeaders),
+        };
+        let len = file.metadata().map_err(OggError::Io)?.len();
+        file.seek(SeekFrom::Start(len.saturating_sub(MAX_PAGE_LEN as u64)))
+            .map_err(OggError::Io)?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).map_err(OggError::Io)?;
+        let granule = (0..(tail.len() + 1).saturating_sub(PAGE_HEADER_LEN))
+            .rev()
+            .map(|x| &tail[x..x + PAGE_HEADER_LEN])
+            .filter(|x| &x[0..4] == CAPTURE_PATTERN && x[14..18] == self.serial.to_le_bytes())
+            .map(|x| u64::from_le_bytes([x[6], x[7], x[8], x[9], x[10], x[11], x[12], x[13]]))
+            .find(|x| *x != GRANULE_NONE);
+        Ok(match granule {
+            Some(granule) if sample_rate > 0 => Some(Duration::from_micros(
+                (u128::from(granule) * 1_000_000 / u128::from(sample_rate)) as u64,
+            )),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Clone)]
+struct Page {
+    header_type: u8,
+    granule: u64,
+    serial: u32,
+    sequence: u32,
+    segments: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Page {
+    fn new(header_type: u8, serial: u32, sequence: u32) -> Self {
+        Self {
+            header_type,
+            granule: GRANULE_NONE,
+            serial,
+            sequence,
+            segments: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    fn read(reader: &mut impl Read) -> Result<Self, OggError> {
+        let mut header = [0; PAGE_HEADER_LEN];
+        read_exact(reader, &mut header)?;
+        if &header[0..4] != CAPTURE_PATTERN {
+            return Err(OggError::NotOgg);
+        }
+        let mut granule = [0; 8];
+        granule.copy_from_slice(&header[6..14]);
+        let mut segments = vec![0; usize::from(header[26])];
+        read_exact(reader, &mut segments)?;
+        let mut data = vec![0; segments.iter().map(|x| usize::from(*x)).sum()];
+        read_exact(reader, &mut data)?;
+        Ok(Self {
+            header_type: header[5],
+            granule: u64::from_le_bytes(granule),
+            serial: u32::from_le_bytes([header[14], header[15], header[16], header[17]]),
+            sequence: u32::from_le_bytes([header[18], header[19], header[20], header[21]]),
+            segments,
+            data,
+        })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(PAGE_HEADER_LEN + self.segments.len() + self.data.len());
+        result.extend_from_slice(CAPTURE_PATTERN);
+        result.push(0); // version
+        result.push(self.header_type);
+        result.extend_from_slice(&self.granule.to_le_bytes());
+        result.extend_from_slice(&self.serial.to_le_bytes());
+        result.extend_from_slice(&self.sequence.to_le_bytes());
+        result.extend_from_slice(&[0; 4]); // checksum
+        result.push(self.segments.len() as u8);
+        result.extend_from_slice(&self.segments);
+        result.extend_from_slice(&self.data);
+        let checksum = crc32(&result);
+        result[22..26].copy_from_slice(&checksum.to_le_bytes());
+        result
+    }
+}
+
+/// Splits packets into pages, the last packet always ends a page
+fn paginate(packets: &[Vec<u8>], serial: u32, first_sequence: u32) -> Vec<Page> {
+    let mut pages = Vec::new();
+    let mut page = Page::new(0, serial, first_sequence);
+    for packet in packets {
+        let mut lacing = vec![MAX_SEGMENT_LEN; packet.len() / MAX_SEGMENT_LEN];
+        lacing.push(packet.len() % MAX_SEGMENT_LEN);
+        let mut offset = 0;
+        for (idx, &len) in lacing.iter().enumerate() {
+            if page.segments.len() == MAX_SEGMENTS {
+                let header_type = if idx > 0 { FLAG_CONTINUED } else { 0 };
+                let next = Page::new(header_type, serial, page.sequence + 1);
+                pages.push(page);
+                page = next;
+            }
+            page.segments.push(len as u8);
+            page.data.extend_from_slice(&packet[offset..offset + len]);
+            offset += len;
+            if idx + 1 == lacing.len() {
+                page.granule = 0;
+            }
+        }
+    }
+    pages.push(page);
+    pages
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut idx = 0;
+    while idx < 256 {
+        let mut value = (idx as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 0x8000_0000 != 0 {
+                (value << 1) ^ 0x04c1_1db7
+            } else {
+                value << 1
+            };
+            bit += 1;
+        }
+        table[idx] = value;
+        idx += 1;
+    }
+    table
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, &byte| {
+        (crc << 8) ^ CRC_TABLE[usize::from((crc >> 24) as u8 ^ byte)]
+    })
+}
+
+fn read_exact(reader: &mut impl Read, buf: &mut [u8]) -> Result<(), OggError> {
+    reader.read_exact(buf).map_err(|err| match err.kind() {
+        IoErrorKind::UnexpectedEof => OggError::UnexpectedEof,
+        _ => OggError::Io(err),
+    })
+}
+
+#[derive(Debug)]
+pub enum OggError {
+    Comments(VorbisError),
+    InvalidHeaders,
+    Io(IoError),
+    NotOgg,
+    NotVorbis,
+    UnexpectedEof,
+    UnsupportedStream,
+}
+
+impl StdError for OggError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::OggError::*;
+        match self {
+            Comments(err) => Some(err),
+            InvalidHeaders => None,
+            Io(err) => Some(err),
+            NotOgg => None,
+            NotVorbis => None,
+            UnexpectedEof => None,
+            UnsupportedStream => None,
+        }
+    }
+}
+
+impl fmt::Display for OggError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::OggError::*;
+        match self {
+            Comments(err) => write!(out, "{}", err),
+            InvalidHeaders => write!(out, "invalid vorbis headers"),
+            Io(err) => write!(out, "{}", err),
+            NotOgg => write!(out, "not an Ogg file"),
+            NotVorbis => write!(out, "not a Vorbis stream"),
+            UnexpectedEof => write!(out, "unexpected end of file"),
+            UnsupportedStream => write!(out, "multiplexed streams are not supported"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{path::PathBuf, process};
+
+    const SERIAL: u32 = 0x1234_5678;
+    const SAMPLE_RATE: u32 = 44100;
+
+    struct TestFile(PathBuf);
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn identification_packet() -> Vec<u8> {
+        let mut packet = PACKET_IDENTIFICATION.to_vec();
+        packet.extend_from_slice(&[0, 0, 0, 0, 2]); // version and channels
+        packet.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        packet.resize(30, 0);
+        packet
+    }
+
+    fn comment_packet(comments: &VorbisComments) -> Vec<u8> {
+        let mut packet = PACKET_COMMENT.to_vec();
+        packet.extend(comments.serialize());
+        packet.push(FRAMING_BIT);
+        packet
+    }
+
+    fn audio_page(serial: u32, sequence: u32, granule: u64, fill: u8) -> Vec<u8> {
+        let mut page = Page::new(0, serial, sequence);
+        page.granule = granule;
+        page.segments = vec![255, 255, 100];
+        page.data = vec![fill; 610];
+        page.serialize()
+    }
+
+    /// Builds a stream of two header pages and three audio pages, the last one of another stream
+    fn build_file(name: &str) -> TestFile {
+        let mut first = Page::new(0x02, SERIAL, 0);
+        first.granule = 0;
+        let packet = identification_packet();
+        first.segments.push(packet.len() as u8);
+        first.data = packet;
+        let mut comments = VorbisComments::default();
+        comments.set("TITLE", "Old title");
+        let mut data = first.serialize();
+        for page in paginate(&[comment_packet(&comments), b"\x05vorbis setup".to_vec()], SERIAL, 1) {
+            data.extend(page.serialize());
+        }
+        data.extend(audio_page(SERIAL, 2, 4096, 1));
+        data.extend(audio_page(SERIAL, 3, u64::from(SAMPLE_RATE) * 2, 2));
+        data.extend(audio_page(SERIAL + 1, 7, 0, 3));
+        let path = std::env::temp_dir().join(format!("tagg-{}-{}.ogg", process::id(), name));
+        fs::write(&path, data).unwrap();
+        TestFile(path)
+    }
+
+    fn read_pages(mut data: &[u8]) -> Vec<(Page, Vec<u8>)> {
+        let mut pages = Vec::new();
+        while !data.is_empty() {
+            let rest = data;
+            let page = Page::read(&mut data).unwrap();
+            let raw = rest[..rest.len() - data.len()].to_vec();
+            pages.push((page, raw));
+        }
+        pages
+    }
+
+    #[test]
+    fn computes_crc() {
+        // the check value of CRC-32 with the Ogg polynomial, no reflection, zero init and no final XOR
+        assert_eq!(crc32(b"123456789"), 0x89a1_897f);
+
+        let mut page = Page::new(0x02, SERIAL, 0);
+        page.granule = 0;
+        page.segments.push(7);
+        page.data = b"\x01vorbis".to_vec();
+        let data = page.serialize();
+        assert_eq!(&data[22..26], &0xa934_787fu32.to_le_bytes());
+    }
+
+    #[test]
+    fn laces_packets() {
+        let pages = paginate(&[vec![1; 510], Vec::new(), vec![2; 10]], SERIAL, 5);
+        assert_eq!(pages.len(), 1);
+        // a packet of a multiple of 255 bytes ends with a zero-length segment
+        assert_eq!(pages[0].segments, vec![255, 255, 0, 0, 10]);
+        assert_eq!(pages[0].data.len(), 520);
+        assert_eq!(pages[0].sequence, 5);
+        assert_eq!(pages[0].granule, 0);
+
+        let pages = paginate(&[vec![1; MAX_SEGMENTS * MAX_SEGMENT_LEN]], SERIAL, 1);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].segments.len(), MAX_SEGMENTS);
+        assert_eq!(pages[0].granule, GRANULE_NONE);
+        assert_eq!(pages[1].segments, vec![0]);
+        assert_eq!(pages[1].header_type, FLAG_CONTINUED);
+        assert_eq!(pages[1].sequence, 2);
+        assert_eq!(pages[1].granule, 0);
+    }
+
+    #[test]
+    fn rewrites_comments_spanning_pages() {
+        let file = build_file("comments");
+        let old_pages = read_pages(&fs::read(&file.0).unwrap());
+        assert_eq!(old_pages.len(), 5);
+
+        let mut comments = read_comments(&file.0).unwrap();
+        assert_eq!(comments.get("TITLE"), Some("Old title"));
+        let lyrics = "la ".repeat(30_000);
+        comments.set("TITLE", "Title");
+        comments.set("LYRICS", lyrics.as_str());
+        write_comments(&file.0, &comments).unwrap();
+
+        let comments = read_comments(&file.0).unwrap();
+        assert_eq!(comments.get("TITLE"), Some("Title"));
+        assert_eq!(comments.get("LYRICS"), Some(lyrics.as_str()));
+
+        let new_pages = read_pages(&fs::read(&file.0).unwrap());
+        // the comment packet takes two pages now, so audio pages move by one
+        assert_eq!(new_pages.len(), 6);
+        assert_eq!(new_pages[0].1, old_pages[0].1);
+        assert_eq!(new_pages[2].0.header_type, FLAG_CONTINUED);
+        for (idx, (page, raw)) in new_pages.iter().enumerate() {
+            let mut data = raw.clone();
+            data[22..26].copy_from_slice(&[0; 4]);
+            assert_eq!(crc32(&data).to_le_bytes(), raw[22..26]);
+            if page.serial == SERIAL {
+                assert_eq!(page.sequence, idx as u32);
+            }
+        }
+        for ((old, old_raw), (new, new_raw)) in old_pages[2..].iter().zip(&new_pages[3..]) {
+            if old.serial == SERIAL {
+                assert_eq!(new.sequence, old.sequence + 1);
+            } else {
+                assert_eq!(new.sequence, old.sequence);
+            }
+            assert_eq!(new_raw[..18], old_raw[..18]);
+            assert_eq!(new_raw[26..], old_raw[26..]);
+        }
+
+        let input = OggBackend.read(&file.0).unwrap();
+        assert_eq!(input.duration, Some(Duration::from_secs(2)));
+    }
+}
@@ -0,0 +1,134 @@
+//! Extended M3U playlists of written albums
+
+use crate::{
+    file::FileOutput,
+    template::{sanitize, DEFAULT_REPLACEMENT},
+};
+use std::{
+    fs::OpenOptions,
+    io::{Error as IoError, ErrorKind as IoErrorKind, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Extension of written playlists, which are always UTF-8
+pub const PLAYLIST_EXTENSION: &str = "m3u8";
+
+/// Name of a playlist of an album without a usable name
+const DEFAULT_NAME: &str = "playlist";
+
+/// Maximum number appended to a name of a playlist taken by an existing file, e.g. `Album (2).m3u8`
+const MAX_NAME_NUMBER: u32 = 99;
+
+/// A track of a playlist
+#[derive(Clone, Debug)]
+pub struct PlaylistEntry {
+    pub path: PathBuf,
+    pub artist: String,
+    pub title: String,
+    pub disc_number: u32,
+    pub track_number: u32,
+    /// `-1` is written when a duration is unknown
+    pub duration: Option<Duration>,
+}
+
+impl PlaylistEntry {
+    /// Returns an entry of an output written to `path`
+    pub fn new(output: &FileOutput, path: PathBuf, duration: Option<Duration>) -> Self {
+        Self {
+            path,
+            artist: output.artist.clone(),
+            title: output.title.clone(),
+            disc_number: output.disc_number,
+            track_number: output.track_number,
+            duration,
+        }
+    }
+}
+
+/// Writes a playlist of tracks ordered by disc and track numbers, returns a path of the playlist
+///
+/// The playlist is named after an album and written to the closest directory containing all tracks,
+/// tracks are listed relative to it. An existing file is never replaced, a number is appended to the name
+/// instead (`Album (2).m3u8`).
+pub fn write_playlist(album: &str, entries: &[PlaylistEntry]) -> Result<PathBuf, IoError> {
+    let mut entries: Vec<&PlaylistEntry> = entries.iter().collect();
+    entries.sort_by_key(|x| (x.disc_number, x.track_number));
+    let dir = common_dir(entries.iter().map(|x| x.path.as_path()));
+    let mut data = String::from("#EXTM3U\n");
+    for entry in entries {
+        // whole seconds, rounded
+        let duration = entry
+            .duration
+            .map(|x| ((x.as_millis() + 500) / 1000) as i64)
+            .unwrap_or(-1);
+        let path = entry.path.strip_prefix(&dir).unwrap_or(&entry.path);
+        data.push_str(&format!("#EXTINF:{},{} - {}\n", duration, entry.artist, entry.title));
+        data.push_str(&format!("{}\n", path.display()));
+    }
+    let name = Some(sanitize(album, DEFAULT_REPLACEMENT))
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| String::from(DEFAULT_NAME));
+    for number in 1..=MAX_NAME_NUMBER {
+        let path = match number {
+            1 => dir.join(format!("{}.{}", name, PLAYLIST_EXTENSION)),
+            _ => dir.join(format!("{} ({}).{}", name, number, PLAYLIST_EXTENSION)),
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(data.as_bytes())?;
+                return Ok(path);
+            }
+            Err(ref err) if err.kind() == IoErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(IoError::new(
+        IoErrorKind::AlreadyExists,
+        format!("{}.{} and its numbered names are taken", name, PLAYLIST_EXTENSION),
+    ))
+}
+
+/// Returns the closest directory containing all paths
+fn common_dir<'a>(mut paths: impl Iterator<Item = &'a Path>) -> PathBuf {
+    let mut result = match paths.next().and_then(Path::parent) {
+        Some(parent) => parent.to_owned(),
+        None => return PathBuf::new(),
+    };
+    for path in paths {
+        while !path.starts_with(&result) {
+            if !result.pop() {
+                return result;
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, process};
+
+    #[test]
+    fn keeps_existing_playlist() {
+        let dir = std::env::temp_dir().join(format!("tagg-{}-playlist", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Album.m3u8"), "existing").unwrap();
+        let entries = [PlaylistEntry {
+            path: dir.join("01.mp3"),
+            artist: String::from("Artist"),
+            title: String::from("Title"),
+            disc_number: 1,
+            track_number: 1,
+            duration: Some(Duration::from_millis(61_500)),
+        }];
+        let result = write_playlist("Album", &entries);
+        let existing = fs::read_to_string(dir.join("Album.m3u8"));
+        let written = fs::read_to_string(dir.join("Album (2).m3u8"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(result.unwrap(), dir.join("Album (2).m3u8"));
+        assert_eq!(existing.unwrap(), "existing");
+        assert_eq!(written.unwrap(), "#EXTM3U\n#EXTINF:62,Artist - Title\n01.mp3\n");
+    }
+}
@@ -0,0 +1,231 @@
+use crate::file::FileOutput;
+use std::{error::Error as StdError, ffi::OsStr, fmt, str::FromStr};
+
+/// Template used when no other template is given
+///
+/// `{number}` is a track number, prefixed with a disc number when an album has more than one disc.
+pub const DEFAULT_TEMPLATE: &str = "{number} - {title}.{ext}";
+
+/// Replaces reserved characters in values of placeholders by default
+pub const DEFAULT_REPLACEMENT: &str = "_";
+
+/// Characters not allowed in filenames on at least one common platform
+const RESERVED_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Template used to organize files into directories
+pub const ORGANIZE_TEMPLATE: &str = "{album_artist}/{year} - {album}/{number} - {title}.{ext}";
+
+/// A filename template with placeholders such as `{artist}` or `{title}`
+///
+/// A `/` in the template separates directories, while a `/` in a value is treated as a reserved character.
+#[derive(Clone, Debug)]
+pub struct FilenameTemplate {
+    parts: Vec<Part>,
+    replacement: String,
+    /// Whether `{ext}` is always lowercase, otherwise a case of the extension of a file is kept
+    lowercase_extension: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Placeholder {
+    Artist,
+    AlbumArtist,
+    Album,
+    Year,
+    Date,
+    Track,
+    Disc,
+    Number,
+    Title,
+    Ext,
+}
+
+impl Placeholder {
+    fn from_name(name: &str) -> Option<Self> {
+        use self::Placeholder::*;
+        Some(match name {
+            "artist" => Artist,
+            "album_artist" => AlbumArtist,
+            "album" => Album,
+            "year" => Year,
+            "date" => Date,
+            "track" => Track,
+            "disc" => Disc,
+            "number" => Number,
+            "title" => Title,
+            "ext" => Ext,
+            _ => return None,
+        })
+    }
+
+    fn render(self, output: &FileOutput, lowercase_extension: bool) -> String {
+        use self::Placeholder::*;
+        match self {
+            Artist => output.artist.clone(),
+            AlbumArtist => output.album_artist.clone(),
+            Album => output.album.clone(),
+            Year => output.date.year.to_string(),
+            Date => output.date.to_string(),
+            Track => output.padded_track_number().0,
+            Disc => output.padded_disc_number().0,
+            Number => {
+                if output.total_discs.unwrap_or(1) > 1 {
+                    format!("{}-{}", output.padded_disc_number().0, output.padded_track_number().0)
+                } else {
+                    output.padded_track_number().0
+                }
+            }
+            Title => output.title.clone(),
+            Ext => match output.path.extension().and_then(OsStr::to_str) {
+                Some(extension)
+                    if !lowercase_extension && extension.eq_ignore_ascii_case(output.format.extension()) =>
+                {
+                    String::from(extension)
+                }
+                _ => String::from(output.format.extension()),
+            },
+        }
+    }
+}
+
+impl FilenameTemplate {
+    /// Parses a template
+    ///
+    /// Reserved characters in values of placeholders are replaced with `replacement`,
+    /// an empty replacement strips them.
+    pub fn new(template: &str, replacement: &str) -> Result<Self, TemplateError> {
+        if replacement.contains(is_reserved) {
+            return Err(TemplateError::InvalidReplacement(String::from(replacement)));
+        }
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while !rest.is_empty() {
+            let offset = template.len() - rest.len();
+            match rest.find(['{', '}']) {
+                Some(idx) if rest[idx..].starts_with('}') => return Err(TemplateError::UnexpectedBrace(offset + idx)),
+                Some(idx) => {
+                    if idx > 0 {
+                        parts.push(Part::Text(String::from(&rest[..idx])));
+                    }
+                    let end = rest[idx..]
+                        .find('}')
+                        .ok_or(TemplateError::UnclosedBrace(offset + idx))?;
+                    let name = &rest[idx + 1..idx + end];
+                    let placeholder = Placeholder::from_name(name)
+                        .ok_or_else(|| TemplateError::UnknownPlaceholder(String::from(name)))?;
+                    parts.push(Part::Placeholder(placeholder));
+                    rest = &rest[idx + end + 1..];
+                }
+                None => {
+                    parts.push(Part::Text(String::from(rest)));
+                    break;
+                }
+            }
+        }
+        Ok(Self {
+            parts,
+            replacement: String::from(replacement),
+            lowercase_extension: false,
+        })
+    }
+
+    /// Lowercases extensions, e.g. `Song.MP3` is renamed to `.mp3`
+    pub fn with_lowercase_extension(mut self, lowercase_extension: bool) -> Self {
+        self.lowercase_extension = lowercase_extension;
+        self
+    }
+
+    /// Expands placeholders with sanitized values of an output
+    ///
+    /// A title which is empty after sanitizing is replaced with a track number,
+    /// so is the whole filename.
+    pub fn render(&self, output: &FileOutput) -> String {
+        let track_number = || output.padded_track_number().0;
+        let result: String = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Placeholder(placeholder) => {
+                    let value = sanitize(&placeholder.render(output, self.lowercase_extension), &self.replacement);
+                    match placeholder {
+                        Placeholder::Title if value.is_empty() => track_number(),
+                        _ => value,
+                    }
+                }
+            })
+            .collect();
+        let result = result.trim_end_matches(['.', ' ']);
+        if result.is_empty() {
+            track_number()
+        } else {
+            String::from(result)
+        }
+    }
+}
+
+fn is_reserved(value: char) -> bool {
+    RESERVED_CHARS.contains(&value) || value.is_control()
+}
+
+/// Replaces reserved characters, collapses whitespace and trims trailing dots and spaces
+pub fn sanitize(value: &str, replacement: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for word in value.split_whitespace() {
+        if !result.is_empty() {
+            result.push(' ');
+        }
+        for x in word.chars() {
+            if is_reserved(x) {
+                result.push_str(replacement);
+            } else {
+                result.push(x);
+            }
+        }
+    }
+    let len = result.trim_end_matches(['.', ' ']).len();
+    result.truncate(len);
+    result
+}
+
+impl Default for FilenameTemplate {
+    fn default() -> Self {
+        Self::new(DEFAULT_TEMPLATE, DEFAULT_REPLACEMENT).expect("default template is valid")
+    }
+}
+
+impl FromStr for FilenameTemplate {
+    type Err = TemplateError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::new(value, DEFAULT_REPLACEMENT)
+    }
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    InvalidReplacement(String),
+    UnclosedBrace(usize),
+    UnexpectedBrace(usize),
+    UnknownPlaceholder(String),
+}
+
+impl StdError for TemplateError {}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::TemplateError::*;
+        match self {
+            InvalidReplacement(value) => write!(out, "replacement contains reserved characters: {:?}", value),
+            UnclosedBrace(offset) => write!(out, "unclosed brace at position {}", offset),
+            UnexpectedBrace(offset) => write!(out, "unexpected closing brace at position {}", offset),
+            UnknownPlaceholder(name) => write!(out, "unknown placeholder: {{{}}}", name),
+        }
+    }
+}
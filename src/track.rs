@@ -1,31 +1,204 @@
 use crate::{
-    editor::{DefaultValue, Editor, EditorError, EditorOutput, OutputBuilder, Prompt, State, StateInput},
+    editor::{
+        DefaultValue, Editor, EditorError, EditorOutput, OutputBuilder, Prompt, Reader, State, StateInput, Terminal,
+    },
     file::FileInput,
 };
 use std::{error::Error as StdError, fmt, num::ParseIntError};
 
 #[derive(Clone, Debug)]
 pub struct TrackInput {
-    track_number: Option<u32>,
-    disc_number: Option<u32>,
-    title: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    /// A name of the disc of a box set, e.g. `Live`, the same for all tracks of the disc
+    pub disc_subtitle: Option<String>,
+    pub title: Option<String>,
+    /// A subtitle of a part of a work, e.g. a movement
+    pub subtitle: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+    pub lyrics: Option<String>,
+    pub bpm: Option<u32>,
+    pub rating: Option<u8>,
+    /// International Standard Recording Code, e.g. `USRC17607839`
+    pub isrc: Option<String>,
 }
 
+/// Values missing in tags are guessed from a file name
+///
+/// Invalid UTF-8 of a file name is replaced with `U+FFFD`, so a number is still guessed.
 impl From<&FileInput> for TrackInput {
     fn from(input: &FileInput) -> Self {
+        let file_name = input
+            .path
+            .file_stem()
+            .map(|x| FileName::parse(&x.to_string_lossy()))
+            .unwrap_or_default();
         Self {
-            track_number: input.track_number,
-            disc_number: input.disc_number,
-            title: input.title.clone(),
+            track_number: input.track_number.or(file_name.track_number),
+            disc_number: input.disc_number.or(file_name.disc_number),
+            disc_subtitle: input.disc_subtitle.clone(),
+            title: input.title.clone().or(file_name.title),
+            subtitle: input.subtitle.clone(),
+            composer: input.composer.clone(),
+            comment: input.comment.clone(),
+            lyrics: input.lyrics.clone(),
+            bpm: input.bpm,
+            rating: input.rating,
+            isrc: input.isrc.clone(),
         }
     }
 }
 
+/// Values of an edited track, e.g. to edit it again
+impl From<&TrackOutput> for TrackInput {
+    fn from(output: &TrackOutput) -> Self {
+        Self {
+            track_number: Some(output.track_number),
+            disc_number: Some(output.disc_number),
+            disc_subtitle: output.disc_subtitle.clone(),
+            title: Some(output.title.clone()),
+            subtitle: output.subtitle.clone(),
+            composer: output.composer.clone(),
+            comment: output.comment.clone(),
+            lyrics: output.lyrics.clone(),
+            bpm: output.bpm,
+            rating: output.rating,
+            isrc: output.isrc.clone(),
+        }
+    }
+}
+
+/// Maximum number of digits in a track number prefix, so a year starting a name is not a track number
+const MAX_NUMBER_DIGITS: usize = 3;
+
+const NAME_SEPARATORS: [char; 4] = [' ', '.', '-', '_'];
+
+/// Maximum number of digits of a disc number in a `D-NN` prefix
+const MAX_DISC_DIGITS: usize = 2;
+
+/// Separators which must follow `D-NN`, so a range in a title (`10-20 Years`) is not a disc and a track
+const DISC_TRACK_SEPARATORS: [&str; 3] = [" - ", ". ", "_"];
+
+/// Maximum number of stars of a rating
+pub const MAX_RATING: u8 = 5;
+
+/// Number of characters of an ISRC without hyphens
+const ISRC_LEN: usize = 12;
+
+/// Values parsed from a file name without extension
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct FileName {
+    pub disc_number: Option<u32>,
+    pub track_number: Option<u32>,
+    pub title: Option<String>,
+}
+
+impl FileName {
+    /// Handles `NN - Title`, `NN. Title`, `NN Title` and `D-NN - Title`
+    ///
+    /// Only a leading number followed by a separator is taken,
+    /// a name without such a prefix (e.g. `2Pac - Song`) is the title itself.
+    /// A number followed by a hyphen and another number (`10-20 Years`, `2-4-6-8 Motorway`) belongs to the title,
+    /// unless it is `D-NN` followed by ` - `, `. ` or `_`.
+    pub fn parse(stem: &str) -> Self {
+        let (disc_number, track_number, rest) = match split_disc_track(stem) {
+            Some((disc, track, rest)) => (Some(disc), Some(track), rest),
+            None => match split_number(stem) {
+                Some((number, rest)) if !starts_with_number(rest.strip_prefix('-').unwrap_or_default()) => {
+                    (None, Some(number), rest)
+                }
+                _ => (None, None, stem),
+            },
+        };
+        let title = rest.trim_start_matches(NAME_SEPARATORS).trim();
+        if title.is_empty() {
+            // a name is a number only, e.g. `1999`
+            return Self {
+                disc_number: None,
+                track_number: None,
+                title: Some(stem.trim().to_string()).filter(|x| !x.is_empty()),
+            };
+        }
+        Self {
+            disc_number,
+            track_number,
+            title: Some(title.to_string()),
+        }
+    }
+}
+
+/// Splits a leading `D-NN` followed by a separator off a name
+fn split_disc_track(value: &str) -> Option<(u32, u32, &str)> {
+    let rest = value.trim_start_matches(|x: char| x.is_ascii_digit());
+    let digits = value.len() - rest.len();
+    if digits == 0 || digits > MAX_DISC_DIGITS {
+        return None;
+    }
+    let (track, rest) = split_number(rest.strip_prefix('-')?)?;
+    if !DISC_TRACK_SEPARATORS.iter().any(|x| rest.starts_with(x)) {
+        return None;
+    }
+    Some((value[..digits].parse().ok()?, track, rest))
+}
+
+fn starts_with_number(value: &str) -> bool {
+    value.starts_with(|x: char| x.is_ascii_digit())
+}
+
+/// Splits a leading number followed by a separator off a name
+fn split_number(value: &str) -> Option<(u32, &str)> {
+    let rest = value.trim_start_matches(|x: char| x.is_ascii_digit());
+    let digits = value.len() - rest.len();
+    if digits == 0 || digits > MAX_NUMBER_DIGITS || !rest.starts_with(NAME_SEPARATORS) {
+        return None;
+    }
+    value[..digits].parse().ok().map(|number| (number, rest))
+}
+
+impl TrackInput {
+    /// Returns an output without editing, fails when a required value is missing
+    pub fn into_output(self) -> Result<TrackOutput, TrackOutputError> {
+        TrackOutputBuilder::new(self).build()
+    }
+
+    /// Defaults a track number to a 1-based position of the file in a sorted list
+    pub fn with_position(mut self, position: u32, numbering: Numbering) -> Self {
+        match numbering {
+            Numbering::Tags => {}
+            Numbering::Missing => {
+                self.track_number.get_or_insert(position);
+            }
+            Numbering::Position => self.track_number = Some(position),
+        }
+        self
+    }
+}
+
+/// Where default track numbers come from
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Numbering {
+    /// Numbers stored in tags
+    Tags,
+    /// Positions of files when tags have no number
+    Missing,
+    /// Positions of files, numbers in tags are ignored
+    Position,
+}
+
 #[derive(Clone, Copy, Debug)]
 enum TrackKey {
     TrackNumber,
     DiscNumber,
+    DiscSubtitle,
     Title,
+    Subtitle,
+    Composer,
+    Comment,
+    Bpm,
+    Rating,
+    Isrc,
+    Lyrics,
 }
 
 impl Prompt for TrackKey {
@@ -34,7 +207,15 @@ impl Prompt for TrackKey {
         match self {
             TrackNumber => "TRACK NUMBER",
             DiscNumber => "DISC NUMBER",
+            DiscSubtitle => "DISC SUBTITLE",
             Title => "TITLE",
+            Subtitle => "SUBTITLE",
+            Composer => "COMPOSER",
+            Comment => "COMMENT",
+            Bpm => "BPM",
+            Rating => "RATING (1-5)",
+            Isrc => "ISRC",
+            Lyrics => "LYRICS (\\n FOR NEW LINE)",
         }
     }
 }
@@ -61,7 +242,37 @@ impl OutputBuilder for TrackOutputBuilder {
         match key {
             TrackNumber => self.track_input.track_number = Some(value.parse().map_err(TrackInputError::TrackNumber)?),
             DiscNumber => self.track_input.disc_number = Some(value.parse().map_err(TrackInputError::DiscNumber)?),
+            DiscSubtitle => self.track_input.disc_subtitle = Some(value),
             Title => self.track_input.title = Some(value),
+            Subtitle => self.track_input.subtitle = Some(value),
+            Composer => self.track_input.composer = Some(value),
+            Comment => self.track_input.comment = Some(value),
+            // an empty value skips an optional number
+            Bpm if value.trim().is_empty() => self.track_input.bpm = None,
+            Bpm => self.track_input.bpm = Some(value.trim().parse().map_err(TrackInputError::Bpm)?),
+            Rating if value.trim().is_empty() => self.track_input.rating = None,
+            Rating => self.track_input.rating = Some(parse_rating(&value).ok_or(TrackInputError::Rating(value))?),
+            Isrc if value.trim().is_empty() => self.track_input.isrc = None,
+            Isrc => self.track_input.isrc = Some(parse_isrc(&value).ok_or(TrackInputError::Isrc(value))?),
+            Lyrics => self.track_input.lyrics = Some(unescape_lines(&value)),
+        }
+        Ok(())
+    }
+
+    fn unset_value(&mut self, key: Self::Key) -> Result<(), Self::OutputError> {
+        use self::TrackKey::*;
+        match key {
+            TrackNumber => return Err(TrackOutputError::TrackNumber),
+            DiscNumber => return Err(TrackOutputError::DiscNumber),
+            DiscSubtitle => self.track_input.disc_subtitle = None,
+            Title => return Err(TrackOutputError::Title),
+            Subtitle => self.track_input.subtitle = None,
+            Composer => self.track_input.composer = None,
+            Comment => self.track_input.comment = None,
+            Bpm => self.track_input.bpm = None,
+            Rating => self.track_input.rating = None,
+            Isrc => self.track_input.isrc = None,
+            Lyrics => self.track_input.lyrics = None,
         }
         Ok(())
     }
@@ -70,22 +281,90 @@ impl OutputBuilder for TrackOutputBuilder {
         Ok(TrackOutput {
             track_number: self.track_input.track_number.ok_or(TrackOutputError::TrackNumber)?,
             disc_number: self.track_input.disc_number.ok_or(TrackOutputError::DiscNumber)?,
+            disc_subtitle: self.track_input.disc_subtitle.filter(|x| !x.is_empty()),
             title: self.track_input.title.ok_or(TrackOutputError::Title)?,
+            subtitle: self.track_input.subtitle.filter(|x| !x.is_empty()),
+            composer: self.track_input.composer.filter(|x| !x.is_empty()),
+            comment: self.track_input.comment.filter(|x| !x.is_empty()),
+            lyrics: self.track_input.lyrics.filter(|x| !x.trim().is_empty()),
+            // zero is a missing value, so no frame is written
+            bpm: self.track_input.bpm.filter(|x| *x > 0),
+            rating: self.track_input.rating.filter(|x| *x > 0),
+            isrc: self.track_input.isrc.filter(|x| !x.trim().is_empty()),
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct TrackOutput {
     pub track_number: u32,
     pub disc_number: u32,
+    pub disc_subtitle: Option<String>,
     pub title: String,
+    pub subtitle: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+    pub lyrics: Option<String>,
+    pub bpm: Option<u32>,
+    pub rating: Option<u8>,
+    pub isrc: Option<String>,
+}
+
+/// Returns a number of stars from 0 to 5
+pub fn parse_rating(value: &str) -> Option<u8> {
+    value.trim().parse().ok().filter(|x| *x <= MAX_RATING)
+}
+
+/// Returns an ISRC without hyphens and in upper case, `us-rc1-76-07839` becomes `USRC17607839`
+///
+/// A code is a country (two letters), a registrant (three letters or digits),
+/// a year (two digits) and a designation (five digits).
+pub fn parse_isrc(value: &str) -> Option<String> {
+    let code = value
+        .chars()
+        .filter(|x| *x != '-' && !x.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase();
+    let is_valid = code.is_ascii()
+        && code.len() == ISRC_LEN
+        && code[..2].chars().all(|x| x.is_ascii_alphabetic())
+        && code[2..5].chars().all(|x| x.is_ascii_alphanumeric())
+        && code[5..].chars().all(|x| x.is_ascii_digit());
+    Some(code).filter(|_| is_valid)
+}
+
+/// Returns lyrics typed on a single line, e.g. `Line 1\nLine 2`, `\\` is a backslash
+fn escape_lines(value: &str) -> String {
+    value.replace('\\', "\\\\").replace("\r\n", "\n").replace('\n', "\\n")
+}
+
+/// Returns lyrics of a single line typed with `\n` for line breaks
+fn unescape_lines(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(x) = chars.next() {
+        match (x, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                result.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                result.push('\\');
+                chars.next();
+            }
+            (x, _) => result.push(x),
+        }
+    }
+    result
 }
 
 #[derive(Debug)]
-enum TrackInputError {
+pub enum TrackInputError {
     TrackNumber(ParseIntError),
     DiscNumber(ParseIntError),
+    Bpm(ParseIntError),
+    Rating(String),
+    Isrc(String),
 }
 
 impl StdError for TrackInputError {
@@ -94,6 +373,9 @@ impl StdError for TrackInputError {
         match self {
             TrackNumber(err) => Some(err),
             DiscNumber(err) => Some(err),
+            Bpm(err) => Some(err),
+            Rating(_) => None,
+            Isrc(_) => None,
         }
     }
 }
@@ -104,12 +386,19 @@ impl fmt::Display for TrackInputError {
         match self {
             TrackNumber(err) => write!(out, "invalid track number: {}", err),
             DiscNumber(err) => write!(out, "invalid disc number: {}", err),
+            Bpm(err) => write!(out, "invalid BPM: {}", err),
+            Rating(value) => write!(out, "invalid rating: {} (0 to {} stars)", value, MAX_RATING),
+            Isrc(value) => write!(
+                out,
+                "invalid ISRC: {} (expected 12 letters and digits, e.g. USRC17607839)",
+                value
+            ),
         }
     }
 }
 
 #[derive(Debug)]
-enum TrackOutputError {
+pub enum TrackOutputError {
     TrackNumber,
     DiscNumber,
     Title,
@@ -134,13 +423,26 @@ impl fmt::Display for TrackOutputError {
 
 struct TrackState {
     track_input: TrackInput,
+    /// Output of the previously edited track
+    previous: Option<TrackOutput>,
     kind: TrackStateKind,
+    /// A field active before an interrupt, editing resumes there when quitting is cancelled
+    resume_kind: TrackStateKind,
 }
 
+#[derive(Clone, Copy)]
 enum TrackStateKind {
     TrackNumber,
     DiscNumber,
+    DiscSubtitle,
     Title,
+    Subtitle,
+    Composer,
+    Comment,
+    Bpm,
+    Rating,
+    Isrc,
+    Lyrics,
     Interrupted,
     Finished,
 }
@@ -149,7 +451,9 @@ impl TrackState {
     fn new(track_input: TrackInput) -> Self {
         Self {
             track_input,
+            previous: None,
             kind: TrackStateKind::TrackNumber,
+            resume_kind: TrackStateKind::TrackNumber,
         }
     }
 }
@@ -166,10 +470,42 @@ impl State<TrackKey> for TrackState {
                 key: TrackKey::DiscNumber,
                 default_value: DefaultValue::from(&self.track_input.disc_number),
             },
+            DiscSubtitle => StateInput::Read {
+                key: TrackKey::DiscSubtitle,
+                default_value: DefaultValue::from(&self.track_input.disc_subtitle),
+            },
             Title => StateInput::Read {
                 key: TrackKey::Title,
                 default_value: DefaultValue::from(&self.track_input.title),
             },
+            Subtitle => StateInput::Read {
+                key: TrackKey::Subtitle,
+                default_value: DefaultValue::from(&self.track_input.subtitle),
+            },
+            Composer => StateInput::Read {
+                key: TrackKey::Composer,
+                default_value: DefaultValue::from(&self.track_input.composer),
+            },
+            Comment => StateInput::Read {
+                key: TrackKey::Comment,
+                default_value: DefaultValue::from(&self.track_input.comment),
+            },
+            Bpm => StateInput::Read {
+                key: TrackKey::Bpm,
+                default_value: DefaultValue::from(&self.track_input.bpm),
+            },
+            Rating => StateInput::Read {
+                key: TrackKey::Rating,
+                default_value: DefaultValue::from(&self.track_input.rating.map(u32::from)),
+            },
+            Isrc => StateInput::Read {
+                key: TrackKey::Isrc,
+                default_value: DefaultValue::from(&self.track_input.isrc),
+            },
+            Lyrics => StateInput::Read {
+                key: TrackKey::Lyrics,
+                default_value: DefaultValue::from(&self.track_input.lyrics.as_deref().map(escape_lines)),
+            },
             Interrupted => StateInput::Interrupted,
             Finished => StateInput::Finished,
         }
@@ -179,8 +515,16 @@ impl State<TrackKey> for TrackState {
         use self::TrackStateKind::*;
         self.kind = match self.kind {
             TrackNumber => DiscNumber,
-            DiscNumber => Title,
-            Title => Finished,
+            DiscNumber => DiscSubtitle,
+            DiscSubtitle => Title,
+            Title => Subtitle,
+            Subtitle => Composer,
+            Composer => Comment,
+            Comment => Bpm,
+            Bpm => Rating,
+            Rating => Isrc,
+            Isrc => Lyrics,
+            Lyrics => Finished,
             Interrupted => Interrupted,
             Finished => Finished,
         }
@@ -191,36 +535,190 @@ impl State<TrackKey> for TrackState {
         self.kind = match self.kind {
             TrackNumber => TrackNumber,
             DiscNumber => TrackNumber,
-            Title => DiscNumber,
-            Interrupted => TrackNumber,
-            Finished => Title,
+            DiscSubtitle => DiscNumber,
+            Title => DiscSubtitle,
+            Subtitle => Title,
+            Composer => Subtitle,
+            Comment => Composer,
+            Bpm => Comment,
+            Rating => Bpm,
+            Isrc => Rating,
+            Lyrics => Isrc,
+            Interrupted => self.resume_kind,
+            Finished => Lyrics,
         }
     }
 
     fn interrupt(&mut self) {
+        if let TrackStateKind::Interrupted = self.kind {
+            return;
+        }
+        self.resume_kind = self.kind;
         self.kind = TrackStateKind::Interrupted;
     }
 
-    fn finish(&mut self) {
-        self.kind = TrackStateKind::Finished;
+    fn resume(&mut self) {
+        if let TrackStateKind::Interrupted = self.kind {
+            self.kind = self.resume_kind;
+        }
+    }
+
+    fn keys(&self) -> Vec<TrackKey> {
+        use self::TrackKey::*;
+        vec![
+            TrackNumber,
+            DiscNumber,
+            DiscSubtitle,
+            Title,
+            Subtitle,
+            Composer,
+            Comment,
+            Bpm,
+            Rating,
+            Isrc,
+            Lyrics,
+        ]
+    }
+
+    fn goto(&mut self, key: &TrackKey) {
+        self.kind = match key {
+            TrackKey::TrackNumber => TrackStateKind::TrackNumber,
+            TrackKey::DiscNumber => TrackStateKind::DiscNumber,
+            TrackKey::DiscSubtitle => TrackStateKind::DiscSubtitle,
+            TrackKey::Title => TrackStateKind::Title,
+            TrackKey::Subtitle => TrackStateKind::Subtitle,
+            TrackKey::Composer => TrackStateKind::Composer,
+            TrackKey::Comment => TrackStateKind::Comment,
+            TrackKey::Bpm => TrackStateKind::Bpm,
+            TrackKey::Rating => TrackStateKind::Rating,
+            TrackKey::Isrc => TrackStateKind::Isrc,
+            TrackKey::Lyrics => TrackStateKind::Lyrics,
+        };
+    }
+
+    fn previous(&self, key: &TrackKey) -> Option<String> {
+        use self::TrackKey::*;
+        let previous = self.previous.as_ref()?;
+        match key {
+            TrackNumber => Some(previous.track_number.to_string()),
+            DiscNumber => Some(previous.disc_number.to_string()),
+            DiscSubtitle => previous.disc_subtitle.clone(),
+            Title => Some(previous.title.clone()),
+            Subtitle => previous.subtitle.clone(),
+            Composer => previous.composer.clone(),
+            Comment => previous.comment.clone(),
+            Bpm => previous.bpm.map(|x| x.to_string()),
+            Rating => previous.rating.map(|x| x.to_string()),
+            Isrc => previous.isrc.clone(),
+            Lyrics => previous.lyrics.as_deref().map(escape_lines),
+        }
     }
 }
 
-pub struct TrackEditor {
-    inner: Editor<TrackState, TrackOutputBuilder>,
+pub struct TrackEditor<R = Terminal> {
+    inner: Editor<TrackState, TrackOutputBuilder, R>,
 }
 
 impl TrackEditor {
     pub fn new(track_input: TrackInput) -> Self {
+        Self::with_reader(track_input, Terminal::default())
+    }
+}
+
+impl<R: Reader> TrackEditor<R> {
+    pub fn with_reader(track_input: TrackInput, reader: R) -> Self {
         Self {
-            inner: Editor::new(
+            inner: Editor::with_reader(
                 TrackState::new(track_input.clone()),
                 TrackOutputBuilder::new(track_input),
+                reader,
             ),
         }
     }
 
+    /// Shows a position of the track in prompts, `number` starts from 1
+    pub fn with_progress(mut self, number: usize, total: usize) -> Self {
+        self.inner = self.inner.with_label(format!("[{}/{}]", number, total));
+        self
+    }
+
+    /// Makes values of the previous track available with `:p`
+    pub fn with_previous(mut self, previous: Option<TrackOutput>) -> Self {
+        self.inner.state_mut().previous = previous;
+        self
+    }
+
     pub fn run(self) -> Result<EditorOutput<TrackOutput>, EditorError> {
         self.inner.run()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finder::Format;
+
+    fn name(disc_number: Option<u32>, track_number: Option<u32>, title: Option<&str>) -> FileName {
+        FileName {
+            disc_number,
+            track_number,
+            title: title.map(ToOwned::to_owned),
+        }
+    }
+
+    #[test]
+    fn parses_track_number() {
+        assert_eq!(FileName::parse("05 Title"), name(None, Some(5), Some("Title")));
+        assert_eq!(FileName::parse("05 - Title"), name(None, Some(5), Some("Title")));
+        assert_eq!(FileName::parse("05. Title"), name(None, Some(5), Some("Title")));
+        assert_eq!(FileName::parse("05_Title"), name(None, Some(5), Some("Title")));
+        assert_eq!(FileName::parse("01-Song"), name(None, Some(1), Some("Song")));
+    }
+
+    #[test]
+    fn parses_disc_and_track_numbers() {
+        assert_eq!(FileName::parse("1-05 - Title"), name(Some(1), Some(5), Some("Title")));
+        assert_eq!(FileName::parse("2-11. Title"), name(Some(2), Some(11), Some("Title")));
+        assert_eq!(FileName::parse("10-003_Title"), name(Some(10), Some(3), Some("Title")));
+    }
+
+    #[test]
+    fn keeps_numbers_of_title() {
+        assert_eq!(FileName::parse("10-20 Years"), name(None, None, Some("10-20 Years")));
+        assert_eq!(
+            FileName::parse("2-4-6-8 Motorway"),
+            name(None, None, Some("2-4-6-8 Motorway"))
+        );
+        assert_eq!(FileName::parse("2Pac - Song"), name(None, None, Some("2Pac - Song")));
+        assert_eq!(FileName::parse("1999 - Song"), name(None, None, Some("1999 - Song")));
+        assert_eq!(
+            FileName::parse("123-4567 Call Me"),
+            name(None, None, Some("123-4567 Call Me"))
+        );
+    }
+
+    #[test]
+    fn keeps_number_only_name_as_title() {
+        assert_eq!(FileName::parse("1999"), name(None, None, Some("1999")));
+        assert_eq!(FileName::parse("05"), name(None, None, Some("05")));
+        assert_eq!(FileName::parse(""), name(None, None, None));
+    }
+
+    #[test]
+    fn guesses_missing_values_from_file_name() {
+        let input = FileInput::empty("/music/1-05 - Title.mp3", Format::Mp3);
+        let track_input = TrackInput::from(&input);
+        assert_eq!(track_input.disc_number, Some(1));
+        assert_eq!(track_input.track_number, Some(5));
+        assert_eq!(track_input.title.as_deref(), Some("Title"));
+        let input = FileInput {
+            track_number: Some(7),
+            title: Some(String::from("Tagged")),
+            ..input
+        };
+        let track_input = TrackInput::from(&input);
+        assert_eq!(track_input.disc_number, Some(1));
+        assert_eq!(track_input.track_number, Some(7));
+        assert_eq!(track_input.title.as_deref(), Some("Tagged"));
+    }
+}
@@ -0,0 +1,210 @@
+//! Checks of edited values which are valid on their own but suspicious for an album
+
+use crate::file::FileOutput;
+use std::{collections::BTreeMap, fmt, path::PathBuf};
+
+/// Paths of files of a disc by their track numbers
+type DiscTracks = BTreeMap<u32, Vec<PathBuf>>;
+
+/// Maximum number of missing tracks listed in an issue, the rest are only counted
+const MAX_LISTED_TRACKS: usize = 20;
+
+/// Returns problems of an album, files are in the order of editing
+pub fn validate(items: &[FileOutput]) -> Vec<Issue> {
+    let mut result = Vec::new();
+    for item in items {
+        match item.total_tracks {
+            Some(total_tracks) if item.track_number > total_tracks => result.push(Issue::TrackExceedsTotal {
+                path: item.path.clone(),
+                track_number: item.track_number,
+                total_tracks,
+            }),
+            _ => {}
+        }
+        match item.total_discs {
+            Some(total_discs) if item.disc_number > total_discs => result.push(Issue::DiscExceedsTotal {
+                path: item.path.clone(),
+                disc_number: item.disc_number,
+                total_discs,
+            }),
+            _ => {}
+        }
+    }
+
+    // track numbers of every disc should go from 1 to a total without gaps and duplicates,
+    // gaps are unknown without a total
+    let mut discs: BTreeMap<u32, (Option<u32>, DiscTracks)> = BTreeMap::new();
+    for item in items {
+        let (_, tracks) = discs
+            .entry(item.disc_number)
+            .or_insert((item.total_tracks, BTreeMap::new()));
+        tracks.entry(item.track_number).or_default().push(item.path.clone());
+    }
+    for (disc_number, (total_tracks, tracks)) in discs {
+        for (&track_number, paths) in tracks.iter().filter(|(_, paths)| paths.len() > 1) {
+            result.push(Issue::DuplicateTrack {
+                disc_number,
+                track_number,
+                paths: paths.clone(),
+            });
+        }
+        let total_tracks = match total_tracks {
+            Some(total_tracks) if total_tracks > 0 => total_tracks,
+            _ => continue,
+        };
+        let missing = total_tracks - tracks.range(1..=total_tracks).count() as u32;
+        if missing > 0 {
+            // a total can be huge, so only the first missing numbers are collected
+            let track_numbers = (1..=total_tracks)
+                .filter(|x| !tracks.contains_key(x))
+                .take(MAX_LISTED_TRACKS)
+                .collect();
+            result.push(Issue::MissingTracks {
+                disc_number,
+                track_numbers,
+                missing,
+            });
+        }
+    }
+    result
+}
+
+#[derive(Debug)]
+pub enum Issue {
+    DiscExceedsTotal {
+        path: PathBuf,
+        disc_number: u32,
+        total_discs: u32,
+    },
+    DuplicateTrack {
+        disc_number: u32,
+        track_number: u32,
+        paths: Vec<PathBuf>,
+    },
+    MissingTracks {
+        disc_number: u32,
+        /// The first missing numbers, at most [`MAX_LISTED_TRACKS`]
+        track_numbers: Vec<u32>,
+        /// A number of all missing tracks
+        missing: u32,
+    },
+    TrackExceedsTotal {
+        path: PathBuf,
+        track_number: u32,
+        total_tracks: u32,
+    },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::Issue::*;
+        match self {
+            DiscExceedsTotal {
+                path,
+                disc_number,
+                total_discs,
+            } => write!(
+                out,
+                "{}: disc number {} exceeds total discs {}",
+                path.display(),
+                disc_number,
+                total_discs
+            ),
+            DuplicateTrack {
+                disc_number,
+                track_number,
+                paths,
+            } => {
+                let paths: Vec<String> = paths.iter().map(|x| x.display().to_string()).collect();
+                write!(
+                    out,
+                    "track {} of disc {} is used by several files: {}",
+                    track_number,
+                    disc_number,
+                    paths.join(", ")
+                )
+            }
+            MissingTracks {
+                disc_number,
+                track_numbers,
+                missing,
+            } => {
+                let track_numbers: Vec<String> = track_numbers.iter().map(ToString::to_string).collect();
+                write!(
+                    out,
+                    "tracks missing on disc {}: {}",
+                    disc_number,
+                    track_numbers.join(", ")
+                )?;
+                match (*missing as usize).checked_sub(track_numbers.len()) {
+                    Some(rest) if rest > 0 => write!(out, " and {} more", rest),
+                    _ => Ok(()),
+                }
+            }
+            TrackExceedsTotal {
+                path,
+                track_number,
+                total_tracks,
+            } => write!(
+                out,
+                "{}: track number {} exceeds total tracks {}",
+                path.display(),
+                track_number,
+                total_tracks
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{date::Date, finder::Format};
+
+    fn output(track_number: u32, total_tracks: Option<u32>) -> FileOutput {
+        FileOutput {
+            path: PathBuf::from(format!("{:02}.mp3", track_number)),
+            format: Format::Mp3,
+            artist: String::from("Artist"),
+            album_artist: String::from("Artist"),
+            album: String::from("Album"),
+            date: Date::from_year(2000),
+            genre: None,
+            grouping: None,
+            title: String::from("Title"),
+            subtitle: None,
+            composer: None,
+            comment: None,
+            lyrics: None,
+            bpm: None,
+            rating: None,
+            isrc: None,
+            track_number,
+            total_tracks,
+            disc_number: 1,
+            total_discs: None,
+            disc_subtitle: None,
+            compilation: false,
+            keep_pictures: true,
+            cover: None,
+            number_width: None,
+        }
+    }
+
+    #[test]
+    fn lists_missing_tracks() {
+        let issues = validate(&[output(1, Some(4)), output(3, Some(4))]);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].to_string(), "tracks missing on disc 1: 2, 4");
+        assert!(validate(&[output(1, None), output(3, None)]).is_empty());
+    }
+
+    #[test]
+    fn limits_listed_missing_tracks() {
+        let issues = validate(&[output(2, Some(u32::MAX))]);
+        assert_eq!(issues.len(), 1);
+        let message = issues[0].to_string();
+        assert!(message.starts_with("tracks missing on disc 1: 1, 3, 4,"));
+        assert!(message.ends_with(" 21 and 4294967274 more"));
+    }
+}
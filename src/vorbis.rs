@@ -0,0 +1,428 @@
+use crate::{
+    cover::Cover,
+    date::Date,
+    file::{parse_bpm, split_values, FileInput, FileOutput, WriteOptions, VALUE_SEPARATOR},
+    finder::Format,
+    track::MAX_RATING,
+};
+use std::{error::Error as StdError, fmt, path::Path, string::FromUtf8Error};
+
+const VENDOR: &str = "tagg";
+
+/// `RATING` is a percentage, a star is worth 20
+const RATING_SCALE: u8 = 100;
+const RATING_STEP: u8 = RATING_SCALE / MAX_RATING;
+
+/// Keys of comments holding embedded pictures
+const PICTURE_KEYS: [&str; 2] = ["METADATA_BLOCK_PICTURE", "COVERART"];
+
+/// Keys of comments written from an editor output, including alternative keys which are read
+const MANAGED_KEYS: [&str; 23] = [
+    "ALBUM",
+    "ALBUMARTIST",
+    "ARTIST",
+    "BPM",
+    "COMMENT",
+    "COMPILATION",
+    "COMPOSER",
+    "DATE",
+    "DISCNUMBER",
+    "DISCSUBTITLE",
+    "DISCTOTAL",
+    "GENRE",
+    "GROUPING",
+    "ISRC",
+    "LYRICS",
+    "RATING",
+    "SUBTITLE",
+    "TITLE",
+    "TOTALDISCS",
+    "TOTALTRACKS",
+    "TRACKNUMBER",
+    "TRACKTOTAL",
+    "UNSYNCEDLYRICS",
+];
+
+/// Vorbis comments shared by FLAC and Ogg containers
+#[derive(Debug)]
+pub struct VorbisComments {
+    vendor: String,
+    comments: Vec<(String, String)>,
+}
+
+impl Default for VorbisComments {
+    fn default() -> Self {
+        Self {
+            vendor: String::from(VENDOR),
+            comments: Vec::new(),
+        }
+    }
+}
+
+impl VorbisComments {
+    /// Parses a comment header, trailing data (e.g. a framing bit) is ignored
+    pub fn parse(data: &[u8]) -> Result<Self, VorbisError> {
+        let mut reader = data;
+        let vendor = read_string(&mut reader)?;
+        let count = read_u32_le(&mut reader)?;
+        let mut comments = Vec::new();
+        for _ in 0..count {
+            let comment = read_string(&mut reader)?;
+            let mut parts = comment.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => comments.push((key.to_owned(), value.to_owned())),
+                _ => return Err(VorbisError::InvalidComment(comment)),
+            }
+        }
+        Ok(Self { vendor, comments })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_string(&mut data, &self.vendor);
+        data.extend_from_slice(&(self.comments.len() as u32).to_le_bytes());
+        for (key, value) in &self.comments {
+            write_string(&mut data, &format!("{}={}", key, value));
+        }
+        data
+    }
+
+    /// Returns the first value for a key (keys are case-insensitive)
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.comments
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns all values for a key joined with [`VALUE_SEPARATOR`]
+    pub fn get_all(&self, key: &str) -> Option<String> {
+        let values: Vec<&str> = self
+            .comments
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+            .collect();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(&VALUE_SEPARATOR.to_string()))
+        }
+    }
+
+    /// Replaces values for a key, an empty value just removes them
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        let value = value.into();
+        self.set_all(key, &[value.as_str()]);
+    }
+
+    /// Replaces values for a key with a comment per value, empty values are skipped
+    pub fn set_all(&mut self, key: &str, values: &[&str]) {
+        self.remove(key);
+        for value in values.iter().filter(|x| !x.is_empty()) {
+            self.comments.push((key.to_uppercase(), (*value).to_owned()));
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.comments.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+    }
+
+    /// Adds a picture as a `METADATA_BLOCK_PICTURE` comment
+    pub fn add_picture(&mut self, cover: &Cover) {
+        self.comments
+            .push((String::from(PICTURE_KEYS[0]), encode_base64(&cover.to_picture_block())));
+    }
+
+    pub fn to_file_input(&self, path: &Path, format: Format) -> FileInput {
+        let (track_number, total_tracks) = parse_number_pair(self.get("TRACKNUMBER"));
+        let (disc_number, total_discs) = parse_number_pair(self.get("DISCNUMBER"));
+        FileInput {
+            path: path.to_owned(),
+            format,
+            artist: self.get_all("ARTIST"),
+            album_artist: self.get_all("ALBUMARTIST"),
+            album: self.get("ALBUM").map(ToOwned::to_owned),
+            date: self.get("DATE").and_then(Date::from_tag),
+            genre: self.get("GENRE").map(ToOwned::to_owned),
+            grouping: self.get("GROUPING").map(ToOwned::to_owned),
+            title: self.get("TITLE").map(ToOwned::to_owned),
+            subtitle: self.get("SUBTITLE").map(ToOwned::to_owned),
+            composer: self.get("COMPOSER").map(ToOwned::to_owned),
+            comment: self.get("COMMENT").map(ToOwned::to_owned),
+            lyrics: self
+                .get("LYRICS")
+                .or_else(|| self.get("UNSYNCEDLYRICS"))
+                .map(ToOwned::to_owned),
+            bpm: self.get("BPM").and_then(parse_bpm),
+            rating: self
+                .get("RATING")
+                .and_then(|x| x.trim().parse::<u8>().ok())
+                .map(|x| (x.min(RATING_SCALE) + RATING_STEP / 2) / RATING_STEP)
+                .filter(|x| *x > 0),
+            isrc: self.get("ISRC").map(ToOwned::to_owned),
+            track_number,
+            total_tracks: self
+                .get("TOTALTRACKS")
+                .or_else(|| self.get("TRACKTOTAL"))
+                .and_then(|x| x.trim().parse().ok())
+                .or(total_tracks),
+            disc_number,
+            total_discs: self
+                .get("TOTALDISCS")
+                .or_else(|| self.get("DISCTOTAL"))
+                .and_then(|x| x.trim().parse().ok())
+                .or(total_discs),
+            disc_subtitle: self.get("DISCSUBTITLE").map(ToOwned::to_owned),
+            compilation: self.get("COMPILATION").map(|x| x.trim() == "1"),
+            duration: None,
+        }
+    }
+
+    /// Replaces managed comments with values of the output
+    ///
+    /// Other comments (e.g. `REPLAYGAIN_*`, `MUSICBRAINZ_*`) and the vendor string are kept.
+    /// Pictures are kept as well, unless the output asks for a clean slate or brings a cover.
+    /// Multiple artists are written as separate comments.
+    pub fn set_file_output(&mut self, output: &FileOutput, options: &WriteOptions) {
+        let keeps_pictures = output.keeps_pictures();
+        self.comments.retain(|(k, _)| {
+            let is_key = |x: &&str| k.eq_ignore_ascii_case(x);
+            !MANAGED_KEYS.iter().any(is_key) && (keeps_pictures || !PICTURE_KEYS.iter().any(is_key))
+        });
+        self.set_all("ARTIST", &split_values(&output.artist, &options.value_separator));
+        self.set_all(
+            "ALBUMARTIST",
+            &split_values(&output.album_artist, &options.value_separator),
+        );
+        self.set("ALBUM", output.album.as_str());
+        if output.has_date() {
+            self.set("DATE", output.date.to_string());
+        }
+        if let Some(ref genre) = output.genre {
+            self.set("GENRE", genre.as_str());
+        }
+        if let Some(ref grouping) = output.grouping {
+            self.set("GROUPING", grouping.as_str());
+        }
+        self.set("TITLE", output.title.as_str());
+        if let Some(ref subtitle) = output.subtitle {
+            self.set("SUBTITLE", subtitle.as_str());
+        }
+        if let Some(ref composer) = output.composer {
+            self.set("COMPOSER", composer.as_str());
+        }
+        if let Some(ref comment) = output.comment {
+            self.set("COMMENT", comment.as_str());
+        }
+        if let Some(ref lyrics) = output.lyrics {
+            self.set("LYRICS", lyrics.as_str());
+        }
+        if let Some(bpm) = output.bpm {
+            self.set("BPM", bpm.to_string());
+        }
+        if let Some(rating) = output.rating {
+            self.set("RATING", (rating.min(MAX_RATING) * RATING_STEP).to_string());
+        }
+        if let Some(ref isrc) = output.isrc {
+            self.set("ISRC", isrc.as_str());
+        }
+        // zero is a missing number
+        let (track_number, total_tracks) = output.padded_track_number();
+        if output.track_number > 0 {
+            self.set("TRACKNUMBER", track_number);
+        }
+        if let Some(total_tracks) = total_tracks {
+            self.set("TOTALTRACKS", total_tracks);
+        }
+        let (disc_number, total_discs) = output.padded_disc_number();
+        if output.disc_number > 0 {
+            self.set("DISCNUMBER", disc_number);
+        }
+        if let Some(total_discs) = total_discs {
+            self.set("TOTALDISCS", total_discs);
+        }
+        if let Some(ref disc_subtitle) = output.disc_subtitle {
+            self.set("DISCSUBTITLE", disc_subtitle.as_str());
+        }
+        if output.compilation {
+            self.set("COMPILATION", "1");
+        }
+    }
+}
+
+/// Parses values like `3` or `3/12`
+fn parse_number_pair(value: Option<&str>) -> (Option<u32>, Option<u32>) {
+    let value = match value {
+        Some(value) => value,
+        None => return (None, None),
+    };
+    let mut parts = value.splitn(2, '/');
+    let number = parts.next().and_then(|x| x.trim().parse().ok());
+    let total = parts.next().and_then(|x| x.trim().parse().ok());
+    (number, total)
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let value = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                let sextet = (value >> (18 - idx * 6)) & 0x3f;
+                result.push(char::from(ALPHABET[sextet as usize]));
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+fn read_u32_le(reader: &mut &[u8]) -> Result<u32, VorbisError> {
+    if reader.len() < 4 {
+        return Err(VorbisError::UnexpectedEof);
+    }
+    let (value, rest) = reader.split_at(4);
+    *reader = rest;
+    Ok(u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+}
+
+fn read_string(reader: &mut &[u8]) -> Result<String, VorbisError> {
+    let len = read_u32_le(reader)? as usize;
+    if len > reader.len() {
+        return Err(VorbisError::UnexpectedEof);
+    }
+    let (value, rest) = reader.split_at(len);
+    *reader = rest;
+    String::from_utf8(value.to_vec()).map_err(VorbisError::Utf8)
+}
+
+fn write_string(data: &mut Vec<u8>, value: &str) {
+    data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    data.extend_from_slice(value.as_bytes());
+}
+
+#[derive(Debug)]
+pub enum VorbisError {
+    InvalidComment(String),
+    UnexpectedEof,
+    Utf8(FromUtf8Error),
+}
+
+impl StdError for VorbisError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use self::VorbisError::*;
+        match self {
+            InvalidComment(_) => None,
+            UnexpectedEof => None,
+            Utf8(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for VorbisError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::VorbisError::*;
+        match self {
+            InvalidComment(comment) => write!(out, "invalid vorbis comment: {}", comment),
+            UnexpectedEof => write!(out, "unexpected end of vorbis comments"),
+            Utf8(err) => write!(out, "invalid UTF-8 in vorbis comment: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_base64(data: &str) -> Vec<u8> {
+        const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let sextets: Vec<u32> = data
+            .bytes()
+            .take_while(|x| *x != b'=')
+            .map(|x| ALPHABET.iter().position(|y| *y == x).unwrap() as u32)
+            .collect();
+        let mut result = Vec::new();
+        for chunk in sextets.chunks(4) {
+            let value = chunk
+                .iter()
+                .chain([0, 0, 0].iter())
+                .take(4)
+                .fold(0, |acc, x| (acc << 6) | x);
+            result.extend_from_slice(&value.to_be_bytes()[1..chunk.len()]);
+        }
+        result
+    }
+
+    #[test]
+    fn round_trips_comments() {
+        let mut comments = VorbisComments::default();
+        comments.set_all("artist", &["First", "Second", ""]);
+        comments.set("TITLE", "Title = with ünïcode");
+        comments.set("REPLAYGAIN_TRACK_GAIN", "-6.5 dB");
+        comments.set("EMPTY", "");
+
+        let data = comments.serialize();
+        let mut with_framing_bit = data.clone();
+        with_framing_bit.push(1);
+        let parsed = VorbisComments::parse(&with_framing_bit).unwrap();
+        assert_eq!(parsed.vendor, VENDOR);
+        assert_eq!(
+            parsed.comments,
+            vec![
+                (String::from("ARTIST"), String::from("First")),
+                (String::from("ARTIST"), String::from("Second")),
+                (String::from("TITLE"), String::from("Title = with ünïcode")),
+                (String::from("REPLAYGAIN_TRACK_GAIN"), String::from("-6.5 dB")),
+            ]
+        );
+        assert_eq!(parsed.serialize(), data);
+        assert_eq!(parsed.get("Title"), Some("Title = with ünïcode"));
+        assert_eq!(
+            parsed.get_all("artist"),
+            Some(format!("First{}Second", VALUE_SEPARATOR))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_comments() {
+        let mut data = Vec::new();
+        write_string(&mut data, VENDOR);
+        data.extend_from_slice(&1u32.to_le_bytes());
+        write_string(&mut data, "NO_SEPARATOR");
+        assert!(matches!(
+            VorbisComments::parse(&data),
+            Err(VorbisError::InvalidComment(ref x)) if x == "NO_SEPARATOR"
+        ));
+
+        data.truncate(data.len() - 2);
+        assert!(matches!(VorbisComments::parse(&data), Err(VorbisError::UnexpectedEof)));
+    }
+
+    #[test]
+    fn encodes_base64() {
+        // test vectors of RFC 4648
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (data, encoded) in vectors.iter() {
+            assert_eq!(encode_base64(data.as_bytes()), *encoded);
+        }
+
+        let data: Vec<u8> = (0..=255).chain((0..=255).rev()).collect();
+        for len in 0..data.len() {
+            let encoded = encode_base64(&data[..len]);
+            assert_eq!(encoded.len() % 4, 0);
+            assert_eq!(decode_base64(&encoded), &data[..len]);
+        }
+    }
+}